@@ -23,21 +23,25 @@ layout (location = 2) in vec3 vNormal;
 out vec4 FragColor;
 
 layout (location = 5) uniform sampler2D uTexture;
+layout (location = 6) uniform float uAlpha;
+// Normalized direction in which the sun shines.
+layout (location = 7) uniform vec3 uLightDirection;
+layout (location = 8) uniform vec3 uLightColor;
+layout (location = 9) uniform float uAmbient;
+// Color multiplied into the texture, white keeps it.
+layout (location = 10) uniform vec3 uTint;
 
 void main() {
     vec3 normal = normalize(vNormal);
-    vec3 lightColor = vec3(1., 1., 1.);
-    vec3 lightDir = normalize(vec3(1., 2., 3.));
 
     // Ambient lighting
-    float ambientStrength = 0.2;
-    vec3 ambient = ambientStrength * lightColor;
+    vec3 ambient = uAmbient * uLightColor;
 
     // Diffuse lighting
-    float diff = max(dot(vNormal, lightDir), 0.0);
-    vec3 diffuse = diff * lightColor;
+    float diff = max(dot(normal, -uLightDirection), 0.0);
+    vec3 diffuse = diff * uLightColor;
 
     // Specular lighting will come soon, maybe :D
     // I like the comic style
-    FragColor = vec4((ambient + diffuse) * texture(uTexture, vUV).rgb, 1.0);
+    FragColor = vec4((ambient + diffuse) * texture(uTexture, vUV).rgb * uTint, uAlpha);
 }
\ No newline at end of file
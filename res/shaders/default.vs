@@ -27,11 +27,13 @@ layout (location = 2) out vec3 vNormal;
 layout (location = 0) uniform mat4 uModel;
 layout (location = 1) uniform mat4 uView;
 layout (location = 2) uniform mat4 uProjection;
+// Inverse transpose of the upper 3x3 of uModel.
+layout (location = 3) uniform mat3 uNormalMatrix;
 
 void main() {
     mat4 modelViewProj = uProjection * uView * uModel;
     vUV = aUV;
     vFragPos = vec3(uModel * vec4(aPosition, 1.0));
-    vNormal = normalize(vec3(transpose(inverse(uModel * uView)) * vec4(aNormal, 0.)));
+    vNormal = normalize(uNormalMatrix * aNormal);
     gl_Position = modelViewProj * vec4(aPosition, 1.);
 }
\ No newline at end of file
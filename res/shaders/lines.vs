@@ -0,0 +1,30 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+#version 330 core
+#extension GL_ARB_explicit_uniform_location : enable
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec3 aPosition;
+layout (location = 1) in vec3 aColor;
+
+layout (location = 0) out vec3 vColor;
+
+layout (location = 1) uniform mat4 uView;
+layout (location = 2) uniform mat4 uProjection;
+
+void main() {
+    vColor = aColor;
+    gl_Position = uProjection * uView * vec4(aPosition, 1.);
+}
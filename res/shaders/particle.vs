@@ -0,0 +1,40 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+#version 330 core
+#extension GL_ARB_explicit_uniform_location : enable
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec3 aPosition;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aUV;
+
+layout (location = 0) out vec2 vUV;
+
+// Translation to the particle center and uniform scale to its size.
+layout (location = 0) uniform mat4 uModel;
+layout (location = 1) uniform mat4 uView;
+layout (location = 2) uniform mat4 uProjection;
+
+void main() {
+    // Billboard: span the quad with the right and up vector of the camera, so it always faces the viewer.
+    vec3 right = vec3(uView[0][0], uView[1][0], uView[2][0]);
+    vec3 up = vec3(uView[0][1], uView[1][1], uView[2][1]);
+    vec3 center = vec3(uModel[3]);
+    float size = uModel[0][0];
+
+    vUV = aUV;
+    vec3 position = center + (right * aPosition.x + up * aPosition.y) * size;
+    gl_Position = uProjection * uView * vec4(position, 1.);
+}
@@ -0,0 +1,32 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+#version 330 core
+#extension GL_ARB_explicit_uniform_location : enable
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec2 vUV;
+
+out vec4 FragColor;
+
+layout (location = 6) uniform float uAlpha;
+
+void main() {
+    // Dark ellipse with a soft edge.
+    float dist = length(vUV - vec2(0.5)) * 2.;
+    if (dist > 1.) {
+        discard;
+    }
+    FragColor = vec4(0., 0., 0., uAlpha * (1. - smoothstep(0.5, 1., dist)));
+}
@@ -0,0 +1,402 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use crate::game::car::Car;
+use crate::game::controller::Controller;
+use crate::game::simulation::CollisionEvent;
+use log::{info, warn};
+use nalgebra::clamp;
+use rodio::{Device, Sink, Source};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Sample rate [Hz] of the synthesized engine sound.
+const SAMPLE_RATE: u32 = 44_100;
+/// Firing frequency [Hz] of an idling engine.
+const IDLE_FREQUENCY: f32 = 40.;
+/// Impulses [N s] below this are scrapes too soft to be heard.
+const SILENT_IMPULSE: f32 = 300.;
+/// Impulse [N s] of a crash at full volume, about a head-on crash of two cars at 25 km/h each.
+const FULL_IMPULSE: f32 = 10_000.;
+/// Time [s] a pair of cars stays quiet after a crash sound, so sustained contact doesn't repeat it.
+const IMPACT_COOLDOWN: f32 = 0.3;
+/// Duration [s] of a crash sound.
+const CRASH_DURATION: f32 = 0.35;
+/// Volume of the engine sound of a car at full master volume.
+const ENGINE_VOLUME: f32 = 0.2;
+
+/// Return the pitch multiplier of the engine sound for a car driving at `speed` [m/s].
+///
+/// The engine revs up with the speed and a bit more while the throttle is pushed.
+pub fn engine_pitch(speed: f32, max_speed: f32, throttle: f32) -> f32 {
+    let revs = if max_speed > 0. { (speed.abs() / max_speed).min(1.) } else { 0. };
+    1. + 2.5 * revs + 0.3 * throttle.max(0.)
+}
+
+/// Return the volume [0.0; 1.0] of the crash sound for a collision with `impulse` [N s].
+pub fn impact_volume(impulse: f32) -> f32 {
+    clamp((impulse - SILENT_IMPULSE) / (FULL_IMPULSE - SILENT_IMPULSE), 0., 1.)
+}
+
+/// Return the volume to play a sound of `volume` with at a `master_volume` [0.0; 1.0] and mute state.
+fn sink_volume(volume: f32, master_volume: f32, is_muted: bool) -> f32 {
+    if is_muted {
+        0.
+    } else {
+        volume * clamp(master_volume, 0., 1.)
+    }
+}
+
+/// Lets each pair of cars make a crash sound once per `IMPACT_COOLDOWN`.
+#[derive(Default)]
+struct ImpactDebounce {
+    cooldowns: HashMap<(usize, usize), f32>,
+}
+
+impl ImpactDebounce {
+    /// Let the cooldowns pass by `dt` [s].
+    fn update(&mut self, dt: f32) {
+        for cooldown in self.cooldowns.values_mut() {
+            *cooldown -= dt;
+        }
+        self.cooldowns.retain(|_, cooldown| *cooldown > 0.);
+    }
+
+    /// Return true if the cars of the `impact` may make a sound and start their cooldown.
+    fn is_audible(&mut self, impact: &CollisionEvent) -> bool {
+        let cars = (impact.a, impact.b);
+        if impact_volume(impact.impulse) <= 0. || self.cooldowns.contains_key(&cars) {
+            return false;
+        }
+        self.cooldowns.insert(cars, IMPACT_COOLDOWN);
+        true
+    }
+}
+
+/// A pitch shared between the game and the audio thread.
+#[derive(Clone)]
+struct SharedPitch(Arc<AtomicU32>);
+
+impl SharedPitch {
+    fn new(pitch: f32) -> SharedPitch {
+        SharedPitch(Arc::new(AtomicU32::new(pitch.to_bits())))
+    }
+
+    fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, pitch: f32) {
+        self.0.store(pitch.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Endless engine sound, a few harmonics of the firing frequency scaled by the pitch.
+struct EngineSound {
+    pitch: SharedPitch,
+    phase: f32,
+}
+
+impl Iterator for EngineSound {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.phase = (self.phase + IDLE_FREQUENCY * self.pitch.get() / SAMPLE_RATE as f32) % 1.;
+        let angle = 2. * PI * self.phase;
+        Some(0.5 * angle.sin() + 0.3 * (2. * angle).sin() + 0.2 * (3. * angle).sin())
+    }
+}
+
+impl Source for EngineSound {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Short burst of fading noise.
+struct CrashSound {
+    samples: u32,
+    sample: u32,
+    volume: f32,
+    seed: u32,
+}
+
+impl CrashSound {
+    fn new(volume: f32) -> CrashSound {
+        CrashSound {
+            samples: (CRASH_DURATION * SAMPLE_RATE as f32) as u32,
+            sample: 0,
+            volume,
+            seed: 0x2545_f491,
+        }
+    }
+}
+
+impl Iterator for CrashSound {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample >= self.samples {
+            return None;
+        }
+        self.sample += 1;
+        // Xorshift noise, fading out quadratically.
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        let noise = self.seed as f32 / u32::MAX as f32 * 2. - 1.;
+        let fade = 1. - self.sample as f32 / self.samples as f32;
+        Some(noise * self.volume * fade * fade)
+    }
+}
+
+impl Source for CrashSound {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some((self.samples - self.sample) as usize)
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_millis((CRASH_DURATION * 1000.) as u64))
+    }
+}
+
+/// Plays the engine sound of every car and the crashes between them.
+///
+/// Without an audio device the game stays silent.
+pub struct AudioEngine {
+    device: Option<Device>,
+    engines: Vec<(SharedPitch, Sink)>,
+    impacts: ImpactDebounce,
+    master_volume: f32,
+    is_muted: bool,
+}
+
+impl AudioEngine {
+    /// Start the engine sound for `cars` cars on the default output device.
+    pub fn new(cars: usize) -> AudioEngine {
+        let device = match rodio::default_output_device() {
+            Some(device) => device,
+            None => {
+                warn!("No audio device found, the game is silent");
+                return AudioEngine {
+                    device: None,
+                    engines: Vec::new(),
+                    impacts: Default::default(),
+                    master_volume: 1.,
+                    is_muted: false,
+                };
+            }
+        };
+        info!("Playing audio on {}", device.name());
+
+        let mut engines = Vec::with_capacity(cars);
+        for _ in 0..cars {
+            let pitch = SharedPitch::new(1.);
+            let mut sink = Sink::new(&device);
+            sink.set_volume(ENGINE_VOLUME);
+            sink.append(EngineSound {
+                pitch: pitch.clone(),
+                phase: 0.,
+            });
+            engines.push((pitch, sink));
+        }
+        AudioEngine {
+            device: Some(device),
+            engines,
+            impacts: Default::default(),
+            master_volume: 1.,
+            is_muted: false,
+        }
+    }
+
+    /// Return the master volume between 0.0 and 1.0.
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    /// Set the volume of all sounds, clamped to [0.0; 1.0].
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = clamp(volume, 0., 1.);
+        self.apply_volume();
+    }
+
+    /// Return true if the game is muted.
+    pub fn is_muted(&self) -> bool {
+        self.is_muted
+    }
+
+    /// Silence all sounds right away or bring them back. The engines keep running while muted.
+    pub fn set_muted(&mut self, is_muted: bool) {
+        self.is_muted = is_muted;
+        self.apply_volume();
+    }
+
+    fn apply_volume(&mut self) {
+        let volume = sink_volume(ENGINE_VOLUME, self.master_volume, self.is_muted);
+        for (_, sink) in &mut self.engines {
+            sink.set_volume(volume);
+        }
+    }
+
+    /// Update the engine sounds from the state of the cars and their controllers after a frame
+    /// of `dt` seconds.
+    pub fn update(&mut self, dt: f32, cars: &[Car], controller: &[Controller]) {
+        self.impacts.update(dt);
+        for (id, (car, (pitch, _))) in cars.iter().zip(self.engines.iter()).enumerate() {
+            let throttle = controller.get(id).map_or(0., |c| c.get_y_axis());
+            pitch.set(engine_pitch(car.speed(), car.max_speed, throttle));
+        }
+    }
+
+    /// Pause or resume the engine sounds.
+    pub fn set_paused(&mut self, is_paused: bool) {
+        for (_, sink) in &self.engines {
+            if is_paused {
+                sink.pause();
+            } else {
+                sink.play();
+            }
+        }
+    }
+
+    /// Play a crash sound for a collision, louder for harder hits.
+    pub fn play_impact(&mut self, impact: &CollisionEvent) {
+        if !self.impacts.is_audible(impact) {
+            return;
+        }
+        let volume = sink_volume(impact_volume(impact.impulse), self.master_volume, self.is_muted);
+        if let (Some(device), true) = (&self.device, volume > 0.) {
+            rodio::play_raw(device, CrashSound::new(volume));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{engine_pitch, impact_volume, sink_volume, CrashSound, EngineSound, ImpactDebounce, SharedPitch, IMPACT_COOLDOWN};
+    use crate::game::car::{resolve_collision, Car};
+    use crate::game::simulation::CollisionEvent;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn pitch_rises_with_speed() {
+        let mut last = engine_pitch(0., 40., 0.);
+        assert_eq!(last, 1.);
+        for i in 1..=50 {
+            let pitch = engine_pitch(i as f32, 40., 0.);
+            assert!(pitch >= last);
+            if i <= 40 {
+                assert!(pitch > last);
+            }
+            last = pitch;
+        }
+        assert!(engine_pitch(20., 40., 1.) > engine_pitch(20., 40., 0.));
+        assert_eq!(engine_pitch(-20., 40., 0.), engine_pitch(20., 40., 0.));
+    }
+
+    #[test]
+    fn engine_sound_follows_pitch() {
+        let pitch = SharedPitch::new(1.);
+        let mut sound = EngineSound {
+            pitch: pitch.clone(),
+            phase: 0.,
+        };
+        sound.next();
+        let slow = sound.phase;
+        pitch.set(2.);
+        sound.next();
+        assert!((sound.phase - 3. * slow).abs() < 1e-6);
+        assert!(sound.take(1000).all(|s| s.abs() <= 1.));
+    }
+
+    /// Return the crash volume of two cars touching with a relative speed of `speed` [m/s].
+    fn crash_volume(speed: f32) -> f32 {
+        let mut a = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let mut b = Car::test_car(Vector3::new(0., 2., 0.), 1000.0);
+        a.set_velocity(Vector3::new(0., speed, 0.));
+        impact_volume(resolve_collision(&mut a, &mut b).unwrap().impulse)
+    }
+
+    #[test]
+    fn volume_scales_with_impact_speed() {
+        assert_eq!(crash_volume(0.1), 0.);
+        let mut last = 0.;
+        for speed in &[1., 3., 5., 10.] {
+            let volume = crash_volume(*speed);
+            assert!(volume > last);
+            last = volume;
+        }
+        assert_eq!(crash_volume(50.), 1.);
+    }
+
+    #[test]
+    fn master_volume_and_mute() {
+        assert_eq!(sink_volume(0.2, 1., false), 0.2);
+        assert_eq!(sink_volume(0.2, 0.5, false), 0.1);
+        assert_eq!(sink_volume(0.2, 0.5, true), 0.);
+        // The master volume is clamped.
+        assert_eq!(sink_volume(0.2, 3., false), 0.2);
+        assert_eq!(sink_volume(0.2, -1., false), 0.);
+    }
+
+    #[test]
+    fn debounce_sustained_contact() {
+        let mut debounce = ImpactDebounce::default();
+        let hit = CollisionEvent {
+            a: 0,
+            b: 1,
+            impulse: 5000.,
+            point: Vector3::new(0., 0., 0.),
+        };
+        assert!(debounce.is_audible(&hit));
+        debounce.update(IMPACT_COOLDOWN * 0.5);
+        assert!(!debounce.is_audible(&hit));
+        // Other cars aren't affected.
+        assert!(debounce.is_audible(&CollisionEvent { b: 2, ..hit }));
+        debounce.update(IMPACT_COOLDOWN * 0.6);
+        assert!(debounce.is_audible(&hit));
+    }
+
+    #[test]
+    fn crash_sound_fades_out() {
+        let sound = CrashSound::new(0.5);
+        let samples: Vec<f32> = sound.collect();
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|s| s.abs() <= 0.5));
+        assert!(samples[samples.len() - 1].abs() < 1e-3);
+    }
+}
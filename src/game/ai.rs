@@ -0,0 +1,344 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::car::{Car, MAX_STEER_ANGLE};
+use super::controller::{Controller, ControllerLayout};
+use super::spline::Spline;
+use nalgebra::{clamp, Vector3};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::FRAC_PI_3;
+
+/// How well computer players drive.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+}
+
+/// Driving style of an `AiController`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AiConfig {
+    /// Fraction of the maximum speed of the car the AI drives at on straights.
+    pub max_speed_fraction: f32,
+    /// Distance [m] before a sharp turn at which the AI starts braking.
+    pub braking_distance: f32,
+    /// Speed [m/s] the AI brakes down to before a sharp turn.
+    pub corner_speed: f32,
+    /// Number of updates the input of the AI lags behind the situation.
+    pub reaction_steps: usize,
+}
+
+impl Difficulty {
+    /// Return the driving style of this difficulty. Harder opponents drive faster, brake later and
+    /// react quicker.
+    pub fn config(self) -> AiConfig {
+        match self {
+            Difficulty::Easy => AiConfig {
+                max_speed_fraction: 0.6,
+                braking_distance: 30.,
+                corner_speed: 7.,
+                reaction_steps: 30,
+            },
+            Difficulty::Medium => AiConfig {
+                max_speed_fraction: 0.8,
+                braking_distance: 20.,
+                corner_speed: 10.,
+                reaction_steps: 15,
+            },
+            Difficulty::Hard => AiConfig {
+                max_speed_fraction: 1.,
+                braking_distance: 12.,
+                corner_speed: 14.,
+                reaction_steps: 4,
+            },
+        }
+    }
+}
+
+impl Default for AiConfig {
+    fn default() -> AiConfig {
+        Difficulty::default().config()
+    }
+}
+
+/// Computer player that drives a `Car` along a sequence of waypoints.
+///
+/// The AI produces the same input as a human player, so its `Controller` can be passed to `Car::update`.
+pub struct AiController {
+    waypoints: Vec<Vector3<f32>>,
+    current: usize,
+    controller: Controller,
+    /// Smooth line through the waypoints, followed instead of driving straight from one to the next.
+    racing_line: Option<Spline>,
+    /// Parameter of the racing line where the car is.
+    progress: f32,
+    /// Inputs that are computed, but not applied yet, the oldest first.
+    pending: VecDeque<(f32, f32)>,
+
+    /// A waypoint is reached once the car is closer than this radius [m].
+    pub radius: f32,
+    /// Turns sharper than this angle [rad] are taken at the corner speed of the `config`.
+    pub sharp_turn: f32,
+    /// Distance [m] ahead of the car on the racing line to steer towards.
+    pub lookahead: f32,
+    pub config: AiConfig,
+}
+
+impl AiController {
+    /// Create an AI that drives to the `waypoints` in order and starts over after the last one.
+    pub fn new(waypoints: Vec<Vector3<f32>>) -> AiController {
+        AiController {
+            waypoints,
+            current: 0,
+            controller: Controller::new(false, &ControllerLayout::WASD),
+            racing_line: None,
+            progress: 0.,
+            pending: VecDeque::new(),
+            radius: 3.,
+            sharp_turn: FRAC_PI_3,
+            lookahead: 8.,
+            config: Default::default(),
+        }
+    }
+
+    /// Create an AI that follows a Catmull-Rom spline through the `waypoints`, so it drives smooth
+    /// curves instead of straight lines with a kink at every waypoint.
+    pub fn with_racing_line(waypoints: Vec<Vector3<f32>>) -> AiController {
+        let mut ai = AiController::new(Vec::new());
+        if !waypoints.is_empty() {
+            ai.racing_line = Some(Spline::new(waypoints));
+        }
+        ai
+    }
+
+    /// Drive with the style of the `difficulty`.
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.config = difficulty.config();
+    }
+
+    /// Return the point the AI currently drives to.
+    pub fn target(&self) -> Option<Vector3<f32>> {
+        match &self.racing_line {
+            Some(line) => Some(line.point_ahead(self.progress, self.lookahead)),
+            None => self.waypoints.get(self.current).cloned(),
+        }
+    }
+
+    /// Compute the input for `car`, advancing to the next waypoint once the current one is reached.
+    ///
+    /// The returned input is the one computed `reaction_steps` updates ago, until then the AI doesn't
+    /// touch the controls.
+    pub fn update(&mut self, car: &Car) -> Controller {
+        let input = self.compute_input(car);
+        self.pending.push_back(input);
+        let (steer, throttle) = if self.pending.len() > self.config.reaction_steps {
+            self.pending.pop_front().unwrap_or_default()
+        } else {
+            (0., 0.)
+        };
+        self.controller.set_axis(steer, throttle);
+        self.controller
+    }
+
+    /// Return the steering and throttle axis for the current situation of `car`.
+    fn compute_input(&mut self, car: &Car) -> (f32, f32) {
+        if let Some(line) = &self.racing_line {
+            self.progress = line.closest_parameter(&car.position);
+            let target = line.point_ahead(self.progress, self.lookahead);
+            return self.input_towards(car, target);
+        }
+
+        let target = match self.target() {
+            Some(target) => target,
+            None => return (0., 0.),
+        };
+
+        let mut to_target = target - car.position;
+        to_target[2] = 0.;
+        if to_target.norm() < self.radius {
+            self.current = (self.current + 1) % self.waypoints.len();
+            return self.input_towards(car, self.waypoints[self.current]);
+        }
+        self.input_towards(car, target)
+    }
+
+    fn input_towards(&self, car: &Car, target: Vector3<f32>) -> (f32, f32) {
+        let forward = car.forward();
+        let angle = signed_angle(&forward, &(target - car.position));
+
+        // Steering right turns clockwise, so a target to the left (positive angle) needs a negative x axis.
+        let steer = clamp(-angle / MAX_STEER_ANGLE, -1., 1.);
+        // Signed, so reversing away from a sharp turn doesn't count as too fast.
+        let speed = car.velocity().dot(&forward);
+        let is_turning = angle.abs() > self.sharp_turn || self.is_sharp_turn_ahead(car);
+        let throttle = if is_turning && speed > self.config.corner_speed {
+            -1.
+        } else if speed > car.max_speed * self.config.max_speed_fraction {
+            0.
+        } else {
+            1.
+        };
+        (steer, throttle)
+    }
+
+    /// Return true if there is a sharp turn within the braking distance of `car`.
+    fn is_sharp_turn_ahead(&self, car: &Car) -> bool {
+        let distance = self.config.braking_distance;
+        match &self.racing_line {
+            Some(line) => {
+                let direction = |t: f32| line.point_ahead(t, 1.) - line.sample(t);
+                let ahead = line.parameter_ahead(self.progress, distance);
+                signed_angle(&direction(self.progress), &direction(ahead)).abs() > self.sharp_turn
+            }
+            None => {
+                let n = self.waypoints.len();
+                if n < 2 {
+                    return false;
+                }
+                let target = self.waypoints[self.current];
+                let previous = self.waypoints[(self.current + n - 1) % n];
+                let next = self.waypoints[(self.current + 1) % n];
+                let turn = signed_angle(&(target - previous), &(next - target));
+                turn.abs() > self.sharp_turn && (target - car.position).xy().norm() < distance
+            }
+        }
+    }
+}
+
+/// Return the angle [rad] from `from` to `to` in the xy-plane, positive counterclockwise.
+fn signed_angle(from: &Vector3<f32>, to: &Vector3<f32>) -> f32 {
+    let cross = from[0] * to[1] - from[1] * to[0];
+    let dot = from[0] * to[0] + from[1] * to[1];
+    cross.atan2(dot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{signed_angle, AiController, Difficulty};
+    use crate::game::car::Car;
+    use crate::game::spline::Spline;
+    use nalgebra::Vector3;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn angle_to_target() {
+        let forward = Vector3::new(0., 1., 0.);
+        assert_eq!(signed_angle(&forward, &Vector3::new(0., 5., 0.)), 0.);
+        assert!((signed_angle(&forward, &Vector3::new(-1., 0., 0.)) - FRAC_PI_2).abs() < 1e-6);
+        assert!((signed_angle(&forward, &Vector3::new(1., 0., 0.)) + FRAC_PI_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn drives_towards_waypoint() {
+        // The waypoint is behind and to the right of the car, so it has to turn around.
+        let waypoint = Vector3::new(20., -20., 0.);
+        let mut car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let mut ai = AiController::new(vec![waypoint, Vector3::new(-20., 20., 0.)]);
+
+        let start = (waypoint - car.position).norm();
+        let mut closest = start;
+        for _ in 0..500 {
+            let controller = ai.update(&car);
+            car.update(0.01, Some(controller));
+            closest = closest.min((waypoint - car.position).norm());
+        }
+        assert!(closest < start * 0.5);
+        assert!(ai.target().is_some());
+    }
+
+    #[test]
+    fn advances_to_next_waypoint() {
+        let car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let next = Vector3::new(0., 50., 0.);
+        let mut ai = AiController::new(vec![Vector3::new(1., 1., 0.), next]);
+        ai.config.reaction_steps = 0;
+
+        let controller = ai.update(&car);
+        assert_eq!(ai.target(), Some(next));
+        assert_eq!(controller.get_x_axis(), 0.);
+        assert_eq!(controller.get_y_axis(), 1.);
+    }
+
+    #[test]
+    fn follows_racing_line() {
+        let waypoints = vec![
+            Vector3::new(0., 0., 0.),
+            Vector3::new(0., 60., 0.),
+            Vector3::new(-60., 60., 0.),
+            Vector3::new(-60., 0., 0.),
+        ];
+        let mut car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let mut ai = AiController::with_racing_line(waypoints.clone());
+        let line = Spline::new(waypoints);
+
+        // The car goes around the first corner without leaving the line.
+        let mut farthest = 0f32;
+        for _ in 0..1000 {
+            let controller = ai.update(&car);
+            car.update(0.01, Some(controller));
+            let t = line.closest_parameter(&car.position);
+            farthest = farthest.max((line.sample(t) - car.position).xy().norm());
+        }
+        assert!(ai.progress > 1.5 && ai.progress < 3.);
+        assert!(farthest < 5.);
+        assert!((ai.target().unwrap() - car.position).norm() > 1.);
+    }
+
+    /// Return the average speed [m/s] of an AI with the `difficulty` during 20 s on a square track.
+    fn average_speed(difficulty: Difficulty) -> f32 {
+        let waypoints = vec![
+            Vector3::new(0., 0., 0.),
+            Vector3::new(0., 80., 0.),
+            Vector3::new(-80., 80., 0.),
+            Vector3::new(-80., 0., 0.),
+        ];
+        let mut car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let mut ai = AiController::new(waypoints);
+        ai.set_difficulty(difficulty);
+
+        let steps = 2000;
+        let mut speed = 0.;
+        for _ in 0..steps {
+            let controller = ai.update(&car);
+            car.update(0.01, Some(controller));
+            speed += car.velocity().norm();
+        }
+        speed / steps as f32
+    }
+
+    #[test]
+    fn harder_is_faster() {
+        let easy = average_speed(Difficulty::Easy);
+        let medium = average_speed(Difficulty::Medium);
+        let hard = average_speed(Difficulty::Hard);
+        assert!(easy > 1.);
+        assert!(hard > medium && medium > easy, "{} {} {}", easy, medium, hard);
+    }
+
+    #[test]
+    fn reaction_lag() {
+        let car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let mut ai = AiController::new(vec![Vector3::new(0., 50., 0.)]);
+        ai.config.reaction_steps = 3;
+        for _ in 0..3 {
+            assert_eq!(ai.update(&car).get_y_axis(), 0.);
+        }
+        assert_eq!(ai.update(&car).get_y_axis(), 1.);
+    }
+}
@@ -13,97 +13,788 @@
 // You should have received a copy of the GNU General Public License
 // along with Foobar.  If not, see <http://www.gnu.org/licenses/>.
 use super::controller::Controller;
-use crate::grphx::Model;
+use super::physics::VehiclePhysics;
+use super::track::Surface;
+use crate::grphx::{DirectionalLight, Model, ShaderError};
 use log::debug;
 use ncollide3d::shape::Cuboid;
+use serde_derive::{Deserialize, Serialize};
 
-use nalgebra::{clamp, zero, Matrix4, Vector3};
+use nalgebra::{clamp, zero, Isometry3, Matrix4, Point3, Vector3};
+use std::error::Error;
+use std::f32::consts::FRAC_PI_2;
+use std::fmt;
+use std::mem;
+
+/// Maximum steering angle of the front wheels [rad].
+pub(crate) const MAX_STEER_ANGLE: f32 = 0.45;
+/// Deceleration while braking [m/s²].
+const BRAKE_DECELERATION: f32 = 20.0;
+/// Forward speed [m/s] below which braking turns into driving backwards.
+const REVERSE_THRESHOLD: f32 = 0.1;
+/// Maximum speed when driving backwards [m/s].
+const MAX_REVERSE_SPEED: f32 = 13.0;
+/// Throttle below which the engine brakes the car.
+const ENGINE_BRAKE_THROTTLE: f32 = 0.05;
+/// Deceleration with locked rear wheels [m/s²].
+const HANDBRAKE_DECELERATION: f32 = 35.0;
+/// Fraction of the steering left with locked rear wheels.
+const HANDBRAKE_STEER_GRIP: f32 = 0.5;
+/// Rate [1/s] at which sideways sliding fades with locked rear wheels.
+const HANDBRAKE_LATERAL_GRIP: f32 = 1.5;
+/// Factor of the yaw rate while turning with the handbrake in arcade mode.
+const ARCADE_DRIFT_YAW: f32 = 2.5;
+
+/// Boost meter of a full tank.
+const MAX_BOOST: f32 = 100.0;
+/// Boost used per second while boosting.
+const BOOST_DRAIN: f32 = 30.0;
+/// Boost refilled per second while not boosting.
+const BOOST_REFILL: f32 = 14.0;
+/// Boost below which boosting has no effect anymore.
+const MIN_BOOST: f32 = 0.1;
+/// Factor of the engine force while boosting.
+const BOOST_FORCE_FACTOR: f32 = 1.4;
+/// Factor of the maximum speed while boosting.
+const BOOST_SPEED_FACTOR: f32 = 1.25;
+/// Deceleration [m/s²] back to the maximum speed after boosting.
+const BOOST_FADE: f32 = 5.0;
+
+/// Damage at which a car is disabled.
+const MAX_DAMAGE: f32 = 100.0;
+
+/// Roll [rad] of the drawn body per lateral acceleration [m/s²].
+const ROLL_PER_ACCELERATION: f32 = 0.006;
+/// Pitch [rad] of the drawn body per forward acceleration [m/s²].
+const PITCH_PER_ACCELERATION: f32 = 0.004;
+/// Largest roll and pitch [rad] of the drawn body.
+const MAX_BODY_TILT: f32 = 0.08;
+/// Rate [1/s] at which the drawn body follows the acceleration.
+const BODY_TILT_RESPONSE: f32 = 8.0;
+
+/// Length [m] of the wake behind a car in which followers are slipstreamed.
+const SLIPSTREAM_LENGTH: f32 = 20.0;
+/// Half width [m] of the wake right behind a car, it widens by `SLIPSTREAM_SPREAD` per meter.
+const SLIPSTREAM_WIDTH: f32 = 1.0;
+/// Widening of the wake per meter behind the car.
+const SLIPSTREAM_SPREAD: f32 = 0.1;
+/// Share of the drag taken off right behind a car.
+const MAX_SLIPSTREAM: f32 = 0.4;
+/// Slowest speed [m/s] at which a car leaves a wake.
+const SLIPSTREAM_MIN_SPEED: f32 = 10.0;
+
+/// Speed [m/s] a straightened car keeps rolling forward with, see `Car::straighten()`.
+const STRAIGHTEN_SPEED: f32 = 2.0;
+
+/// Snapshot of the simulated state of a `Car`, e.g. for save games and replays.
+///
+/// The tuning parameters, collider and `Model` aren't part of the state.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CarState {
+    pub position: Vector3<f32>,
+    pub rotation: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub mass: f32,
+    pub boost: f32,
+    #[serde(default)]
+    pub damage: f32,
+}
+
+/// How collisions wear a car down.
+///
+/// The top speed and engine force lose `max_loss * (damage / MAX_DAMAGE)^exponent` of their value,
+/// and at `MAX_DAMAGE` the car is disabled.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DamageModel {
+    /// Impulses [N s] up to this don't do any damage, so cars can nudge each other.
+    pub min_impulse: f32,
+    /// Damage per impulse [1/(N s)] above `min_impulse`.
+    pub damage_per_impulse: f32,
+    /// Fraction of the performance lost right before the car is disabled.
+    pub max_loss: f32,
+    /// Shape of the degradation, 1.0 is linear and larger values spare lightly damaged cars.
+    pub exponent: f32,
+}
+
+impl Default for DamageModel {
+    fn default() -> DamageModel {
+        DamageModel {
+            min_impulse: 2000.,
+            damage_per_impulse: 0.002,
+            max_loss: 0.5,
+            exponent: 2.,
+        }
+    }
+}
+
+/// Oriented bounding box of a car in world space, rotated around the z-axis only.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Obb {
+    pub center: Vector3<f32>,
+    /// Half of the size [m] along the local axes of the box.
+    pub half_extents: Vector3<f32>,
+    /// Rotation around the z-axis [rad].
+    pub rotation: f32,
+}
+
+impl Obb {
+    /// Return the local x- and y-axis of the box in world space.
+    pub fn axes(&self) -> [Vector3<f32>; 2] {
+        [heading(self.rotation - FRAC_PI_2), heading(self.rotation)]
+    }
+
+    /// Return the half length of the box projected onto the unit vector `axis` in the x-y plane.
+    pub fn radius(&self, axis: &Vector3<f32>) -> f32 {
+        let [x, y] = self.axes();
+        self.half_extents[0] * x.dot(axis).abs() + self.half_extents[1] * y.dot(axis).abs()
+    }
+
+    /// Return the half extents of the smallest axis aligned box around this one.
+    pub fn world_half_extents(&self) -> Vector3<f32> {
+        Vector3::new(self.radius(&Vector3::x()), self.radius(&Vector3::y()), self.half_extents[2])
+    }
+}
 
 /// A GameObject controlled by a player.
 pub struct Car {
     pub position: Vector3<f32>, // position in world space
     pub rotation: Vector3<f32>, // rotation in radians per axis
-    _velocity: Vector3<f32>,
+    velocity: Vector3<f32>,     // velocity in world space [m/s]
     _force: Vector3<f32>,
-    _mass: f32,
+    mass: f32,
+    /// Boost meter between 0 and `MAX_BOOST`, it drains while boosting and refills slowly.
     pub boost: f32,
+    /// Damage between 0 and `MAX_DAMAGE` from collisions with other cars, see `damage_model`.
+    pub damage: f32,
+    /// How the damage grows with the impacts and slows the car down.
+    pub damage_model: DamageModel,
 
-    pub model: Model,
+    /// Force of the engine at full throttle [N].
+    pub engine_force: f32,
+    /// Maximum speed when driving forward [m/s].
+    pub max_speed: f32,
+    /// Aerodynamic drag, the force opposing the car is `drag_coefficient * speed²` [N].
+    pub drag_coefficient: f32,
+    /// Rolling resistance, the force opposing the car is `rolling_resistance * speed` [N].
+    pub rolling_resistance: f32,
+    /// Deceleration [m/s²] of the engine while driving forward without throttle, on top of the resistance.
+    pub engine_brake: f32,
+    /// Bounciness in collisions with other cars, 0.0 is plastic and 1.0 elastic.
+    pub restitution: f32,
+
+    /// Maximum acceleration of the tires [m/s²]. Turning harder than that makes the car slide.
+    ///
+    /// Accelerating, braking and cornering share the grip: the engine and brakes act with `tire_grip`
+    /// at most, and whatever they use is missing for cornering.
+    pub tire_grip: f32,
+    /// How fast the steering loses authority with speed [s/m]. The steering angle is divided by
+    /// `1 + steer_speed_falloff * speed`, so the car parks nimbly and stays stable at high speed.
+    pub steer_speed_falloff: f32,
+    /// Arcade instead of realistic handbrake turns: the car swings around faster and shoots off in its
+    /// new direction with the tires gripping again as soon as the handbrake is released.
+    pub arcade_drift: bool,
+    /// Set while drifting with the handbrake in arcade mode, until the tires grip again.
+    is_drifting: bool,
+    /// Ground under the car, it scales the `tire_grip` and `rolling_resistance`. The `Simulation`
+    /// looks it up on the track before each update.
+    pub surface: Surface,
+    /// Share of the drag taken off by the wake of a car ahead, see `slipstream_behind()`. The
+    /// `Simulation` looks for the car ahead before each update.
+    pub slipstream: f32,
+
+    /// Distance from the center of the car to the front axle [m].
+    pub dist_front_axle: f32,
+    /// Distance from the center of the car to the rear axle [m].
+    pub dist_rear_axle: f32,
+    /// Distance between the left and right wheels of an axle [m].
+    pub track_width: f32,
+
+    /// Driving model used instead of the one built into the car, see `update()`.
+    pub physics: Option<Box<dyn VehiclePhysics>>,
+    pub model: Option<Model>,
+    /// Obj file and color palette the model was loaded from, see `looks_like()`.
+    model_files: Option<(String, String)>,
     pub cuboid: Cuboid<f32>,
+    /// RGB tint multiplied into the colors of the model, white keeps them.
+    color: Vector3<f32>,
+    /// Position and yaw [rad] before the last update, to draw the car in between two updates.
+    /// `None` after spawning or teleporting the car.
+    previous_pose: Option<(Vector3<f32>, f32)>,
+    /// Smoothed acceleration [m/s²] of the last updates, it only tilts the drawn body.
+    body_acceleration: Vector3<f32>,
+}
+
+/// Error while building a `Car` with invalid parameters.
+#[derive(Debug, PartialEq)]
+pub enum CarError {
+    /// The distances of the axles from the center have to be positive, given as (front, rear).
+    Axles(f32, f32),
+}
+
+impl fmt::Display for CarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CarError::Axles(front, rear) => write!(f, "axle distances have to be positive, got {}m front and {}m rear", front, rear),
+        }
+    }
+}
+
+impl Error for CarError {}
+
+/// Sets up a `Car` with custom tuning before building it.
+///
+/// Parameters that aren't set keep the defaults of `Car::new()`.
+pub struct CarBuilder {
+    position: Vector3<f32>,
+    mass: f32,
+    model: Option<(String, String)>,
+    half_extents: Option<Vector3<f32>>,
+    axles: Option<(f32, f32)>,
+    engine_force: Option<f32>,
+    max_speed: Option<f32>,
+    drag_coefficient: Option<f32>,
+    arcade_drift: bool,
+}
+
+impl CarBuilder {
+    /// Start a car with a `mass` [kg] at `position`.
+    pub fn new(position: Vector3<f32>, mass: f32) -> CarBuilder {
+        CarBuilder {
+            position,
+            mass,
+            model: None,
+            half_extents: None,
+            axles: None,
+            engine_force: None,
+            max_speed: None,
+            drag_coefficient: None,
+            arcade_drift: false,
+        }
+    }
+
+    /// Load the `model` with a `color_palette`, its size replaces the half extents.
+    ///
+    /// For `model` and `color_palette` see `Car::model_from_id()` and `Car::color_from_id()`.
+    pub fn model(mut self, model: &str, color_palette: &str) -> CarBuilder {
+        self.model = Some((model.to_owned(), color_palette.to_owned()));
+        self
+    }
+
+    /// Set the half extents [m] of the collider instead of fitting it to the model.
+    pub fn half_extents(mut self, half_extents: Vector3<f32>) -> CarBuilder {
+        self.half_extents = Some(half_extents);
+        self
+    }
+
+    /// Set the distances [m] from the center to the `front` and `rear` axle.
+    pub fn axles(mut self, front: f32, rear: f32) -> CarBuilder {
+        self.axles = Some((front, rear));
+        self
+    }
+
+    /// Set the force of the engine at full throttle [N].
+    pub fn engine_force(mut self, engine_force: f32) -> CarBuilder {
+        self.engine_force = Some(engine_force);
+        self
+    }
+
+    /// Set the maximum speed when driving forward [m/s].
+    pub fn max_speed(mut self, max_speed: f32) -> CarBuilder {
+        self.max_speed = Some(max_speed);
+        self
+    }
+
+    /// Set the aerodynamic drag coefficient.
+    pub fn drag_coefficient(mut self, drag_coefficient: f32) -> CarBuilder {
+        self.drag_coefficient = Some(drag_coefficient);
+        self
+    }
+
+    /// Use arcade handbrake turns instead of realistic ones, see `Car::arcade_drift`.
+    pub fn arcade_drift(mut self, arcade_drift: bool) -> CarBuilder {
+        self.arcade_drift = arcade_drift;
+        self
+    }
+
+    /// Build the car. The model is loaded only if the parameters are valid.
+    pub fn build(self) -> Result<Car, CarError> {
+        if let Some((front, rear)) = self.axles {
+            // Written this way to reject NaN, too.
+            if !(front > 0. && rear > 0.) {
+                return Err(CarError::Axles(front, rear));
+            }
+        }
+
+        let mut car = match &self.model {
+            Some((model, color_palette)) => {
+                let model = Model::new(model, color_palette);
+                let (min, max) = model.get_min_max();
+                let half_extents = self.half_extents.unwrap_or((max - min) * 0.25);
+                let mut car = Car::with_half_extents(half_extents, self.position, self.mass);
+                car.model = Some(model);
+                car.model_files = self.model.clone();
+                car
+            }
+            None => Car::with_half_extents(self.half_extents.unwrap_or_else(default_half_extents), self.position, self.mass),
+        };
+        if let Some((front, rear)) = self.axles {
+            car.dist_front_axle = front;
+            car.dist_rear_axle = rear;
+        }
+        car.engine_force = self.engine_force.unwrap_or(car.engine_force);
+        car.max_speed = self.max_speed.unwrap_or(car.max_speed);
+        car.drag_coefficient = self.drag_coefficient.unwrap_or(car.drag_coefficient);
+        car.arcade_drift = self.arcade_drift;
+        Ok(car)
+    }
+}
+
+/// Tuning of all cars in the config, parameters that aren't set keep the defaults of the car.
+///
+/// See `CarBuilder` for the parameters.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CarTuning {
+    pub half_extents: Option<Vector3<f32>>,
+    /// Distances [m] of the `(front, rear)` axle from the center.
+    pub axles: Option<(f32, f32)>,
+    pub engine_force: Option<f32>,
+    pub max_speed: Option<f32>,
+    pub drag_coefficient: Option<f32>,
+    /// Taken from the `arcade_drift` setting of the game.
+    #[serde(skip)]
+    pub arcade_drift: bool,
+}
+
+impl CarTuning {
+    /// Set the parameters of this tuning on a `builder`.
+    pub fn apply(&self, builder: CarBuilder) -> CarBuilder {
+        let mut builder = builder.arcade_drift(self.arcade_drift);
+        if let Some(half_extents) = self.half_extents {
+            builder = builder.half_extents(half_extents);
+        }
+        if let Some((front, rear)) = self.axles {
+            builder = builder.axles(front, rear);
+        }
+        if let Some(engine_force) = self.engine_force {
+            builder = builder.engine_force(engine_force);
+        }
+        if let Some(max_speed) = self.max_speed {
+            builder = builder.max_speed(max_speed);
+        }
+        if let Some(drag_coefficient) = self.drag_coefficient {
+            builder = builder.drag_coefficient(drag_coefficient);
+        }
+        builder
+    }
+}
+
+/// Return the half extents [m] of the collider of a car without a `Model`.
+fn default_half_extents() -> Vector3<f32> {
+    Vector3::new(0.5, 1.0, 0.3)
 }
 
 impl Car {
     /// Create a new `Car`.
     ///
     /// For `model` and `color_palette` see `model_from_id()` and `color_from_id()`.
-    /// `mass` is the mass of the car in [kg]
+    /// `mass` is the mass of the car in [kg]. Use a `CarBuilder` to tune the car.
     pub fn new(model: &str, color_palette: &str, position: Vector3<f32>, mass: f32) -> Car {
         debug!("New({}, {}, {:?}, {})", model, color_palette, position, mass);
 
-        let model = Model::new(model, color_palette);
-        let (min, max) = model.get_min_max();
-        let cuboid = Cuboid::new((max - min) * 0.25);
+        CarBuilder::new(position, mass)
+            .model(model, color_palette)
+            .build()
+            .expect("ERROR: Failed to build car!")
+    }
+
+    /// Create a car at `position` with the half extents of a car without a `Model`, so tests can
+    /// do without a GL context.
+    #[cfg(test)]
+    pub(crate) fn test_car(position: Vector3<f32>, mass: f32) -> Car {
+        Car::with_half_extents(default_half_extents(), position, mass)
+    }
 
+    /// Create a new `Car` without a `Model` from the half extents of its collider.
+    ///
+    /// The axles are placed at 60% of the half length in front of and behind the center.
+    pub(crate) fn with_half_extents(half_extents: Vector3<f32>, position: Vector3<f32>, mass: f32) -> Car {
         Car {
             position,
             rotation: zero(),
-            _velocity: zero(),
+            velocity: zero(),
             _force: zero(),
-            _mass: mass,
-            boost: 100.0,
-            model,
-            cuboid,
+            mass,
+            boost: MAX_BOOST,
+            damage: 0.,
+            damage_model: Default::default(),
+            engine_force: 10_000.0,
+            max_speed: 40.0,
+            drag_coefficient: 20.0,
+            rolling_resistance: 500.0,
+            engine_brake: 2.0,
+            restitution: 0.4,
+            tire_grip: 25.0,
+            steer_speed_falloff: 0.05,
+            arcade_drift: false,
+            is_drifting: false,
+            surface: Surface::default(),
+            slipstream: 0.,
+            dist_front_axle: half_extents[1] * 0.6,
+            dist_rear_axle: half_extents[1] * 0.6,
+            track_width: half_extents[0] * 2.,
+            physics: None,
+            model: None,
+            model_files: None,
+            cuboid: Cuboid::new(half_extents),
+            color: Vector3::repeat(1.),
+            previous_pose: None,
+            body_acceleration: zero(),
         }
     }
 
     /// Update the car position and velocity based on the internal car state for
     /// a given time step.
+    ///
+    /// The controller input accelerates the car, while the position is integrated
+    /// from the velocity. Without input the car keeps its momentum.
+    ///
+    /// Steering uses a kinematic bicycle model, so the car turns around its rear axle
+    /// with a yaw rate of `speed / wheelbase * tan(steer_angle)`. The steering angle shrinks
+    /// with speed, see `steer_speed_falloff`. With `physics` set, that model drives the car instead.
     pub(super) fn update(&mut self, dt: f32, controller: Option<Controller>) {
-        if controller.is_some() {
-            let ct = controller.unwrap();
+        self.previous_pose = Some((self.position, self.rotation[2]));
+        let previous_velocity = self.velocity;
+        let mut state = self.state();
+        match self.physics.as_mut() {
+            Some(physics) => physics.step(&mut state, controller, dt),
+            None => {
+                let mut model = self.bicycle_model();
+                model.step(&mut state, controller, dt);
+                self.is_drifting = model.is_drifting;
+            }
+        }
+        // The car takes back all of the state, a model may e.g. burn fuel and lose mass.
+        self.position = state.position;
+        self.rotation = state.rotation;
+        self.velocity = state.velocity;
+        self.mass = state.mass;
+        self.boost = state.boost;
+        self.damage = state.damage;
 
-            // accel:  0.0 - None
-            //         1.0 - Pedal to the metal
-            //        -1.0 - Emergency brake
-            let accel = ct.get_y_axis();
-            // steer:  0.0 - Forward
-            //         1.0 - Full right
-            //        -1.0 - Full left
-            // * accel to prevent steering a non moving car.
-            let steer = ct.get_x_axis() * accel;
-
-            // x,y-axis rotation are fixed to 0. No rollovers!
-            self.rotation[2] -= steer * dt * 3.5;
-
-            let rot_mat = Matrix4::new_rotation(self.rotation);
-            let mut forward = Vector3::new(0f32, 1., 0.).to_homogeneous();
-            forward = rot_mat * forward;
-            // Set homogeneous coordinate to 0 or unwrap() will panic.
-            forward[3] = 0.;
-
-            let booster = if ct.get_boost() {
-                self.boost = clamp(self.boost - dt * 30.0, 0.0, 100.0);
-                if self.boost > 0.1 {
-                    14.0
-                } else {
-                    10.0
-                }
-            } else {
-                self.boost = clamp(self.boost + dt * 14.0, 0.0, 100.0);
-                10.0
-            };
+        // The drawn body leans with the acceleration, smoothed over the last updates.
+        if dt > 0. {
+            let acceleration = (self.velocity - previous_velocity) / dt;
+            let factor = (BODY_TILT_RESPONSE * dt).min(1.);
+            self.body_acceleration += (acceleration - self.body_acceleration) * factor;
+        }
+    }
 
-            self.position += Vector3::from_homogeneous(forward).unwrap() * accel * booster * dt;
+    /// Return the driving model built into the car, set up with its tuning and the ground under it.
+    fn bicycle_model(&self) -> BicycleModel {
+        BicycleModel {
+            engine_force: self.engine_force,
+            max_speed: self.max_speed,
+            performance: self.performance(),
+            drag_coefficient: self.drag_coefficient * (1. - self.slipstream),
+            rolling_resistance: self.rolling_resistance * self.surface.rolling_resistance,
+            engine_brake: self.engine_brake,
+            tire_grip: self.tire_grip * self.surface.grip,
+            steer_speed_falloff: self.steer_speed_falloff,
+            arcade_drift: self.arcade_drift,
+            is_drifting: self.is_drifting,
+            dist_front_axle: self.dist_front_axle,
+            dist_rear_axle: self.dist_rear_axle,
         }
     }
 
-    /// Draw the car to the currently bound framebuffer.
-    pub(super) fn draw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+    /// Put the car back to `position` as if it was just spawned there.
+    ///
+    /// The car stands still facing forward with a full boost, while its physical
+    /// properties, damage and `Model` are kept.
+    pub fn reset(&mut self, position: Vector3<f32>) {
+        self.position = position;
+        self.rotation = zero();
+        self.velocity = zero();
+        self.boost = MAX_BOOST;
+        self.is_drifting = false;
+        self.previous_pose = None;
+        self.body_acceleration = zero();
+    }
+
+    /// Turn the car to `yaw` [rad] where it is, e.g. after a bump left it facing the wrong way.
+    ///
+    /// Roll and pitch are cleared and the car rolls forward slowly. Unlike `reset()` the boost,
+    /// damage and position are kept.
+    pub fn straighten(&mut self, yaw: f32) {
+        self.rotation = Vector3::new(0., 0., yaw);
+        self.velocity = self.forward() * STRAIGHTEN_SPEED;
+        self.is_drifting = false;
+        self.previous_pose = None;
+        self.body_acceleration = zero();
+    }
+
+    /// Return the share of the drag the wake of the `leader` takes off this car, 0 outside of it.
+    ///
+    /// The wake widens like a cone behind the leader once it is fast enough, and the effect fades
+    /// out towards its end and its sides.
+    pub fn slipstream_behind(&self, leader: &Car) -> f32 {
+        if leader.speed() < SLIPSTREAM_MIN_SPEED {
+            return 0.;
+        }
+        let offset = self.position - leader.position;
+        let behind = -offset.dot(&leader.forward());
+        let lateral = offset.dot(&heading(leader.rotation[2] - FRAC_PI_2)).abs();
+        let width = SLIPSTREAM_WIDTH + SLIPSTREAM_SPREAD * behind;
+        if behind <= 0. || behind > SLIPSTREAM_LENGTH || lateral > width {
+            return 0.;
+        }
+        MAX_SLIPSTREAM * (1. - behind / SLIPSTREAM_LENGTH) * (1. - lateral / width)
+    }
+
+    /// Return true if the collider of this car overlaps or touches the one of `other`.
+    ///
+    /// Both colliders are tested as oriented boxes in the x-y plane with the separating axis theorem,
+    /// after ruling out cars whose bounding circles are apart, which most pairs are.
+    pub fn collides_with(&self, other: &Car) -> bool {
+        let reach = self.cuboid.half_extents().xy().norm() + other.cuboid.half_extents().xy().norm();
+        let distance = (other.position - self.position).xy();
+        distance.norm_squared() <= reach * reach && self.penetration(other).is_some()
+    }
+
+    /// Return a snapshot of the simulated state.
+    pub fn state(&self) -> CarState {
+        CarState {
+            position: self.position,
+            rotation: self.rotation,
+            velocity: self.velocity,
+            mass: self.mass,
+            boost: self.boost,
+            damage: self.damage,
+        }
+    }
+
+    /// Restore the simulated state from a snapshot.
+    pub fn set_state(&mut self, state: &CarState) {
+        self.position = state.position;
+        self.rotation = state.rotation;
+        self.velocity = state.velocity;
+        self.mass = state.mass;
+        self.boost = state.boost;
+        self.damage = state.damage;
+        self.previous_pose = None;
+        self.body_acceleration = zero();
+    }
+
+    /// Take damage from a collision with an `impulse` [N s].
+    pub fn apply_impact(&mut self, impulse: f32) {
+        let model = &self.damage_model;
+        let damage = (impulse - model.min_impulse).max(0.) * model.damage_per_impulse;
+        self.damage = (self.damage + damage).min(MAX_DAMAGE);
+    }
+
+    /// Return the damage between 0.0 (intact) and 1.0 (disabled).
+    pub fn damage_level(&self) -> f32 {
+        self.damage / MAX_DAMAGE
+    }
+
+    /// Return true if the car is too damaged to drive on.
+    pub fn is_disabled(&self) -> bool {
+        self.damage >= MAX_DAMAGE
+    }
+
+    /// Return the factor of the engine force and top speed left after the damage.
+    fn performance(&self) -> f32 {
+        if self.is_disabled() {
+            return 0.;
+        }
+        let model = &self.damage_model;
+        1. - model.max_loss * self.damage_level().powf(model.exponent)
+    }
+
+    /// Return the position before the last update, `None` after spawning or teleporting the car.
+    pub fn previous_position(&self) -> Option<Vector3<f32>> {
+        self.previous_pose.map(|(position, _)| position)
+    }
+
+    /// Return the oriented bounding box of the collider in world space.
+    pub fn bounding_box(&self) -> Obb {
+        Obb {
+            center: self.position,
+            half_extents: *self.cuboid.half_extents(),
+            rotation: self.rotation[2],
+        }
+    }
+
+    /// Return the contact normal pointing from this car to `other` and the penetration depth [m]
+    /// if their colliders overlap or touch in the x-y plane.
+    fn penetration(&self, other: &Car) -> Option<(Vector3<f32>, f32)> {
+        let (box_a, box_b) = (self.bounding_box(), other.bounding_box());
+        let mut distance = box_b.center - box_a.center;
+        distance[2] = 0.;
+
+        // The axis with the smallest overlap separates the boxes the fastest.
+        let mut contact = (zero(), f32::MAX);
+        for axis in box_a.axes().iter().chain(box_b.axes().iter()) {
+            let projected = distance.dot(axis);
+            let depth = box_a.radius(axis) + box_b.radius(axis) - projected.abs();
+            if depth < 0. {
+                return None;
+            }
+            if depth < contact.1 {
+                let normal = if projected < 0. { -axis } else { *axis };
+                contact = (normal, depth);
+            }
+        }
+        Some(contact)
+    }
+
+    /// Return the fill level of the boost meter between 0.0 (empty) and 1.0 (full).
+    pub fn boost_level(&self) -> f32 {
+        self.boost / MAX_BOOST
+    }
+
+    /// Return the velocity of the car in world space [m/s].
+    pub fn velocity(&self) -> Vector3<f32> {
+        self.velocity
+    }
+
+    /// Set the velocity of the car in world space [m/s], e.g. after hitting a wall.
+    pub fn set_velocity(&mut self, velocity: Vector3<f32>) {
+        self.velocity = velocity;
+    }
+
+    /// Return the speed of the car along its forward axis [m/s], regardless of driving forward or
+    /// in reverse. Sliding sideways doesn't count.
+    pub fn speed(&self) -> f32 {
+        self.velocity.dot(&self.forward()).abs()
+    }
+
+    /// Return the angle [rad] between the velocity and the forward axis of the car, 0.0 while the
+    /// tires grip and up to π/2 when sliding sideways. Driving in reverse doesn't count as slip.
+    pub fn slip_angle(&self) -> f32 {
+        let forward = self.forward();
+        let speed = self.velocity.dot(&forward);
+        let lateral = (self.velocity - forward * speed).norm();
+        if lateral < 1e-6 {
+            0.
+        } else {
+            lateral.atan2(speed.abs())
+        }
+    }
+
+    /// Return the positions of the wheels in world space: front left, front right, rear left and rear right.
+    pub fn wheel_positions(&self) -> [Vector3<f32>; 4] {
+        let pose = self.isometry();
+        let (front, rear, track) = (self.dist_front_axle, -self.dist_rear_axle, self.track_width * 0.5);
+        let wheel = |x: f32, y: f32| (pose * Point3::new(x, y, 0.)).coords;
+        [wheel(-track, front), wheel(track, front), wheel(-track, rear), wheel(track, rear)]
+    }
+
+    /// Return the positions of the left and right rear wheel in world space.
+    pub fn rear_wheels(&self) -> [Vector3<f32>; 2] {
+        let [_, _, left, right] = self.wheel_positions();
+        [left, right]
+    }
+
+    /// Return the unit vector the car is facing in world space.
+    pub fn forward(&self) -> Vector3<f32> {
+        heading(self.rotation[2])
+    }
+
+    /// Return the pose of the car, the translation to its position and the rotation around the z-axis.
+    pub fn isometry(&self) -> Isometry3<f32> {
         // x,y-axis rotation are fixed to 0. No rollovers!
-        let rotation = Matrix4::from_euler_angles(0., 0., self.rotation[2]);
-        let translation = Matrix4::new_translation(&self.position);
-        let model = translation * rotation * Matrix4::new_scaling(0.5f32);
-        self.model.draw(&model, view, projection);
+        Isometry3::new(self.position, Vector3::z() * self.rotation[2])
+    }
+
+    /// Return the pose of the car an `alpha` between 0.0 (the last) and 1.0 (the current update)
+    /// of the way from the previous update to the current one.
+    ///
+    /// The simulation runs with a fixed time step, so drawing the cars in between keeps them moving
+    /// smoothly at any frame rate.
+    pub fn interpolated_pose(&self, alpha: f32) -> Isometry3<f32> {
+        match self.previous_pose {
+            Some((position, yaw)) => Isometry3::new(
+                position + (self.position - position) * alpha,
+                Vector3::z() * (yaw + (self.rotation[2] - yaw) * alpha),
+            ),
+            None => self.isometry(),
+        }
+    }
+
+    /// Return the roll [rad] of the drawn body around its forward axis, leaning out of turns.
+    ///
+    /// Roll and pitch are purely visual, the car itself always stays level.
+    pub fn visual_roll(&self) -> f32 {
+        let [right, _] = self.bounding_box().axes();
+        let roll = -self.body_acceleration.dot(&right) * ROLL_PER_ACCELERATION;
+        clamp(roll, -MAX_BODY_TILT, MAX_BODY_TILT)
+    }
+
+    /// Return the pitch [rad] of the drawn body around its right axis, the nose rises while
+    /// accelerating and dives while braking.
+    pub fn visual_pitch(&self) -> f32 {
+        let pitch = self.body_acceleration.dot(&self.forward()) * PITCH_PER_ACCELERATION;
+        clamp(pitch, -MAX_BODY_TILT, MAX_BODY_TILT)
+    }
+
+    /// Return the RGB tint of the car.
+    pub fn color(&self) -> Vector3<f32> {
+        self.color
+    }
+
+    /// Tint the car with an RGB `color` between 0.0 and 1.0 per channel, to tell cars with the same
+    /// color palette apart.
+    pub fn set_color(&mut self, color: Vector3<f32>) {
+        self.color = color;
+    }
+
+    /// Draw the car to the currently bound framebuffer.
+    ///
+    /// It is drawn `interpolation` of the way from its previous to its current pose, see `interpolated_pose()`.
+    pub(super) fn draw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>, light: &DirectionalLight, interpolation: f32) {
+        self.draw_translucent(view, projection, light, interpolation, 1.);
+    }
+
+    /// Draw the car with an opacity between 0.0 (invisible) and 1.0 (opaque).
+    pub(super) fn draw_translucent(
+        &self,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+        light: &DirectionalLight,
+        interpolation: f32,
+        alpha: f32,
+    ) {
+        if let Some(m) = &self.model {
+            m.draw_tinted(&self.model_matrix(interpolation), view, projection, light, alpha, &self.color);
+        }
+    }
+
+    /// Draw the model of the car once for every model matrix in `instances`, for cars that look like it.
+    pub(super) fn draw_instanced(
+        &mut self,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+        light: &DirectionalLight,
+        instances: &[Matrix4<f32>],
+    ) -> Result<(), ShaderError> {
+        match &mut self.model {
+            Some(m) => m.draw_instanced(view, projection, light, &self.color, instances),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the model matrix of the car drawn `interpolation` of the way from its previous to its current pose.
+    pub(super) fn model_matrix(&self, interpolation: f32) -> Matrix4<f32> {
+        let tilt = Matrix4::from_euler_angles(self.visual_pitch(), self.visual_roll(), 0.);
+        self.interpolated_pose(interpolation).to_homogeneous() * tilt * Matrix4::new_scaling(0.5f32)
+    }
+
+    /// Return whether the car is drawn with the same model, color palette and tint as `other`, so both can
+    /// be drawn in one go with `draw_instanced()`.
+    pub fn looks_like(&self, other: &Car) -> bool {
+        self.model_files.is_some() && self.model_files == other.model_files && self.color == other.color
     }
 
     /// Return a `Car` file name from an id.
@@ -138,3 +829,945 @@ impl Car {
         }
     }
 }
+
+/// Where and how hard two cars hit each other.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Contact {
+    /// Impulse magnitude [N s] exchanged between the cars, 0.0 if they touch without approaching.
+    pub impulse: f32,
+    /// Point in world space where the cars touch after being pushed apart, on the line between their centers.
+    pub point: Vector3<f32>,
+}
+
+/// Resolve a collision between two cars.
+///
+/// If the cars touch, an impulse along the contact normal changes their velocities with respect to
+/// their masses and the mean restitution of both, so a lighter car gets knocked away harder. Both cars
+/// are pushed apart so they don't stick together. Returns the `Contact` on a collision.
+pub fn resolve_collision(a: &mut Car, b: &mut Car) -> Option<Contact> {
+    let (normal, depth) = a.penetration(b)?;
+    let inv_mass_a = 1. / a.mass;
+    let inv_mass_b = 1. / b.mass;
+    let inv_mass = inv_mass_a + inv_mass_b;
+
+    // Push the cars apart, the lighter car moves further.
+    a.position -= normal * depth * inv_mass_a / inv_mass;
+    b.position += normal * depth * inv_mass_b / inv_mass;
+    let point = a.position + normal * a.bounding_box().radius(&normal);
+
+    // Only cars moving towards each other exchange momentum.
+    let approach = (b.velocity - a.velocity).dot(&normal);
+    if approach >= 0. {
+        return Some(Contact { impulse: 0., point });
+    }
+    let restitution = (a.restitution + b.restitution) * 0.5;
+    let impulse = -(1. + restitution) * approach / inv_mass;
+    a.velocity -= normal * impulse * inv_mass_a;
+    b.velocity += normal * impulse * inv_mass_b;
+
+    Some(Contact { impulse, point })
+}
+
+/// Driving model built into every `Car`, used unless the car has other `physics`.
+///
+/// A kinematic bicycle model turning around the rear axle, with tires that slip once the engine,
+/// brakes and cornering ask for more than they grip, see `Car::update()`. The car sets it up from its
+/// tuning before every update, so its fields still tune the car.
+struct BicycleModel {
+    engine_force: f32,
+    max_speed: f32,
+    /// Share of the engine force and top speed left after the damage.
+    performance: f32,
+    /// Drag coefficient less the slipstream.
+    drag_coefficient: f32,
+    /// Rolling resistance on the ground under the car.
+    rolling_resistance: f32,
+    engine_brake: f32,
+    /// Grip of the tires on the ground under the car.
+    tire_grip: f32,
+    steer_speed_falloff: f32,
+    arcade_drift: bool,
+    is_drifting: bool,
+    dist_front_axle: f32,
+    dist_rear_axle: f32,
+}
+
+impl VehiclePhysics for BicycleModel {
+    fn step(&mut self, state: &mut CarState, controller: Option<Controller>, dt: f32) {
+        let mut accel = 0.;
+        let mut is_boosting = false;
+        let mut steer = 0.;
+        let mut handbrake = false;
+        if controller.is_some() {
+            let ct = controller.unwrap();
+
+            // accel:  0.0 - None
+            //         1.0 - Pedal to the metal
+            //        -1.0 - Brake, reverse when standing still
+            accel = ct.get_y_axis();
+            // steer:  0.0 - Forward
+            //         1.0 - Full right
+            //        -1.0 - Full left
+            steer = ct.get_x_axis();
+            handbrake = ct.get_handbrake();
+
+            // An empty meter has to refill before the boost works again.
+            if ct.get_boost() {
+                is_boosting = state.boost > MIN_BOOST;
+                state.boost = clamp(state.boost - dt * BOOST_DRAIN, 0.0, MAX_BOOST);
+            } else {
+                state.boost = clamp(state.boost + dt * BOOST_REFILL, 0.0, MAX_BOOST);
+            }
+        }
+
+        // The velocity is split into the part along the forward axis of the car, which the engine
+        // and brakes act on, and the sideways slip.
+        let forward = heading(state.rotation[2]);
+        let mut speed = state.velocity.dot(&forward);
+        let mut lateral = state.velocity - forward * speed;
+
+        // A heavier car accelerates slower with the same engine. Damage takes away engine force and
+        // top speed, a disabled car only rolls out.
+        let performance = self.performance;
+        let engine_accel = self.engine_force * performance / state.mass;
+        let (booster, max_speed) = if is_boosting {
+            (BOOST_FORCE_FACTOR, self.max_speed * performance * BOOST_SPEED_FACTOR)
+        } else {
+            (1., self.max_speed * performance)
+        };
+        let tire_speed = speed;
+        if speed > max_speed {
+            // After boosting or a hit, the car slows down to its maximum speed smoothly.
+            speed = (speed - BOOST_FADE * dt).max(max_speed);
+        } else if accel >= 0. {
+            speed = (speed + accel * engine_accel * booster * dt).min(max_speed);
+            // Lifting off the throttle, the engine holds the car back. It doesn't act while reversing.
+            if accel < ENGINE_BRAKE_THROTTLE && speed > 0. && !handbrake {
+                speed = (speed - self.engine_brake * dt).max(0.);
+            }
+        } else if speed > REVERSE_THRESHOLD {
+            // Braking stops at zero, reversing starts on the next update.
+            speed = (speed + accel * BRAKE_DECELERATION * dt).max(0.);
+        } else {
+            speed = (speed + accel * engine_accel * dt).max(-MAX_REVERSE_SPEED);
+        }
+        // The wheels spin or lock when the engine or brakes demand more than the tires grip.
+        let max_grip = self.tire_grip * dt;
+        let traction = clamp(speed - tire_speed, -max_grip, max_grip);
+        speed = tire_speed + traction;
+
+        // Drag and rolling resistance slow the car down, but never push it backwards.
+        let resistance = (self.rolling_resistance * speed + self.drag_coefficient * speed * speed.abs()) / state.mass;
+        let resisted_speed = speed - resistance * dt;
+        speed = if resisted_speed * speed > 0. { resisted_speed } else { 0. };
+        if handbrake {
+            let decel = HANDBRAKE_DECELERATION * dt;
+            speed = if speed > 0. {
+                (speed - decel).max(0.)
+            } else {
+                (speed + decel).min(0.)
+            };
+            steer *= HANDBRAKE_STEER_GRIP;
+            lateral *= (-HANDBRAKE_LATERAL_GRIP * dt).exp();
+        }
+
+        // A non moving car can't be steered, because the yaw rate scales with its speed. The faster
+        // the car, the less the wheels turn.
+        steer /= 1. + self.steer_speed_falloff * speed.abs();
+        let wheelbase = self.dist_front_axle + self.dist_rear_axle;
+        let yaw_rate = speed / wheelbase * (steer * MAX_STEER_ANGLE).tan();
+        let mut yaw_delta = -yaw_rate * dt;
+
+        if handbrake {
+            if self.arcade_drift {
+                yaw_delta *= ARCADE_DRIFT_YAW;
+                self.is_drifting = true;
+            }
+            // The locked rear wheels slide, so the car keeps moving in its direction of travel
+            // while it turns. The sideways part of the velocity fades slowly.
+            state.rotation[2] += yaw_delta;
+            state.velocity = forward * speed + lateral;
+            state.position += state.velocity * dt;
+        } else {
+            // The tires turn the momentum of the car towards its new heading with the grip left from
+            // accelerating or braking. What they can't carry along is left as slip.
+            let velocity = forward * speed + lateral;
+            let new_forward = heading(state.rotation[2] + yaw_delta);
+            let along = velocity.dot(&new_forward);
+            let mut slip = velocity - new_forward * along;
+            // At the end of an arcade drift the tires bite at once and carry all of the slip along.
+            let grip = if mem::take(&mut self.is_drifting) {
+                f32::INFINITY
+            } else {
+                (max_grip * max_grip - traction * traction).max(0.).sqrt()
+            };
+            let slip_speed = slip.norm();
+            slip = if slip_speed <= grip {
+                zero()
+            } else {
+                slip * (1. - grip / slip_speed)
+            };
+            // The tires push sideways only, so they don't change the speed of the car.
+            let along = along.signum() * (velocity.norm_squared() - slip.norm_squared()).max(0.).sqrt();
+
+            // Move the rear axle along the arc (midpoint heading) and place the car in front of it,
+            // then let it slide. x,y-axis rotation are fixed to 0. No rollovers!
+            let rear_axle = state.position - forward * self.dist_rear_axle;
+            let rear_axle = rear_axle + heading(state.rotation[2] + yaw_delta * 0.5) * along * dt + slip * dt;
+            state.rotation[2] += yaw_delta;
+
+            state.velocity = new_forward * along + slip;
+            state.position = rear_axle + new_forward * self.dist_rear_axle;
+        }
+    }
+}
+
+/// Return the unit vector a car with a z-rotation of `yaw` [rad] is facing.
+///
+/// At a `yaw` of 0 the car faces along the y-axis.
+fn heading(yaw: f32) -> Vector3<f32> {
+    Vector3::new(-yaw.sin(), yaw.cos(), 0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        heading, resolve_collision, Car, CarBuilder, CarError, CarState, CarTuning, Obb, BOOST_DRAIN, BOOST_FADE, BOOST_REFILL,
+        BOOST_SPEED_FACTOR, MAX_BODY_TILT, MAX_BOOST, MAX_REVERSE_SPEED, MAX_STEER_ANGLE, REVERSE_THRESHOLD,
+    };
+    use crate::game::controller::{Controller, ControllerLayout};
+    use crate::grphx::{DirectionalLight, Model, Shader, Texture};
+    use nalgebra::{Matrix4, Point3, Vector3};
+    use std::cell::RefCell;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+    use std::mem;
+    use std::os::raw::c_void;
+
+    fn test_car() -> Car {
+        Car::test_car(Vector3::new(0., 0., 0.), 1000.0)
+    }
+
+    fn test_controller(x: f32, y: f32) -> Controller {
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        controller.set_axis(x, y);
+        controller
+    }
+
+    /// Count the updates until a car driving at 10 m/s stands still.
+    fn steps_to_stop(controller: Controller) -> usize {
+        let mut car = test_car();
+        car.velocity = Vector3::new(0., 10., 0.);
+        let mut steps = 0;
+        while car.velocity()[1] > REVERSE_THRESHOLD {
+            car.update(0.01, Some(controller));
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut car = test_car();
+        for _ in 0..50 {
+            car.update(0.01, Some(test_controller(0.5, 1.)));
+        }
+        let state = car.state();
+
+        let json = serde_json::to_string(&state).unwrap();
+        let restored: CarState = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, state);
+
+        let mut other = Car::test_car(Vector3::new(5., 5., 0.), 500.0);
+        other.set_state(&restored);
+        assert_eq!(other.state(), state);
+    }
+
+    #[test]
+    fn forward() {
+        let mut car = test_car();
+        assert_eq!(car.forward(), Vector3::new(0., 1., 0.));
+
+        car.rotation[2] = FRAC_PI_2;
+        assert!((car.forward() - Vector3::new(-1., 0., 0.)).norm() < 1e-6);
+        car.rotation[2] = -FRAC_PI_2;
+        assert!((car.forward() - Vector3::new(1., 0., 0.)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn speed() {
+        let mut car = test_car();
+        car.rotation[2] = FRAC_PI_2;
+        car.velocity = Vector3::new(-3., 4., 0.);
+        assert!((car.speed() - 3.).abs() < 1e-6);
+
+        // Reversing is a positive speed, too.
+        car.velocity = Vector3::new(5., 0., 0.);
+        assert!((car.speed() - 5.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reset() {
+        let mut car = test_car();
+        for _ in 0..100 {
+            car.update(0.01, Some(test_controller(1., 1.)));
+        }
+        assert!(car.rotation[2] != 0.);
+
+        let spawn = Vector3::new(1., 2., 0.5);
+        car.reset(spawn);
+
+        assert_eq!(car.position, spawn);
+        assert_eq!(car.rotation, Vector3::new(0., 0., 0.));
+        assert_eq!(car.velocity(), Vector3::new(0., 0., 0.));
+        assert_eq!(car.mass, 1000.0);
+    }
+
+    #[test]
+    fn collision_overlapping() {
+        let a = test_car();
+        let mut b = test_car();
+        b.position = Vector3::new(0.5, 0.5, 0.);
+        assert!(a.collides_with(&b));
+
+        b.position = Vector3::new(1.2, 0., 0.);
+        b.rotation[2] = FRAC_PI_4;
+        assert!(a.collides_with(&b));
+        assert!(b.collides_with(&a));
+    }
+
+    #[test]
+    fn collision_touching() {
+        let a = test_car();
+        let mut b = test_car();
+        b.position = Vector3::new(1.0, 0., 0.);
+        assert!(a.collides_with(&b));
+
+        b.position = Vector3::new(0., 2.0, 0.);
+        assert!(a.collides_with(&b));
+    }
+
+    #[test]
+    fn collision_separated() {
+        let a = test_car();
+        let mut b = test_car();
+        b.position = Vector3::new(3., 0., 0.);
+        assert!(!a.collides_with(&b));
+
+        // Rotated by 90° the long side of `b` faces `a`.
+        b.position = Vector3::new(1.6, 0., 0.);
+        b.rotation[2] = FRAC_PI_2;
+        assert!(!a.collides_with(&b));
+        assert!(!b.collides_with(&a));
+
+        // Rotated boxes which would overlap as axis aligned boxes.
+        b.position = Vector3::new(1.2, 1.9, 0.);
+        b.rotation[2] = -FRAC_PI_4;
+        assert!(!a.collides_with(&b));
+    }
+
+    #[test]
+    fn head_on_collision() {
+        let mut a = test_car();
+        let mut b = test_car();
+        a.velocity = Vector3::new(0., 10., 0.);
+        b.position = Vector3::new(0., 1.9, 0.);
+
+        let contact = resolve_collision(&mut a, &mut b).unwrap();
+
+        // Same masses with a restitution of 0.4 split the speed 3 to 7.
+        assert!(contact.impulse > 0.);
+        // The cars touch between their centers.
+        assert!((contact.point - (a.position + b.position) * 0.5).norm() < 1e-3);
+        assert!((a.velocity()[1] - 3.).abs() < 1e-3);
+        assert!((b.velocity()[1] - 7.).abs() < 1e-3);
+        assert!((a.velocity() + b.velocity() - Vector3::new(0., 10., 0.)).norm() < 1e-3);
+        if let Some((_, depth)) = a.penetration(&b) {
+            assert!(depth < 1e-5);
+        }
+
+        // A miss does not change anything.
+        b.position = Vector3::new(5., 0., 0.);
+        assert!(resolve_collision(&mut a, &mut b).is_none());
+    }
+
+    #[test]
+    fn stationary_car_does_not_rotate() {
+        let mut car = test_car();
+        for _ in 0..100 {
+            car.update(0.01, Some(test_controller(1., 0.)));
+        }
+
+        assert_eq!(car.rotation[2], 0.);
+        assert_eq!(car.position, Vector3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn slipstream() {
+        let mut leader = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        leader.velocity = Vector3::new(0., 30., 0.);
+        let follower = |x: f32, y: f32| {
+            let mut car = Car::test_car(Vector3::new(x, y, 0.), 1000.0);
+            car.velocity = Vector3::new(0., 30., 0.);
+            car
+        };
+
+        let (mut behind, mut beside) = (follower(0., -6.), follower(4., -6.));
+        behind.slipstream = behind.slipstream_behind(&leader);
+        beside.slipstream = beside.slipstream_behind(&leader);
+        assert!(behind.slipstream > 0.);
+        assert_eq!(beside.slipstream, 0.);
+        // The wake fades out to the sides and further back, and there is none ahead or at a crawl.
+        assert!(follower(0.8, -6.).slipstream_behind(&leader) < behind.slipstream);
+        assert!(follower(0., -15.).slipstream_behind(&leader) < behind.slipstream);
+        assert_eq!(follower(0., 6.).slipstream_behind(&leader), 0.);
+        leader.velocity = Vector3::new(0., 5., 0.);
+        assert_eq!(follower(0., -6.).slipstream_behind(&leader), 0.);
+
+        // Less drag keeps the car right behind faster.
+        for _ in 0..60 {
+            behind.update(1. / 60., None);
+            beside.update(1. / 60., None);
+        }
+        assert!(behind.speed() > beside.speed() + 0.1);
+    }
+
+    #[test]
+    fn full_steer_traces_circle() {
+        let mut car = test_car();
+        car.velocity = Vector3::new(0., 10., 0.);
+        car.drag_coefficient = 0.;
+        car.rolling_resistance = 0.;
+        car.engine_brake = 0.;
+        car.steer_speed_falloff = 0.;
+        // The tight circle needs more grip than any tire has.
+        car.tire_grip = f32::INFINITY;
+
+        // Turning right around the rear axle, the center of the circle is to the right of it.
+        let wheelbase = car.dist_front_axle + car.dist_rear_axle;
+        let radius = wheelbase / MAX_STEER_ANGLE.tan();
+        let center = Vector3::new(radius, -car.dist_rear_axle, 0.);
+
+        let dt = 0.001;
+        for _ in 0..2000 {
+            car.update(dt, Some(test_controller(1., 0.)));
+            let rear_axle = car.position - heading(car.rotation[2]) * car.dist_rear_axle;
+            assert!(((rear_axle - center).norm() - radius).abs() < 1e-2);
+        }
+        assert!((car.rotation[2] + 10. / radius * 2.).abs() < 1e-2);
+        assert!((car.velocity().norm() - 10.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn engine_brake() {
+        // Speed after coasting from 20 m/s for a second.
+        let coast = |engine_brake: f32, throttle: f32| {
+            let mut car = test_car();
+            car.drag_coefficient = 0.;
+            car.engine_brake = engine_brake;
+            car.velocity = Vector3::new(0., 20., 0.);
+            for _ in 0..100 {
+                car.update(0.01, Some(test_controller(0., throttle)));
+            }
+            car.speed()
+        };
+        let rolling = coast(0., 0.);
+        assert!(rolling < 20.);
+        assert!(coast(2., 0.) < rolling - 1.5);
+        // On the throttle the engine pulls instead.
+        assert_eq!(coast(2., 0.5), coast(0., 0.5));
+
+        // It holds back forward driving only, it stops at zero and doesn't act while reversing.
+        let mut car = test_car();
+        car.velocity = Vector3::new(0., 0.01, 0.);
+        car.update(0.01, Some(test_controller(0., 0.)));
+        assert_eq!(car.velocity(), Vector3::new(0., 0., 0.));
+        car.velocity = Vector3::new(0., -5., 0.);
+        car.rolling_resistance = 0.;
+        car.drag_coefficient = 0.;
+        car.update(0.01, Some(test_controller(0., 0.)));
+        assert!((car.velocity()[1] + 5.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn grip_limits_acceleration() {
+        // Speed after flooring the throttle from a standstill for half a second.
+        let launch = |tire_grip: f32| {
+            let mut car = test_car();
+            car.tire_grip = tire_grip;
+            for _ in 0..50 {
+                car.update(0.01, Some(test_controller(0., 1.)));
+            }
+            car.speed()
+        };
+        // The wheels spin on a slippery surface and the car gets away at the grip limit.
+        assert!(launch(3.) <= 3. * 0.5 + 1e-3);
+        assert!(launch(25.) > launch(3.) + 1.);
+    }
+
+    #[test]
+    fn slide_and_regain_grip() {
+        let mut car = test_car();
+        car.velocity = Vector3::new(0., 30., 0.);
+        let lateral = |car: &Car| (car.velocity() - car.forward() * car.velocity().dot(&car.forward())).norm();
+
+        // Yanking the wheel at speed needs more grip than the tires have.
+        for _ in 0..20 {
+            car.update(0.01, Some(test_controller(1., 0.)));
+        }
+        let slip = lateral(&car);
+        assert!(slip > 1.);
+
+        // Going straight again, the tires take the slip out at `tire_grip`.
+        car.update(0.1, Some(test_controller(0., 0.)));
+        assert!(lateral(&car) < slip);
+        assert!((slip - lateral(&car) - car.tire_grip * 0.1).abs() < 0.5);
+        for _ in 0..100 {
+            car.update(0.01, Some(test_controller(0., 0.)));
+        }
+        assert!(lateral(&car) < 1e-4);
+        assert!(car.slip_angle() < 1e-4);
+    }
+
+    #[test]
+    fn steering_falls_off_with_speed() {
+        // Yaw rate [rad/s] with full steering at `speed`.
+        let yaw_rate = |speed: f32, falloff: f32| {
+            let mut car = test_car();
+            car.steer_speed_falloff = falloff;
+            car.velocity = Vector3::new(0., speed, 0.);
+            car.update(0.01, Some(test_controller(1., 0.)));
+            -car.rotation[2] / 0.01
+        };
+
+        // Without falloff the yaw rate grows with the speed.
+        assert!((yaw_rate(30., 0.) / yaw_rate(5., 0.) - 6.).abs() < 0.05);
+
+        // With falloff the car turns on a wider circle at high speed.
+        let (slow, fast) = (yaw_rate(5., 0.05), yaw_rate(30., 0.05));
+        assert!(fast > slow);
+        assert!(fast / 30. < slow / 5. * 0.6);
+        assert!(yaw_rate(5., 0.05) < yaw_rate(5., 0.));
+    }
+
+    #[test]
+    fn terminal_speed() {
+        let mut car = test_car();
+
+        // At full throttle the engine force is balanced by drag and rolling resistance.
+        let (c_d, c_rr) = (car.drag_coefficient, car.rolling_resistance);
+        let expected = (-c_rr + (c_rr * c_rr + 4. * c_d * car.engine_force).sqrt()) / (2. * c_d);
+
+        for _ in 0..1000 {
+            car.update(0.01, Some(test_controller(0., 1.)));
+        }
+        let speed = car.velocity().norm();
+        for _ in 0..200 {
+            car.update(0.01, Some(test_controller(0., 1.)));
+        }
+
+        assert!((car.velocity().norm() - speed).abs() < 5e-2);
+        assert!((car.velocity().norm() - expected).abs() < expected * 0.01);
+    }
+
+    #[test]
+    fn max_speed() {
+        let mut car = test_car();
+        car.drag_coefficient = 0.;
+        car.rolling_resistance = 0.;
+        let mut controller = test_controller(0., 1.);
+        controller.set_boost(true);
+
+        for _ in 0..6000 {
+            car.update(0.01, Some(controller));
+            assert!(car.velocity()[1] <= car.max_speed * BOOST_SPEED_FACTOR + 1e-3);
+        }
+        // The boost is used up long ago.
+        assert!((car.velocity()[1] - car.max_speed).abs() < 1e-3);
+    }
+
+    #[test]
+    fn boost_drains_and_refills() {
+        let mut car = test_car();
+        let mut boost = test_controller(0., 1.);
+        boost.set_boost(true);
+
+        car.update(1., Some(boost));
+        assert!((car.boost - (MAX_BOOST - BOOST_DRAIN)).abs() < 1e-3);
+        car.update(1., Some(test_controller(0., 1.)));
+        assert!((car.boost - (MAX_BOOST - BOOST_DRAIN + BOOST_REFILL)).abs() < 1e-3);
+        for _ in 0..10 {
+            car.update(1., Some(test_controller(0., 0.)));
+        }
+        assert_eq!(car.boost_level(), 1.);
+
+        // With an empty meter boosting does nothing.
+        let mut car = test_car();
+        car.boost = 0.;
+        let mut plain = test_car();
+        plain.boost = 0.;
+        car.update(0.1, Some(boost));
+        plain.update(0.1, Some(test_controller(0., 1.)));
+        assert_eq!(car.velocity(), plain.velocity());
+        assert_eq!(car.boost, 0.);
+    }
+
+    #[test]
+    fn boost_raises_speed_cap() {
+        let mut car = test_car();
+        car.drag_coefficient = 0.;
+        car.rolling_resistance = 0.;
+        car.velocity = Vector3::new(0., car.max_speed, 0.);
+        let mut boost = test_controller(0., 1.);
+        boost.set_boost(true);
+
+        car.update(0.5, Some(boost));
+        assert!(car.speed() > car.max_speed);
+        // Without boost the car fades back to its maximum speed.
+        let boosted = car.speed();
+        car.update(0.1, Some(test_controller(0., 1.)));
+        assert!((boosted - car.speed() - BOOST_FADE * 0.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn lighter_car_accelerates_faster() {
+        let mut light = test_car();
+        let mut heavy = Car::test_car(Vector3::new(0., 0., 0.), 2000.0);
+        for _ in 0..200 {
+            light.update(0.01, Some(test_controller(0., 1.)));
+            heavy.update(0.01, Some(test_controller(0., 1.)));
+        }
+
+        assert!(light.position[1] > heavy.position[1]);
+        assert!(heavy.position[1] > 0.);
+    }
+
+    #[test]
+    fn coasting_car_stops() {
+        let mut car = test_car();
+        car.velocity = Vector3::new(0., 10., 0.);
+        for _ in 0..3000 {
+            car.update(0.01, None);
+        }
+
+        assert!(car.velocity().norm() < 1e-2);
+    }
+
+    #[test]
+    fn brake_before_reverse() {
+        let mut car = test_car();
+        car.velocity = Vector3::new(0., 10., 0.);
+
+        let mut last_speed = 10.;
+        let mut braked = false;
+        for _ in 0..500 {
+            car.update(0.01, Some(test_controller(0., -1.)));
+            let speed = car.velocity()[1];
+            if speed > 0. {
+                assert!(speed < last_speed);
+                braked = true;
+            } else {
+                assert!(braked);
+            }
+            assert!(speed >= -MAX_REVERSE_SPEED);
+            last_speed = speed;
+        }
+        assert!(last_speed < 0.);
+        assert!(car.position[1] < 5.);
+    }
+
+    #[test]
+    fn handbrake_stops_faster_than_brake() {
+        let mut handbrake = test_controller(0., 0.);
+        handbrake.set_handbrake(true);
+
+        assert!(steps_to_stop(handbrake) < steps_to_stop(test_controller(0., -1.)));
+    }
+
+    #[test]
+    fn damage_reduces_top_speed() {
+        // Speed after flooring the throttle for 20s.
+        let top_speed = |car: &mut Car| {
+            car.velocity = Vector3::new(0., 0., 0.);
+            for _ in 0..2000 {
+                car.update(0.01, Some(test_controller(0., 1.)));
+            }
+            car.speed()
+        };
+        let mut car = test_car();
+        let mut last = top_speed(&mut car);
+        // Nudges don't hurt.
+        car.apply_impact(car.damage_model.min_impulse);
+        assert_eq!(car.damage, 0.);
+
+        for _ in 0..3 {
+            car.apply_impact(10_000.);
+            let speed = top_speed(&mut car);
+            assert!(speed < last - 0.1);
+            last = speed;
+        }
+        assert!(!car.is_disabled());
+
+        // A wreck doesn't move on its own anymore.
+        for _ in 0..10 {
+            car.apply_impact(10_000.);
+        }
+        assert!(car.is_disabled());
+        assert_eq!(car.damage_level(), 1.);
+        assert_eq!(top_speed(&mut car), 0.);
+    }
+
+    #[test]
+    fn arcade_drift() {
+        // Yaw and velocity after turning with the handbrake at 20 m/s for half a second.
+        let drift = |arcade_drift: bool| {
+            let mut car = test_car();
+            car.arcade_drift = arcade_drift;
+            car.velocity = Vector3::new(0., 20., 0.);
+            let mut controller = test_controller(1., 0.);
+            controller.set_handbrake(true);
+            for _ in 0..50 {
+                car.update(0.01, Some(controller));
+            }
+            car
+        };
+        let sim = drift(false);
+        let mut arcade = drift(true);
+        assert!(arcade.rotation[2] < sim.rotation[2] - 0.1);
+        assert!(sim.rotation[2] < 0.);
+
+        // Releasing the handbrake sends the car off in the direction it faces without losing speed.
+        let speed = arcade.velocity().norm();
+        assert!((arcade.velocity() - arcade.forward() * arcade.speed()).norm() > 1.);
+        arcade.update(0.01, Some(test_controller(0., 0.)));
+        assert!((arcade.velocity() - arcade.forward() * arcade.speed()).norm() < 1e-3);
+        assert!((arcade.velocity().norm() - speed).abs() < 0.5);
+    }
+
+    #[test]
+    fn build_tuned_car() {
+        let car = CarBuilder::new(Vector3::new(1., 2., 0.), 800.)
+            .half_extents(Vector3::new(0.6, 1.5, 0.4))
+            .axles(1.2, 0.9)
+            .engine_force(12_000.)
+            .max_speed(55.)
+            .drag_coefficient(15.)
+            .arcade_drift(true)
+            .build()
+            .unwrap();
+        assert_eq!(car.position, Vector3::new(1., 2., 0.));
+        assert_eq!(car.state().mass, 800.);
+        assert_eq!(*car.cuboid.half_extents(), Vector3::new(0.6, 1.5, 0.4));
+        assert_eq!((car.dist_front_axle, car.dist_rear_axle), (1.2, 0.9));
+        assert_eq!(car.engine_force, 12_000.);
+        assert_eq!(car.max_speed, 55.);
+        assert_eq!(car.drag_coefficient, 15.);
+        assert!(car.arcade_drift);
+
+        // Untouched parameters keep their defaults.
+        let default = Car::with_half_extents(Vector3::new(0.6, 1.5, 0.4), Vector3::new(1., 2., 0.), 800.);
+        assert_eq!(car.rolling_resistance, default.rolling_resistance);
+        assert_eq!(car.restitution, default.restitution);
+    }
+
+    #[test]
+    fn apply_tuning() {
+        let tuning = CarTuning {
+            axles: Some((1.1, 0.8)),
+            max_speed: Some(40.),
+            arcade_drift: true,
+            ..Default::default()
+        };
+        let car = tuning.apply(CarBuilder::new(Vector3::new(0., 0., 0.), 1000.)).build().unwrap();
+        let default = test_car();
+        assert_eq!((car.dist_front_axle, car.dist_rear_axle), (1.1, 0.8));
+        assert_eq!(car.max_speed, 40.);
+        assert_eq!(car.engine_force, default.engine_force);
+        assert_eq!(*car.cuboid.half_extents(), *default.cuboid.half_extents());
+        assert!(car.arcade_drift);
+
+        let invalid = CarTuning {
+            axles: Some((1., 0.)),
+            ..Default::default()
+        };
+        assert!(invalid.apply(CarBuilder::new(Vector3::new(0., 0., 0.), 1000.)).build().is_err());
+    }
+
+    #[test]
+    fn reject_invalid_axles() {
+        let build = |front, rear| CarBuilder::new(Vector3::new(0., 0., 0.), 1000.).axles(front, rear).build();
+        assert_eq!(build(0., 1.).err(), Some(CarError::Axles(0., 1.)));
+        assert_eq!(build(1., -0.5).err(), Some(CarError::Axles(1., -0.5)));
+        assert!(build(f32::NAN, 1.).is_err());
+        assert!(build(1., 1.).is_ok());
+    }
+
+    #[test]
+    fn isometry() {
+        let mut car = test_car();
+        car.position = Vector3::new(2., -3., 0.5);
+        car.rotation[2] = 2.5;
+        let by_hand = Matrix4::new_translation(&car.position) * Matrix4::from_euler_angles(0., 0., car.rotation[2]);
+        assert!((car.isometry().to_homogeneous() - by_hand).norm() < 1e-6);
+
+        // The local y-axis is the direction the car faces.
+        let forward = car.isometry() * Vector3::y();
+        assert!((forward - car.forward()).norm() < 1e-6);
+        let front = car.isometry() * Point3::new(0., 1., 0.);
+        assert!((front.coords - car.position - car.forward()).norm() < 1e-6);
+    }
+
+    #[test]
+    fn interpolated_pose() {
+        let mut car = test_car();
+        // Nothing to interpolate from before the first update.
+        assert_eq!(car.interpolated_pose(0.5), car.isometry());
+
+        car.velocity = Vector3::new(0., 10., 0.);
+        car.update(0.01, Some(test_controller(1., 0.)));
+        let (first, first_yaw) = (car.position, car.rotation[2]);
+        car.update(0.01, Some(test_controller(1., 0.)));
+        assert!(car.rotation[2] < first_yaw);
+
+        let pose = car.interpolated_pose(0.5);
+        assert!((pose.translation.vector - (first + car.position) * 0.5).norm() < 1e-6);
+        assert!((pose.rotation.angle() - (first_yaw + car.rotation[2]).abs() * 0.5).abs() < 1e-6);
+        assert_eq!(car.interpolated_pose(1.), car.isometry());
+
+        // Teleporting doesn't interpolate.
+        car.reset(Vector3::new(20., 0., 0.));
+        assert_eq!(car.interpolated_pose(0.5), car.isometry());
+    }
+
+    #[test]
+    fn body_tilt() {
+        // Driving straight doesn't roll the body, but the nose rises while speeding up.
+        let mut car = test_car();
+        for _ in 0..100 {
+            car.update(0.01, Some(test_controller(0., 1.)));
+        }
+        assert!(car.visual_roll().abs() < 1e-6);
+        assert!(car.visual_pitch() > 0.);
+        let state = car.state();
+
+        // The body leans out of a hard right turn, within the limit.
+        for _ in 0..50 {
+            car.update(0.01, Some(test_controller(1., 1.)));
+        }
+        assert!(car.visual_roll() < -0.01);
+        assert!(car.visual_roll() >= -MAX_BODY_TILT);
+        assert_eq!(car.position[2], 0.);
+
+        // Braking dives the nose, teleporting levels the body.
+        car.set_state(&state);
+        assert_eq!((car.visual_roll(), car.visual_pitch()), (0., 0.));
+        for _ in 0..50 {
+            car.update(0.01, Some(test_controller(0., -1.)));
+        }
+        assert!(car.visual_pitch() < 0.);
+    }
+
+    #[test]
+    fn wheel_positions() {
+        let mut car = test_car();
+        car.position = Vector3::new(1., 2., 0.5);
+        let [front_left, front_right, rear_left, rear_right] = car.wheel_positions();
+        let wheelbase = car.dist_front_axle + car.dist_rear_axle;
+        assert!((front_left - rear_left - Vector3::new(0., wheelbase, 0.)).norm() < 1e-6);
+        assert!((front_right - rear_right - Vector3::new(0., wheelbase, 0.)).norm() < 1e-6);
+        assert!((front_right - front_left - Vector3::new(car.track_width, 0., 0.)).norm() < 1e-6);
+        assert_eq!(front_left[2], 0.5);
+
+        // The wheels turn with the car.
+        car.rotation[2] = FRAC_PI_2;
+        let [front_left, _, rear_left, rear_right] = car.wheel_positions();
+        assert!((front_left - rear_left - Vector3::new(-wheelbase, 0., 0.)).norm() < 1e-6);
+        assert_eq!(car.rear_wheels(), [rear_left, rear_right]);
+    }
+
+    #[test]
+    fn color_tint() {
+        let mut car = test_car();
+        assert_eq!(car.color(), Vector3::new(1., 1., 1.));
+        car.set_color(Vector3::new(1., 0.2, 0.2));
+        assert_eq!(car.color(), Vector3::new(1., 0.2, 0.2));
+        // Respawning keeps the color.
+        car.reset(Vector3::new(0., 0., 0.));
+        assert_eq!(car.color(), Vector3::new(1., 0.2, 0.2));
+    }
+
+    thread_local! {
+        /// Location and value of every vector uniform the fake GL set on this thread.
+        static UNIFORMS: RefCell<Vec<(i32, [f32; 3])>> = const { RefCell::new(Vec::new()) };
+    }
+
+    extern "system" fn uniform_vec3(location: i32, _count: i32, value: *const f32) {
+        let value = unsafe { [*value, *value.add(1), *value.add(2)] };
+        UNIFORMS.with(|uniforms| uniforms.borrow_mut().push((location, value)));
+    }
+
+    extern "system" fn ignore() {}
+
+    #[test]
+    fn draw_tint() {
+        // Only the vector uniforms are looked at, the other calls of a draw do nothing.
+        gl::Uniform3fv::load_with(|_| uniform_vec3 as *const c_void);
+        gl::UseProgram::load_with(|_| ignore as *const c_void);
+        gl::ActiveTexture::load_with(|_| ignore as *const c_void);
+        gl::BindTexture::load_with(|_| ignore as *const c_void);
+        gl::UniformMatrix3fv::load_with(|_| ignore as *const c_void);
+        gl::UniformMatrix4fv::load_with(|_| ignore as *const c_void);
+        gl::Uniform1f::load_with(|_| ignore as *const c_void);
+
+        let mut car = test_car();
+        car.model = Some(Model {
+            meshes: Vec::new(),
+            shader: Shader::with_id(1),
+            instanced_shader: None,
+            texture: Texture { id: 3 },
+        });
+        car.set_color(Vector3::new(0.2, 0.4, 1.));
+        car.draw(&Matrix4::identity(), &Matrix4::identity(), &DirectionalLight::default(), 1.);
+        let uniforms = UNIFORMS.with(|uniforms| uniforms.borrow().clone());
+        assert!(uniforms.contains(&(10, [0.2, 0.4, 1.])));
+
+        // Dropping the model would delete its GL handles, which the fake GL doesn't provide.
+        mem::forget(car.model.take());
+    }
+
+    #[test]
+    fn looks_alike() {
+        let files = Some((Car::model_from_id(3), Car::color_from_id(6)));
+        let mut car = test_car();
+        let mut other = test_car();
+        // Cars without a model have nothing to draw together.
+        assert!(!car.looks_like(&other));
+
+        car.model_files = files.clone();
+        other.model_files = files;
+        assert!(car.looks_like(&other));
+        other.set_color(Vector3::new(1., 0.3, 0.3));
+        assert!(!car.looks_like(&other));
+    }
+
+    #[test]
+    fn bounding_box() {
+        let mut car = test_car();
+        car.position = Vector3::new(2., 3., 0.5);
+        let obb = car.bounding_box();
+        assert_eq!(
+            obb,
+            Obb {
+                center: Vector3::new(2., 3., 0.5),
+                half_extents: Vector3::new(0.5, 1.0, 0.3),
+                rotation: 0.,
+            }
+        );
+        assert!((obb.world_half_extents() - Vector3::new(0.5, 1.0, 0.3)).norm() < 1e-6);
+
+        // Turned sideways, the car is wider than long in world space.
+        car.rotation[2] = FRAC_PI_2;
+        let obb = car.bounding_box();
+        assert!((obb.world_half_extents() - Vector3::new(1.0, 0.5, 0.3)).norm() < 1e-6);
+        assert!((obb.axes()[1] - Vector3::new(-1., 0., 0.)).norm() < 1e-6);
+    }
+}
@@ -14,11 +14,23 @@
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
 use imgui_glfw_rs::glfw;
 
-use self::ControllerInternal as CI;
 use self::ControllerLayout as CL;
+use enum_primitive::FromPrimitive;
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
 use glfw::{Action, Key, Window};
-use log::debug;
-use nalgebra::{zero, Vector2};
+use log::{debug, warn};
+use nalgebra::{clamp, zero, Vector2};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::mem;
+
+/// Stick and trigger values below this magnitude read as zero, so stick drift doesn't move the car.
+const GAMEPAD_DEADZONE: f32 = 0.15;
+
+/// Gamepad button that boosts.
+const GAMEPAD_BOOST: Button = Button::South;
+/// Gamepad button that pulls the handbrake.
+const GAMEPAD_HANDBRAKE: Button = Button::East;
 
 /// Control with WASD or arrow keys.
 #[derive(Debug)]
@@ -27,50 +39,136 @@ pub enum ControllerLayout {
     Arrows,
 }
 
+/// Keys bound to the actions of a controller.
+///
+/// Keys are stored by their glfw name, e.g. `"W"`, `"Up"` or `"LeftShift"`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    #[serde(with = "key_name")]
+    pub accelerate: Key,
+    #[serde(with = "key_name")]
+    pub brake: Key,
+    #[serde(with = "key_name")]
+    pub steer_left: Key,
+    #[serde(with = "key_name")]
+    pub steer_right: Key,
+    #[serde(with = "key_name")]
+    pub boost: Key,
+    #[serde(with = "key_name")]
+    pub handbrake: Key,
+}
+
+impl KeyBindings {
+    /// Default bindings for a layout.
+    pub fn new(controller_layout: &ControllerLayout) -> KeyBindings {
+        match controller_layout {
+            CL::WASD => KeyBindings {
+                accelerate: Key::W,
+                brake: Key::S,
+                steer_left: Key::A,
+                steer_right: Key::D,
+                boost: Key::LeftShift,
+                handbrake: Key::Space,
+            },
+            CL::Arrows => KeyBindings {
+                accelerate: Key::Up,
+                brake: Key::Down,
+                steer_left: Key::Left,
+                steer_right: Key::Right,
+                boost: Key::RightShift,
+                handbrake: Key::RightControl,
+            },
+        }
+    }
+
+    /// Load the bindings of a player from the `[player]` table of a toml file.
+    ///
+    /// Actions that aren't mapped in the file keep their binding from `defaults`. If the file is missing
+    /// or broken, `defaults` is returned as is.
+    pub fn load(file: &str, player: &str, defaults: KeyBindings) -> KeyBindings {
+        let content = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(_) => return defaults,
+        };
+        match Self::from_toml(&content, player, defaults) {
+            Ok(key_bindings) => key_bindings,
+            Err(e) => {
+                warn!("Could not load key bindings for {} from {}: {}", player, file, e);
+                defaults
+            }
+        }
+    }
+
+    /// Parse the bindings of a player, falling back to `defaults` for each unmapped action.
+    fn from_toml(content: &str, player: &str, defaults: KeyBindings) -> Result<KeyBindings, toml::de::Error> {
+        let file: toml::Value = toml::from_str(content)?;
+        let mut merged = toml::Value::try_from(defaults).expect("Key bindings are always serializable");
+        if let (Some(table), Some(mapped)) = (merged.as_table_mut(), file.get(player).and_then(|v| v.as_table())) {
+            for (action, key) in mapped {
+                table.insert(action.clone(), key.clone());
+            }
+        }
+        merged.try_into()
+    }
+}
+
+/// Serialize a glfw key by the name of its variant, since glfw doesn't implement serde itself.
+mod key_name {
+    use super::{FromPrimitive, Key};
+    use serde::de::{Deserialize, Deserializer, Error};
+    use serde::ser::Serializer;
+
+    // Range of the glfw key codes, see `glfw::Key`.
+    const KEY_CODES: std::ops::RangeInclusive<i32> = -1..=348;
+
+    pub fn serialize<S: Serializer>(key: &Key, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", key))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        KEY_CODES
+            .filter_map(Key::from_i32)
+            .find(|key| format!("{:?}", key) == name)
+            .ok_or_else(|| D::Error::custom(format!("unknown key {}", name)))
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 struct ControllerInternal {
-    forward: Key,
+    keys: KeyBindings,
+
     is_forward: bool,
-    backward: Key,
     is_backward: bool,
-    left: Key,
     is_left: bool,
-    right: Key,
     is_right: bool,
-
-    boost: Key,
     is_boost: bool,
+    is_handbrake: bool,
+    /// Set by a key or button press, until the next step takes it, so taps between two steps aren't lost.
+    boost_tap: bool,
+    handbrake_tap: bool,
+
+    /// Position of the gamepad among the connected ones.
+    gamepad: usize,
 }
 
 impl ControllerInternal {
     pub fn new(controller_layout: &ControllerLayout) -> ControllerInternal {
-        match controller_layout {
-            CL::WASD => Default::default(),
-            CL::Arrows => CI {
-                forward: Key::Up,
-                backward: Key::Down,
-                left: Key::Left,
-                right: Key::Right,
-                boost: Key::RightShift,
-                ..Default::default()
-            },
-        }
-    }
-}
-
-impl Default for ControllerInternal {
-    fn default() -> ControllerInternal {
+        let gamepad = match controller_layout {
+            CL::WASD => 0,
+            CL::Arrows => 1,
+        };
         ControllerInternal {
-            forward: Key::W,
+            keys: KeyBindings::new(controller_layout),
             is_forward: false,
-            backward: Key::S,
             is_backward: false,
-            left: Key::A,
             is_left: false,
-            right: Key::D,
             is_right: false,
-            boost: Key::LeftShift,
             is_boost: false,
+            is_handbrake: false,
+            boost_tap: false,
+            handbrake_tap: false,
+            gamepad,
         }
     }
 }
@@ -78,6 +176,10 @@ impl Default for ControllerInternal {
 /// Prototype controller emulation for keyboard users.
 #[derive(Copy, Clone, Debug)]
 pub struct Controller {
+    /// Rate [1/s] at which a smooth axis ramps towards its raw input, so a full
+    /// deflection takes `1.0 / ramp_rate` seconds.
+    pub ramp_rate: f32,
+
     /// Internal settings and flags.
     is_smooth: bool,
     ci: ControllerInternal,
@@ -86,6 +188,10 @@ pub struct Controller {
     /// Buttons and input axis that can be used in the game.
     axis: Vector2<f32>,
     boost: bool,
+    handbrake: bool,
+    /// A tap of the button between the last two steps, they count as pressed for one step.
+    is_boost_tapped: bool,
+    is_handbrake_tapped: bool,
 }
 
 impl Controller {
@@ -94,63 +200,153 @@ impl Controller {
     pub fn new(smooth: bool, controller_layout: &ControllerLayout) -> Controller {
         debug!("New smooth: {}, layout: {:?}", smooth, controller_layout);
         Controller {
+            ramp_rate: 5.,
             is_smooth: smooth,
             ci: ControllerInternal::new(&controller_layout),
             axis_goal: zero(),
             axis: zero(),
             boost: false,
+            handbrake: false,
+            is_boost_tapped: false,
+            is_handbrake_tapped: false,
         }
     }
 
-    /// Process input keys for this controller.
+    /// Note a key event of the window, before `process_input()`.
     ///
-    /// The input is handled from glfw::Window due to event polling.
-    pub fn process_input(&mut self, window: &Window, dt: f32) {
-        if window.get_key(self.ci.forward) == Action::Press && !self.ci.is_forward {
+    /// The boost and handbrake keys are latched on press, so the next step sees a tap that was
+    /// released before the keys are polled.
+    pub fn handle_key(&mut self, key: Key, action: Action) {
+        if action != Action::Press {
+            return;
+        }
+        if key == self.ci.keys.boost {
+            self.ci.boost_tap = true;
+        }
+        if key == self.ci.keys.handbrake {
+            self.ci.handbrake_tap = true;
+        }
+    }
+
+    /// Note a button press of the gamepad, before `process_input()`.
+    ///
+    /// The boost and handbrake buttons are latched like their keys.
+    pub fn handle_button(&mut self, button: Button) {
+        if button == GAMEPAD_BOOST {
+            self.ci.boost_tap = true;
+        }
+        if button == GAMEPAD_HANDBRAKE {
+            self.ci.handbrake_tap = true;
+        }
+    }
+
+    /// Process input for this controller.
+    ///
+    /// The input is read from a gamepad if one is connected, otherwise the keys are
+    /// handled from glfw::Window due to event polling. Call `update()` afterwards to
+    /// move the axis values towards the new input.
+    pub fn process_input(&mut self, window: &Window, gamepads: &Gamepads) {
+        match gamepads.state(self.ci.gamepad) {
+            Some(state) => self.process_gamepad(&state),
+            None => self.process_keys(window),
+        }
+    }
+
+    /// Move the axis values towards the raw input for a time step `dt`.
+    ///
+    /// With smooth axis interpolation each axis ramps linearly with `ramp_rate`,
+    /// otherwise it snaps to the input. Buttons tapped since the last step are pressed for this one.
+    pub fn update(&mut self, dt: f32) {
+        self.is_boost_tapped = mem::take(&mut self.ci.boost_tap);
+        self.is_handbrake_tapped = mem::take(&mut self.ci.handbrake_tap);
+        if self.is_smooth {
+            let max_step = self.ramp_rate * dt;
+            for i in 0..2 {
+                let delta = self.axis_goal[i] - self.axis[i];
+                self.axis[i] += clamp(delta, -max_step, max_step);
+            }
+        } else {
+            self.axis = self.axis_goal;
+        }
+    }
+
+    /// Apply the analog sticks, triggers and buttons of a gamepad.
+    ///
+    /// Analog input is smooth by itself, so it is not interpolated.
+    fn process_gamepad(&mut self, state: &GamepadState) {
+        self.axis_goal = Vector2::new(state.steer, state.throttle - state.brake);
+        self.axis = self.axis_goal;
+        self.boost = state.boost;
+        self.handbrake = state.handbrake;
+    }
+
+    /// Process input keys for this controller.
+    fn process_keys(&mut self, window: &Window) {
+        self.apply_keys(|key| window.get_key(key));
+    }
+
+    /// Update the input from the state of the bound keys, as reported by `get_key`.
+    fn apply_keys<F: Fn(Key) -> Action>(&mut self, get_key: F) {
+        let keys = self.ci.keys;
+        if get_key(keys.accelerate) == Action::Press && !self.ci.is_forward {
             self.set_y_axis(1.);
             self.ci.is_forward = true;
-        } else if window.get_key(self.ci.forward) == Action::Release && self.ci.is_forward {
+        } else if get_key(keys.accelerate) == Action::Release && self.ci.is_forward {
             self.set_y_axis(0.);
             self.ci.is_forward = false;
         }
-        if window.get_key(self.ci.backward) == Action::Press && !self.ci.is_backward {
+        if get_key(keys.brake) == Action::Press && !self.ci.is_backward {
             self.set_y_axis(-1.);
             self.ci.is_backward = true;
-        } else if window.get_key(self.ci.backward) == Action::Release && self.ci.is_backward {
+        } else if get_key(keys.brake) == Action::Release && self.ci.is_backward {
             self.set_y_axis(0.);
             self.ci.is_backward = false;
         }
-        if window.get_key(self.ci.left) == Action::Press && !self.ci.is_left {
+        if get_key(keys.steer_left) == Action::Press && !self.ci.is_left {
             self.set_x_axis(-1.);
             self.ci.is_left = true;
-        } else if window.get_key(self.ci.left) == Action::Release && self.ci.is_left {
+        } else if get_key(keys.steer_left) == Action::Release && self.ci.is_left {
             self.set_x_axis(0.);
             self.ci.is_left = false;
         }
-        if window.get_key(self.ci.right) == Action::Press && !self.ci.is_right {
+        if get_key(keys.steer_right) == Action::Press && !self.ci.is_right {
             self.set_x_axis(1.);
             self.ci.is_right = true;
-        } else if window.get_key(self.ci.right) == Action::Release && self.ci.is_right {
+        } else if get_key(keys.steer_right) == Action::Release && self.ci.is_right {
             self.set_x_axis(0.);
             self.ci.is_right = false;
         }
-        if window.get_key(self.ci.boost) == Action::Press && !self.ci.is_boost {
+        if get_key(keys.boost) == Action::Press && !self.ci.is_boost {
             self.boost = true;
             self.ci.is_boost = true;
-        } else if window.get_key(self.ci.boost) == Action::Release && self.ci.is_boost {
+        } else if get_key(keys.boost) == Action::Release && self.ci.is_boost {
             self.boost = false;
             self.ci.is_boost = false;
         }
-
-        if self.is_smooth {
-            self.axis = Vector2::lerp(&self.axis, &self.axis_goal, 5. * dt);
-            self.axis[0] = (self.axis[0] * 10_000.).trunc() / 10_000.;
-            self.axis[1] = (self.axis[1] * 10_000.).trunc() / 10_000.;
-        } else {
-            self.axis = self.axis_goal;
+        if get_key(keys.handbrake) == Action::Press && !self.ci.is_handbrake {
+            self.handbrake = true;
+            self.ci.is_handbrake = true;
+        } else if get_key(keys.handbrake) == Action::Release && self.ci.is_handbrake {
+            self.handbrake = false;
+            self.ci.is_handbrake = false;
         }
     }
 
+    /// Return the keys bound to this controller.
+    pub fn key_bindings(&self) -> KeyBindings {
+        self.ci.keys
+    }
+
+    /// Read the input from another gamepad, by its position among the connected ones.
+    pub fn set_gamepad(&mut self, gamepad: usize) {
+        self.ci.gamepad = gamepad;
+    }
+
+    /// Bind other keys to this controller.
+    pub fn set_key_bindings(&mut self, key_bindings: KeyBindings) {
+        self.ci.keys = key_bindings;
+    }
+
     /// Return the x axis value, clamped between [-1.0f32; 1.0f32].
     pub fn get_x_axis(&self) -> f32 {
         self.axis[0]
@@ -161,9 +357,31 @@ impl Controller {
         self.axis[1]
     }
 
-    /// Return true if the boost button is pressed.
+    /// Return true if the boost button is pressed or was tapped since the last step.
     pub fn get_boost(&self) -> bool {
-        self.boost
+        self.boost || self.is_boost_tapped
+    }
+
+    /// Return true if the handbrake button is pressed or was tapped since the last step, which locks
+    /// the rear wheels.
+    pub fn get_handbrake(&self) -> bool {
+        self.handbrake || self.is_handbrake_tapped
+    }
+
+    /// Set both axis values instantly, bypassing the key input and smoothing.
+    pub fn set_axis(&mut self, x: f32, y: f32) {
+        self.axis_goal = Vector2::new(x, y);
+        self.axis = self.axis_goal;
+    }
+
+    /// Set the boost button state, bypassing the key input.
+    pub fn set_boost(&mut self, value: bool) {
+        self.boost = value;
+    }
+
+    /// Set the handbrake button state, bypassing the key input.
+    pub fn set_handbrake(&mut self, value: bool) {
+        self.handbrake = value;
     }
 
     /// Sets the x axis value to make `process_input` better readable.
@@ -176,3 +394,235 @@ impl Controller {
         self.axis_goal[1] = value;
     }
 }
+
+/// Input of a gamepad in the standard mapping, with the deadzone applied.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct GamepadState {
+    /// Left stick, from -1.0 (left) to 1.0 (right).
+    steer: f32,
+    /// Right trigger, from 0.0 to 1.0.
+    throttle: f32,
+    /// Left trigger, from 0.0 to 1.0.
+    brake: f32,
+    boost: bool,
+    handbrake: bool,
+}
+
+/// Gamepads connected to the computer, read through gilrs.
+///
+/// gilrs maps known gamepads to the layout of an Xbox gamepad, so the sticks, triggers
+/// and buttons are the same for every supported model.
+pub struct Gamepads {
+    /// `None` if gilrs could not be initialized, then only the keyboard is used.
+    gilrs: Option<Gilrs>,
+}
+
+impl Gamepads {
+    pub fn new() -> Gamepads {
+        let gilrs = Gilrs::new().map_err(|e| warn!("Could not read gamepads: {}", e)).ok();
+        Gamepads { gilrs }
+    }
+
+    /// Take the gamepad events since the last call, before the controllers process their input.
+    ///
+    /// Button presses are passed to the controllers reading the gamepad, so taps between
+    /// two polls aren't lost.
+    pub fn poll(&mut self, controllers: &mut [Controller]) {
+        let gilrs = match self.gilrs.as_mut() {
+            Some(gilrs) => gilrs,
+            None => return,
+        };
+        while let Some(event) = gilrs.next_event() {
+            if let EventType::ButtonPressed(button, _) = event.event {
+                let slot = connected(gilrs).position(|id| id == event.id);
+                for ctrl in controllers.iter_mut().filter(|ctrl| Some(ctrl.ci.gamepad) == slot) {
+                    ctrl.handle_button(button);
+                }
+            }
+        }
+    }
+
+    /// Return the input of the connected gamepad at position `slot`, `None` if there is none.
+    fn state(&self, slot: usize) -> Option<GamepadState> {
+        let gilrs = self.gilrs.as_ref()?;
+        let gamepad = gilrs.gamepad(connected(gilrs).nth(slot)?);
+        let trigger = |button| gamepad.button_data(button).map_or(0., |data| apply_deadzone(data.value()));
+        Some(GamepadState {
+            steer: apply_deadzone(gamepad.value(Axis::LeftStickX)),
+            throttle: trigger(Button::RightTrigger2),
+            brake: trigger(Button::LeftTrigger2),
+            boost: gamepad.is_pressed(GAMEPAD_BOOST),
+            handbrake: gamepad.is_pressed(GAMEPAD_HANDBRAKE),
+        })
+    }
+}
+
+/// Ids of the connected gamepads, in the order of their slots.
+fn connected(gilrs: &Gilrs) -> impl Iterator<Item = GamepadId> + '_ {
+    gilrs.gamepads().map(|(id, _)| id)
+}
+
+/// Map analog values inside the `GAMEPAD_DEADZONE` to zero and rescale the rest to [-1.0; 1.0].
+fn apply_deadzone(value: f32) -> f32 {
+    if value.abs() < GAMEPAD_DEADZONE {
+        0.
+    } else {
+        value.signum() * (value.abs() - GAMEPAD_DEADZONE) / (1. - GAMEPAD_DEADZONE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_deadzone, Controller, ControllerLayout, GamepadState, KeyBindings, GAMEPAD_DEADZONE};
+    use gilrs::Button;
+    use imgui_glfw_rs::glfw::{Action, Key};
+
+    #[test]
+    fn smooth_axis() {
+        let mut controller = Controller::new(true, &ControllerLayout::WASD);
+        controller.ramp_rate = 4.;
+
+        // A held key reaches the full value after 1 / ramp_rate = 0.25s.
+        controller.set_y_axis(1.);
+        for _ in 0..24 {
+            controller.update(0.01);
+            assert!(controller.get_y_axis() < 1.);
+        }
+        controller.update(0.01);
+        assert!((controller.get_y_axis() - 1.).abs() < 1e-5);
+        controller.update(0.01);
+        assert_eq!(controller.get_y_axis(), 1.);
+
+        // Releasing decays back to zero in the same time.
+        controller.set_y_axis(0.);
+        controller.update(0.125);
+        assert!((controller.get_y_axis() - 0.5).abs() < 1e-5);
+        controller.update(0.125);
+        assert_eq!(controller.get_y_axis(), 0.);
+        assert_eq!(controller.get_x_axis(), 0.);
+    }
+
+    #[test]
+    fn deadzone() {
+        assert_eq!(apply_deadzone(0.), 0.);
+        assert_eq!(apply_deadzone(GAMEPAD_DEADZONE * 0.9), 0.);
+        assert_eq!(apply_deadzone(-GAMEPAD_DEADZONE * 0.9), 0.);
+        assert_eq!(apply_deadzone(1.), 1.);
+        assert_eq!(apply_deadzone(-1.), -1.);
+        assert!(apply_deadzone(0.5) > 0. && apply_deadzone(0.5) < 0.5);
+    }
+
+    #[test]
+    fn key_bindings_round_trip() {
+        let key_bindings = KeyBindings {
+            accelerate: Key::I,
+            steer_left: Key::J,
+            ..KeyBindings::new(&ControllerLayout::WASD)
+        };
+        let content = toml::to_string(&key_bindings).unwrap();
+        assert!(content.contains("accelerate = \"I\""));
+        assert_eq!(toml::from_str::<KeyBindings>(&content).unwrap(), key_bindings);
+    }
+
+    #[test]
+    fn key_bindings_fall_back_to_defaults() {
+        let defaults = KeyBindings::new(&ControllerLayout::Arrows);
+        let content = "[player2]\naccelerate = \"Kp8\"\n";
+
+        let key_bindings = KeyBindings::from_toml(content, "player2", defaults).unwrap();
+        assert_eq!(key_bindings.accelerate, Key::Kp8);
+        assert_eq!(key_bindings.brake, Key::Down);
+        assert_eq!(KeyBindings::from_toml(content, "player1", defaults).unwrap(), defaults);
+        assert!(KeyBindings::from_toml("[player2]\nbrake = \"NoSuchKey\"\n", "player2", defaults).is_err());
+    }
+
+    #[test]
+    fn remapped_key_drives_axis() {
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        controller.set_key_bindings(KeyBindings {
+            accelerate: Key::I,
+            steer_right: Key::L,
+            ..controller.key_bindings()
+        });
+
+        let pressed = |keys: &'static [Key]| move |key| if keys.contains(&key) { Action::Press } else { Action::Release };
+        controller.apply_keys(pressed(&[Key::W, Key::D]));
+        controller.update(0.01);
+        assert_eq!(controller.get_y_axis(), 0.);
+        assert_eq!(controller.get_x_axis(), 0.);
+
+        controller.apply_keys(pressed(&[Key::I, Key::L]));
+        controller.update(0.01);
+        assert_eq!(controller.get_y_axis(), 1.);
+        assert_eq!(controller.get_x_axis(), 1.);
+    }
+
+    #[test]
+    fn tap_between_steps() {
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        let keys = controller.key_bindings();
+
+        // The boost key is pressed and released within one frame, polling only sees it released.
+        controller.handle_key(keys.boost, Action::Press);
+        controller.handle_key(keys.boost, Action::Release);
+        controller.apply_keys(|_| Action::Release);
+        assert!(!controller.get_boost());
+        controller.update(0.01);
+        assert!(controller.get_boost());
+        assert!(!controller.get_handbrake());
+
+        // It counts for a single step.
+        controller.update(0.01);
+        assert!(!controller.get_boost());
+
+        // Other keys aren't latched.
+        controller.handle_key(keys.accelerate, Action::Press);
+        controller.apply_keys(|_| Action::Release);
+        controller.update(0.01);
+        assert_eq!(controller.get_y_axis(), 0.);
+    }
+
+    #[test]
+    fn gamepad_drives_axis() {
+        let mut controller = Controller::new(true, &ControllerLayout::WASD);
+
+        // Released triggers read as zero, the stick isn't interpolated.
+        controller.process_gamepad(&GamepadState {
+            steer: -0.5,
+            ..GamepadState::default()
+        });
+        controller.update(0.01);
+        assert_eq!(controller.get_x_axis(), -0.5);
+        assert_eq!(controller.get_y_axis(), 0.);
+
+        controller.process_gamepad(&GamepadState {
+            throttle: 1.,
+            brake: 0.25,
+            boost: true,
+            ..GamepadState::default()
+        });
+        controller.update(0.01);
+        assert_eq!(controller.get_y_axis(), 0.75);
+        assert!(controller.get_boost());
+        assert!(!controller.get_handbrake());
+    }
+
+    #[test]
+    fn button_tap_between_steps() {
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+
+        // The button was released again before the gamepad is read.
+        controller.handle_button(Button::East);
+        controller.process_gamepad(&GamepadState::default());
+        controller.update(0.01);
+        assert!(controller.get_handbrake());
+        assert!(!controller.get_boost());
+
+        controller.update(0.01);
+        assert!(!controller.get_handbrake());
+
+        controller.handle_button(Button::North);
+        controller.update(0.01);
+        assert!(!controller.get_boost() && !controller.get_handbrake());
+    }
+}
@@ -0,0 +1,269 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::car::{Car, Obb};
+use super::spline::Spline;
+use super::track::TrackLayout;
+use crate::grphx::{Shader, ShaderError};
+use log::debug;
+use nalgebra::{Matrix4, Vector3};
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::ptr;
+
+/// Color of the bounding boxes of the cars.
+const CAR_COLOR: [f32; 3] = [1., 0.2, 0.2];
+/// Color of the track walls.
+const WALL_COLOR: [f32; 3] = [1., 1., 0.2];
+/// Color of the waypoints and the straight path between them.
+const WAYPOINT_COLOR: [f32; 3] = [0.2, 0.6, 1.];
+/// Color of the racing line through the waypoints.
+const SPLINE_COLOR: [f32; 3] = [0.2, 1., 0.4];
+
+/// Half size [m] of the cross marking a waypoint.
+const WAYPOINT_SIZE: f32 = 0.5;
+/// Number of straight lines a segment of the racing line is drawn with.
+const SPLINE_STEPS: usize = 8;
+
+/// End of a line with its color, as it is stored in the vertex buffer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+/// Lines to draw on top of the scene to see what the physics and the AI see.
+#[derive(Debug, Clone, Default)]
+pub struct DebugLines {
+    /// Both ends of every line, one after the other.
+    pub vertices: Vec<LineVertex>,
+}
+
+impl DebugLines {
+    pub fn new() -> DebugLines {
+        Default::default()
+    }
+
+    /// Collect the bounding boxes of the `cars`, the walls of the `layout`, its waypoints and the racing line through them.
+    pub fn from_scene<'a>(cars: impl IntoIterator<Item = &'a Car>, layout: Option<&TrackLayout>) -> DebugLines {
+        let mut lines = DebugLines::new();
+        for car in cars {
+            lines.add_obb(&car.bounding_box(), CAR_COLOR);
+        }
+        if let Some(layout) = layout {
+            for wall in &layout.track.walls {
+                lines.add_line(&wall.a, &wall.b, WALL_COLOR);
+            }
+            for waypoint in &layout.waypoints {
+                lines.add_line(
+                    &(waypoint - Vector3::x() * WAYPOINT_SIZE),
+                    &(waypoint + Vector3::x() * WAYPOINT_SIZE),
+                    WAYPOINT_COLOR,
+                );
+                lines.add_line(
+                    &(waypoint - Vector3::y() * WAYPOINT_SIZE),
+                    &(waypoint + Vector3::y() * WAYPOINT_SIZE),
+                    WAYPOINT_COLOR,
+                );
+            }
+            lines.add_loop(&layout.waypoints, WAYPOINT_COLOR);
+            let spline = Spline::new(layout.waypoints.clone());
+            let samples = (0..spline.len() * SPLINE_STEPS)
+                .map(|i| spline.sample(i as f32 / SPLINE_STEPS as f32))
+                .collect::<Vec<_>>();
+            lines.add_loop(&samples, SPLINE_COLOR);
+        }
+        lines
+    }
+
+    /// Add a line from `a` to `b`.
+    pub fn add_line(&mut self, a: &Vector3<f32>, b: &Vector3<f32>, color: [f32; 3]) {
+        self.vertices.push(LineVertex {
+            position: (*a).into(),
+            color,
+        });
+        self.vertices.push(LineVertex {
+            position: (*b).into(),
+            color,
+        });
+    }
+
+    /// Add lines from every point to the next one and from the last one back to the first one.
+    pub fn add_loop(&mut self, points: &[Vector3<f32>], color: [f32; 3]) {
+        for (i, a) in points.iter().enumerate() {
+            self.add_line(a, &points[(i + 1) % points.len()], color);
+        }
+    }
+
+    /// Add the edges of a bounding box.
+    pub fn add_obb(&mut self, obb: &Obb, color: [f32; 3]) {
+        for edge in obb_lines(obb).chunks(2) {
+            self.add_line(&edge[0], &edge[1], color);
+        }
+    }
+}
+
+/// Return both ends of the 12 edges of `obb`, one edge after the other.
+fn obb_lines(obb: &Obb) -> Vec<Vector3<f32>> {
+    let [x, y] = obb.axes();
+    let axes = [x, y, Vector3::z()];
+    // Bit `i` of a corner index is set, if the corner is on the positive side along axis `i`.
+    let corner = |index: usize| {
+        (0..3).fold(obb.center, |corner, i| {
+            let side = if index & (1 << i) == 0 { -1. } else { 1. };
+            corner + axes[i] * obb.half_extents[i] * side
+        })
+    };
+    let mut vertices = Vec::with_capacity(24);
+    for index in 0..8 {
+        for i in 0..3 {
+            // Every edge connects two corners differing along a single axis.
+            if index & (1 << i) == 0 {
+                vertices.push(corner(index));
+                vertices.push(corner(index | 1 << i));
+            }
+        }
+    }
+    vertices
+}
+
+/// Draws `DebugLines` unlit and on top of everything else.
+pub struct DebugLineRenderer {
+    vao: u32,
+    vbo: u32,
+    /// `None` if the shader failed to load, nothing is drawn then.
+    shader: Option<Shader>,
+}
+
+impl DebugLineRenderer {
+    pub fn new() -> DebugLineRenderer {
+        let mut renderer = DebugLineRenderer {
+            vao: 0,
+            vbo: 0,
+            shader: Shader::for_effect("lines", "lines", "Debug lines"),
+        };
+        unsafe {
+            gl::GenVertexArrays(1, &mut renderer.vao);
+            gl::BindVertexArray(renderer.vao);
+            gl::GenBuffers(1, &mut renderer.vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.vbo);
+
+            let size = size_of::<LineVertex>() as i32;
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size, ptr::null());
+            gl::EnableVertexAttribArray(1);
+            let offset = size_of::<[f32; 3]>() as *const c_void;
+            gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, size, offset);
+            gl::BindVertexArray(0);
+        }
+        debug!("DebugLineRenderer::new() : vao == {}, vbo == {}", renderer.vao, renderer.vbo);
+        renderer
+    }
+
+    /// Compile the shader again from its files, a shader that failed to load stays off.
+    pub fn reload_shader(&mut self) -> Result<(), ShaderError> {
+        match &mut self.shader {
+            Some(shader) => shader.reload(),
+            None => Ok(()),
+        }
+    }
+
+    /// Draw the `lines` to the bound framebuffer, over everything drawn before.
+    pub fn draw(&self, lines: &DebugLines, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        let shader = match &self.shader {
+            Some(shader) if !lines.vertices.is_empty() => shader,
+            _ => return,
+        };
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            shader.bind();
+            Shader::set_uniform_mat4(1, view);
+            Shader::set_uniform_mat4(2, projection);
+
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            let size = (lines.vertices.len() * size_of::<LineVertex>()) as isize;
+            let data = lines.vertices.as_ptr() as *const c_void;
+            gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STREAM_DRAW);
+            gl::DrawArrays(gl::LINES, 0, lines.vertices.len() as i32);
+            gl::BindVertexArray(0);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+}
+
+impl Drop for DebugLineRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{obb_lines, DebugLines};
+    use crate::game::car::Obb;
+    use crate::game::track::{Track, TrackLayout};
+    use nalgebra::Vector3;
+
+    #[test]
+    fn obb_edges() {
+        let obb = Obb {
+            center: Vector3::new(1., 2., 0.5),
+            half_extents: Vector3::new(0.5, 1.0, 0.3),
+            rotation: 0.3,
+        };
+        let vertices = obb_lines(&obb);
+        assert_eq!(vertices.len(), 24);
+
+        // Every edge runs along one axis of the box and is as long as the box along that axis.
+        let [x, y] = obb.axes();
+        for edge in vertices.chunks(2) {
+            let direction = edge[1] - edge[0];
+            let length = direction.norm();
+            assert!([1., 2., 0.6].iter().any(|size| (length - size).abs() < 1e-5));
+            let along = [x, y, Vector3::z()].iter().filter(|axis| axis.dot(&direction).abs() > 1e-5).count();
+            assert_eq!(along, 1);
+            // The corners are on the box.
+            for corner in edge {
+                assert!(((corner - obb.center)[2].abs() - 0.3).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn lines_of_a_track() {
+        let boundaries = vec![vec![
+            Vector3::new(0., 0., 0.),
+            Vector3::new(10., 0., 0.),
+            Vector3::new(10., 10., 0.),
+        ]];
+        let layout = TrackLayout {
+            track: Track::from_boundaries(&boundaries),
+            spawns: Vec::new(),
+            finish_line: None,
+            checkpoints: Vec::new(),
+            waypoints: boundaries[0].clone(),
+            slopes: Vec::new(),
+            surfaces: Vec::new(),
+            laps: None,
+        };
+        let lines = DebugLines::from_scene(&[], Some(&layout));
+        // Three walls, two lines per waypoint, the path and the racing line.
+        assert_eq!(lines.vertices.len(), 2 * (3 + 2 * 3 + 3 + 3 * super::SPLINE_STEPS));
+    }
+}
@@ -0,0 +1,87 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use crate::game::track::Spawn;
+use nalgebra::{Isometry3, Point3, Vector3};
+
+/// Two columns of cars behind the start, the right one half a row further back.
+///
+/// Neighbours are `lateral_spacing` apart from center to center and cars in the same column
+/// `row_spacing`, so cars up to that wide and half that long don't overlap at the start.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StartGrid {
+    /// Distance [m] between the centers of the two columns.
+    pub lateral_spacing: f32,
+    /// Distance [m] between two cars one behind the other in a column.
+    pub row_spacing: f32,
+}
+
+impl Default for StartGrid {
+    fn default() -> StartGrid {
+        StartGrid {
+            lateral_spacing: 2.3,
+            row_spacing: 3.4,
+        }
+    }
+}
+
+impl StartGrid {
+    /// Return the pose of the car in the `slot` of the grid behind the `start`, facing along it.
+    ///
+    /// Slot 0 is on pole to the left of the start, the slots behind alternate between right and left.
+    pub fn slot(&self, start: &Spawn, slot: usize) -> Isometry3<f32> {
+        let rotation = Vector3::z() * start.yaw;
+        let grid = Isometry3::new(start.position, rotation);
+        let side = if slot & 1 == 0 { -0.5 } else { 0.5 };
+        // Forward is +y and right is +x before the rotation of the start.
+        let offset = Vector3::new(side * self.lateral_spacing, -(slot as f32) * self.row_spacing / 2., 0.);
+        Isometry3::new((grid * Point3::from(offset)).coords, rotation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::car::Car;
+
+    #[test]
+    fn cars_dont_overlap() {
+        let start = Spawn {
+            position: Vector3::new(10., -5., 0.5),
+            yaw: 0.7,
+        };
+        let grid = StartGrid::default();
+        let cars: Vec<Car> = (0..8)
+            .map(|slot| {
+                let pose = grid.slot(&start, slot);
+                let mut car = Car::with_half_extents(Vector3::new(0.9, 1.6, 0.5), pose.translation.vector, 1000.0);
+                car.rotation[2] = pose.rotation.euler_angles().2;
+                car
+            })
+            .collect();
+
+        assert!((cars[0].position - start.position).norm() < grid.lateral_spacing);
+        for (i, car) in cars.iter().enumerate() {
+            // All facing along the start, each one further back than the one before.
+            assert!((car.rotation[2] - start.yaw).abs() < 1e-5);
+            if i > 0 {
+                let behind = (cars[i - 1].position - car.position).dot(&car.forward());
+                assert!((behind - grid.row_spacing / 2.).abs() < 1e-4);
+            }
+            for other in &cars[i + 1..] {
+                assert!(!car.collides_with(other));
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use crate::grphx::{DirectionalLight, Model, ShaderError};
+use log::debug;
+use nalgebra::{Matrix4, Vector3};
+use std::path::Path;
+
+/// Height [m] of the ground, just below the base of the race tracks.
+const GROUND_HEIGHT: f32 = -2.01;
+
+/// Large textured plane around the race track, so the scene doesn't float in the void.
+pub struct Ground {
+    model: Model,
+    matrix: Matrix4<f32>,
+}
+
+impl Ground {
+    /// Create a square ground with an edge length of `size` [m], covered with `texture` tiles
+    /// of `tile_size` [m].
+    pub fn new(size: f32, tile_size: f32, texture: &str) -> Ground {
+        debug!("New with size {}m and tiles of {}m", size, tile_size);
+        let (positions, uvs) = ground_quad(size, tile_size);
+        let mut model = Model::from_vertices(&positions, &[[0., 0., 1.]; 4], &uvs, &[0, 1, 2, 0, 2, 3])
+            .unwrap_or_else(|e| panic!("Failed to create the ground: {}", e));
+        let texture_str = format!("{}{}", "res/textures/", texture);
+        model.set_texture(Path::new(&texture_str)).expect("ERROR: Failed to load texture!");
+
+        Ground {
+            model,
+            matrix: Matrix4::new_translation(&Vector3::new(0., 0., GROUND_HEIGHT)),
+        }
+    }
+
+    /// Compile the shaders of the ground again from their files.
+    pub fn reload_shaders(&mut self) -> Result<(), ShaderError> {
+        self.model.reload_shaders()
+    }
+
+    pub fn draw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>, light: &DirectionalLight) {
+        self.model.draw(&self.matrix, view, projection, light);
+    }
+}
+
+/// Return the corner positions and texture coordinates of a square of `size` centered at the origin,
+/// counterclockwise from below.
+///
+/// The texture coordinates repeat every `tile_size`.
+fn ground_quad(size: f32, tile_size: f32) -> ([[f32; 3]; 4], [[f32; 2]; 4]) {
+    let tiles = size / tile_size;
+    let corners = [[0., 0.], [1., 0.], [1., 1.], [0., 1.]];
+    let positions = corners.map(|[x, y]: [f32; 2]| [(x - 0.5) * size, (y - 0.5) * size, 0.]);
+    let uvs = corners.map(|[x, y]: [f32; 2]| [x * tiles, y * tiles]);
+    (positions, uvs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ground_quad;
+
+    #[test]
+    fn corners_and_tiles() {
+        let (positions, uvs) = ground_quad(200., 4.);
+
+        // The corners span the whole size and the texture repeats every tile.
+        assert_eq!(positions[0], [-100., -100., 0.]);
+        assert_eq!(positions[2], [100., 100., 0.]);
+        assert_eq!(uvs[0], [0., 0.]);
+        assert_eq!(uvs[2], [50., 50.]);
+    }
+}
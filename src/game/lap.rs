@@ -0,0 +1,413 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+use nalgebra::Vector3;
+use std::cmp::Ordering;
+
+/// Time [s] "GO!" is shown after the countdown.
+const GO_DURATION: f32 = 1.;
+
+/// Laps of a race if neither the settings nor the track set them.
+pub const DEFAULT_LAPS: usize = 3;
+
+/// Countdown before the start of a race. The cars don't take any input until it elapsed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Countdown {
+    duration: f32,
+    time: f32,
+}
+
+impl Countdown {
+    /// Create a countdown of `duration` [s], 0.0 starts right away.
+    pub fn new(duration: f32) -> Countdown {
+        Countdown { duration, time: 0. }
+    }
+
+    /// Count down by the time step `dt`.
+    pub fn update(&mut self, dt: f32) {
+        self.time = (self.time + dt).min(self.duration + GO_DURATION);
+    }
+
+    /// Return true once the race started.
+    pub fn is_finished(&self) -> bool {
+        self.time >= self.duration
+    }
+
+    /// Return the text to show, the full seconds left, "GO!" right after the start and `None` later on.
+    pub fn label(&self) -> Option<String> {
+        if !self.is_finished() {
+            Some(format!("{}", (self.duration - self.time).ceil()))
+        } else if self.duration > 0. && self.time < self.duration + GO_DURATION {
+            Some(String::from("GO!"))
+        } else {
+            None
+        }
+    }
+}
+
+/// Finish line between two points in the x-y plane.
+///
+/// Cars cross it in the driving direction when moving from the right to the left side of `a -> b`.
+#[derive(Debug, Copy, Clone)]
+pub struct FinishLine {
+    a: Vector3<f32>,
+    b: Vector3<f32>,
+}
+
+impl FinishLine {
+    pub fn new(a: Vector3<f32>, b: Vector3<f32>) -> FinishLine {
+        FinishLine { a, b }
+    }
+
+    /// Return the signed distance of `p` to the line, positive on the left side of `a -> b`.
+    ///
+    /// Points on the line count as the left side, so a car stopping right on the line
+    /// crosses it only once.
+    fn side(&self, p: &Vector3<f32>) -> f32 {
+        let (ab, ap) = (self.b - self.a, p - self.a);
+        ab[0] * ap[1] - ab[1] * ap[0]
+    }
+
+    /// Check if the movement from `from` to `to` crosses the line.
+    ///
+    /// Returns the fraction of the movement [0.0; 1.0] at which the line is crossed and true if
+    /// it was crossed in the driving direction.
+    pub fn crossing(&self, from: &Vector3<f32>, to: &Vector3<f32>) -> Option<(f32, bool)> {
+        let (side_from, side_to) = (self.side(from), self.side(to));
+        let forward = side_from < 0. && side_to >= 0.;
+        let backward = side_from >= 0. && side_to < 0.;
+        if !forward && !backward {
+            return None;
+        }
+
+        // Only count crossings between the end points of the line.
+        let t = side_from / (side_from - side_to);
+        let hit = from + (to - from) * t;
+        let ab = self.b - self.a;
+        let along = ((hit[0] - self.a[0]) * ab[0] + (hit[1] - self.a[1]) * ab[1]) / (ab[0] * ab[0] + ab[1] * ab[1]);
+        if !(0. ..=1.).contains(&along) {
+            return None;
+        }
+        Some((t, forward))
+    }
+}
+
+/// Counts the laps of a car and measures the lap times [s].
+///
+/// The clock starts when the car crosses the finish line for the first time. Driving back over
+/// the line has to be made up before the next crossing counts as a lap, and so do the checkpoints
+/// the car missed on its way.
+#[derive(Debug, Clone)]
+pub struct LapTimer {
+    line: FinishLine,
+    /// Lines to cross in order during a lap, in the driving direction like the finish line.
+    checkpoints: Vec<FinishLine>,
+    /// Index of the checkpoint to cross next, all of them are passed once it is their number.
+    next_checkpoint: usize,
+    previous: Option<Vector3<f32>>,
+    is_started: bool,
+    /// Backward crossings that have to be made up.
+    debt: u32,
+    time: f32,
+    splits: Vec<f32>,
+}
+
+impl LapTimer {
+    /// Create a timer of laps over the finish `line` through all `checkpoints` in order.
+    pub fn new(line: FinishLine, checkpoints: Vec<FinishLine>) -> LapTimer {
+        LapTimer {
+            line,
+            checkpoints,
+            next_checkpoint: 0,
+            previous: None,
+            is_started: false,
+            debt: 0,
+            time: 0.,
+            splits: Vec::new(),
+        }
+    }
+
+    /// Update the timer with the new `position` of the car after a time step `dt`.
+    ///
+    /// Returns true if a lap was completed during this step.
+    pub fn update(&mut self, position: Vector3<f32>, dt: f32) -> bool {
+        self.time += dt;
+        let previous = match self.previous.replace(position) {
+            Some(previous) => previous,
+            None => return false,
+        };
+        // Only the next checkpoint counts, the car drives past the others as if they weren't there.
+        if let Some(checkpoint) = self.checkpoints.get(self.next_checkpoint).filter(|_| self.is_started) {
+            if let Some((_, true)) = checkpoint.crossing(&previous, &position) {
+                self.next_checkpoint += 1;
+            }
+        }
+        let (t, forward) = match self.line.crossing(&previous, &position) {
+            Some(crossing) => crossing,
+            None => return false,
+        };
+
+        if !forward {
+            self.debt += 1;
+            return false;
+        }
+        if self.debt > 0 {
+            self.debt -= 1;
+            return false;
+        }
+        if self.is_started && self.next_checkpoint < self.checkpoints.len() {
+            // A shortcut to the line, the lap goes on until the car comes back through the checkpoints.
+            return false;
+        }
+        self.next_checkpoint = 0;
+
+        // The line was crossed somewhere during the step, take the time of the crossing.
+        let remainder = (1. - t) * dt;
+        let completed = self.is_started;
+        if completed {
+            self.splits.push(self.time - remainder);
+        }
+        self.is_started = true;
+        self.time = remainder;
+        completed
+    }
+
+    /// Return the number of completed laps.
+    pub fn laps(&self) -> usize {
+        self.splits.len()
+    }
+
+    /// Return the time of the running lap, or `None` before the first crossing.
+    pub fn current_lap_time(&self) -> Option<f32> {
+        if self.is_started {
+            Some(self.time)
+        } else {
+            None
+        }
+    }
+
+    /// Return the time of the last completed lap.
+    pub fn last_lap(&self) -> Option<f32> {
+        self.splits.last().cloned()
+    }
+
+    /// Return the fastest completed lap time.
+    pub fn best_lap(&self) -> Option<f32> {
+        fastest(&self.splits)
+    }
+
+    /// Return the times of all completed laps.
+    pub fn splits(&self) -> &[f32] {
+        &self.splits
+    }
+}
+
+/// Final times of a player in a race.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RaceResult {
+    /// Index of the player, starting at 0.
+    pub player: usize,
+    /// Time [s] for all laps of the race, `None` if the player didn't finish.
+    pub total: Option<f32>,
+    pub best_lap: Option<f32>,
+}
+
+/// Return the results of a race over `laps` laps from the lap times of each player, in finishing order.
+///
+/// The fastest total time wins and the best lap breaks ties. Players who didn't finish come last.
+pub fn race_results(splits: &[&[f32]], laps: usize) -> Vec<RaceResult> {
+    let mut results = splits
+        .iter()
+        .enumerate()
+        .map(|(player, splits)| RaceResult {
+            player,
+            total: if splits.len() >= laps {
+                Some(splits[..laps].iter().sum())
+            } else {
+                None
+            },
+            best_lap: fastest(splits),
+        })
+        .collect::<Vec<_>>();
+    results.sort_by(|a, b| compare_times(a.total, b.total).then_with(|| compare_times(a.best_lap, b.best_lap)));
+    results
+}
+
+/// Return the fastest of the lap `splits`.
+fn fastest(splits: &[f32]) -> Option<f32> {
+    splits.iter().cloned().fold(None, |best, t| match best {
+        Some(b) if b <= t => Some(b),
+        _ => Some(t),
+    })
+}
+
+/// Order times from fast to slow, missing times last.
+fn compare_times(a: Option<f32>, b: Option<f32>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{race_results, Countdown, FinishLine, LapTimer};
+    use nalgebra::Vector3;
+
+    /// Finish line across the y-axis, crossed when driving towards +y.
+    fn line() -> FinishLine {
+        FinishLine::new(Vector3::new(-5., 0., 0.), Vector3::new(5., 0., 0.))
+    }
+
+    #[test]
+    fn crossing() {
+        let line = line();
+        let (before, after) = (Vector3::new(0., -1., 0.), Vector3::new(0., 3., 0.));
+        assert_eq!(line.crossing(&before, &after), Some((0.25, true)));
+        assert_eq!(line.crossing(&after, &before), Some((0.75, false)));
+        assert_eq!(line.crossing(&before, &before), None);
+        // Passing beside the line doesn't count.
+        assert_eq!(line.crossing(&Vector3::new(6., -1., 0.), &Vector3::new(6., 1., 0.)), None);
+    }
+
+    #[test]
+    fn drive_across_once() {
+        let line = line();
+        let mut timer = LapTimer::new(line, Vec::new());
+        let mut previous = Vector3::new(0., -2., 0.);
+        let mut crossings = 0;
+        // In steps of 0.1m, so the car ends up exactly on the line in one step.
+        for i in 1..40 {
+            let position = Vector3::new(0., -2. + 0.1 * i as f32, 0.);
+            if line.crossing(&previous, &position).is_some() {
+                crossings += 1;
+            }
+            timer.update(position, 0.01);
+            previous = position;
+        }
+        assert_eq!(crossings, 1);
+        assert!(timer.current_lap_time().is_some());
+        assert_eq!(timer.laps(), 0);
+    }
+
+    #[test]
+    fn lap_times() {
+        let mut timer = LapTimer::new(line(), Vec::new());
+        let (before, after) = (Vector3::new(0., -1., 0.), Vector3::new(0., 1., 0.));
+        // Drive around the end of the line back to `before` within `time`.
+        let drive_around = |timer: &mut LapTimer, time: f32| {
+            timer.update(Vector3::new(10., 1., 0.), time * 0.5);
+            timer.update(Vector3::new(10., -1., 0.), 0.);
+            timer.update(before, time * 0.5)
+        };
+        timer.update(before, 0.);
+
+        // Start the clock halfway through a 1s step.
+        assert!(!timer.update(after, 1.));
+        assert_eq!(timer.current_lap_time(), Some(0.5));
+
+        // A lap of 10s and a faster one of 8s.
+        assert!(!drive_around(&mut timer, 9.));
+        assert!(timer.update(after, 1.));
+        assert!(!drive_around(&mut timer, 7.));
+        assert!(timer.update(after, 1.));
+        assert_eq!(timer.splits(), &[10., 8.]);
+        assert_eq!(timer.best_lap(), Some(8.));
+        assert_eq!(timer.last_lap(), Some(8.));
+
+        // Backwards over the line and forth again isn't a lap.
+        assert!(!timer.update(before, 1.));
+        assert!(!timer.update(after, 1.));
+        assert_eq!(timer.laps(), 2);
+        assert_eq!(timer.current_lap_time(), Some(2.5));
+    }
+
+    #[test]
+    fn checkpoints_in_order() {
+        // Two checkpoints to the right of the finish line, crossed when driving towards -y.
+        let checkpoints = vec![
+            FinishLine::new(Vector3::new(15., 0., 0.), Vector3::new(5., 0., 0.)),
+            FinishLine::new(Vector3::new(25., 0., 0.), Vector3::new(15., 0., 0.)),
+        ];
+        let mut timer = LapTimer::new(line(), checkpoints);
+        // Drive through the points a second each, return true if the last one completed a lap.
+        let drive = |timer: &mut LapTimer, points: &[(f32, f32)]| {
+            points.iter().map(|&(x, y)| timer.update(Vector3::new(x, y, 0.), 1.)).last() == Some(true)
+        };
+        let first = [(10., 1.), (10., -1.)];
+        let second = [(30., -1.), (30., 1.), (20., 1.), (20., -1.)];
+        let finish = [(0., -1.), (0., 1.)];
+        assert!(!drive(&mut timer, &finish));
+
+        // Passing the second checkpoint before the first one doesn't count it, and the lap is one short.
+        let out_of_order = [(20., 1.), (20., -1.), (30., -1.), (30., 1.), (10., 1.), (10., -1.)];
+        assert!(!drive(&mut timer, &[&out_of_order[..], &finish].concat()));
+        // Neither does the first checkpoint again.
+        assert!(!drive(&mut timer, &[&first[..], &finish].concat()));
+        assert_eq!(timer.laps(), 0);
+
+        // The lap counts once the missed checkpoint is made up, it took all the time since the start.
+        assert!(drive(&mut timer, &[&second[..], &finish].concat()));
+        assert_eq!(timer.splits(), &[18.]);
+        assert!(drive(&mut timer, &[&first[..], &second[..], &finish].concat()));
+        assert_eq!(timer.splits(), &[18., 8.]);
+    }
+
+    #[test]
+    fn finishing_order() {
+        let splits: [&[f32]; 5] = [
+            &[30., 29., 31.],
+            &[28., 30., 32.],
+            &[31., 30.],
+            &[29., 32., 29.],
+            &[31., 32., 28., 25.],
+        ];
+        let results = race_results(&splits, 3);
+        let order = results.iter().map(|r| r.player).collect::<Vec<_>>();
+        // Players 0, 1 and 3 tie at 90s, the best lap decides and equal times keep the player order.
+        // Player 2 didn't finish.
+        assert_eq!(order, vec![1, 0, 3, 4, 2]);
+        assert_eq!(results[0].total, Some(90.));
+        assert_eq!(results[0].best_lap, Some(28.));
+        // Laps after the race don't count for the total, but for the best lap.
+        assert_eq!(results[3].total, Some(91.));
+        assert_eq!(results[3].best_lap, Some(25.));
+        assert_eq!(results[4].total, None);
+        assert_eq!(results[4].best_lap, Some(30.));
+    }
+
+    #[test]
+    fn countdown() {
+        let mut countdown = Countdown::new(3.);
+        let mut labels = Vec::new();
+        for _ in 0..20 {
+            labels.push(countdown.label());
+            countdown.update(0.25);
+        }
+        assert_eq!(labels[0], Some(String::from("3")));
+        assert_eq!(labels[6], Some(String::from("2")));
+        assert_eq!(labels[11], Some(String::from("1")));
+        assert_eq!(labels[12], Some(String::from("GO!")));
+        assert_eq!(labels[15], Some(String::from("GO!")));
+        assert_eq!(labels[16], None);
+        assert!(countdown.is_finished());
+
+        let countdown = Countdown::new(0.);
+        assert!(countdown.is_finished());
+        assert_eq!(countdown.label(), None);
+    }
+}
@@ -12,8 +12,9 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
-use crate::grphx::Model;
-use log::debug;
+use super::track::TrackLayout;
+use crate::grphx::{DirectionalLight, Model, ShaderError};
+use log::{debug, warn};
 use nalgebra::{zero, Isometry3, Matrix4, Point3, Vector3};
 use ncollide3d::shape::{Cuboid, TriMesh};
 use std::path::Path;
@@ -22,8 +23,8 @@ use std::path::Path;
 ///
 /// Currently our Environment consist of a race track with some colliders.
 pub struct Level {
-    /// Racetrack model
-    model: Model,
+    /// Racetrack model, levels without one only collide.
+    model: Option<Model>,
     /// Identity matrix for model rendering.
     matrix: Matrix4<f32>,
     /// A simple box collider for the ground.
@@ -36,8 +37,14 @@ impl Level {
     /// Load a model from raw model files.
     pub fn new(file: &str) -> Level {
         debug!("New from {}", file);
-        let model = Model::new(file, "racetrack.png");
+        Level {
+            model: Some(Model::new(file, "racetrack.png")),
+            ..Level::without_model(file)
+        }
+    }
 
+    /// Load only the colliders of a level, so it can be driven on without an OpenGL context.
+    pub(crate) fn without_model(file: &str) -> Level {
         // No scaliing, rotating, translating (Just for render)
         let matrix = Matrix4::identity();
 
@@ -46,13 +53,30 @@ impl Level {
         let border = (Isometry3::new(zero(), zero()), col_border);
 
         Level {
-            model,
+            model: None,
             matrix,
             ground,
             border,
         }
     }
 
+    /// Load the walls, spawn points and more of the track from an optional json file next to the model.
+    pub(super) fn load_layout(file: &str) -> Option<TrackLayout> {
+        let path_str = format!("res/models/{}.json", file);
+        let path = Path::new(&path_str);
+        if !path.exists() {
+            debug!("No track layout at {}", path_str);
+            return None;
+        }
+        match TrackLayout::load(path) {
+            Ok(layout) => Some(layout),
+            Err(e) => {
+                warn!("Ignoring track layout {}: {}", path_str, e);
+                None
+            }
+        }
+    }
+
     /// Load the collider mesh from an obj file.
     fn load_collider(file: &str) -> (Cuboid<f32>, TriMesh<f32>) {
         let path_str = format!("res/models/{}.col", file);
@@ -85,8 +109,48 @@ impl Level {
         (col_ground, col_border)
     }
 
+    /// Compile the shaders of the racetrack again from their files.
+    pub fn reload_shaders(&mut self) -> Result<(), ShaderError> {
+        match &mut self.model {
+            Some(model) => model.reload_shaders(),
+            None => Ok(()),
+        }
+    }
+
     /// Render the environment to the bound framebuffer.
-    pub fn draw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
-        self.model.draw(&self.matrix, view, projection);
+    pub fn draw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>, light: &DirectionalLight) {
+        if let Some(model) = &self.model {
+            model.draw(&self.matrix, view, projection, light);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Level;
+    use crate::game::car::Car;
+    use crate::game::grid::StartGrid;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn load_shipped_layouts() {
+        for map in &["maps/race_track_1", "maps/race_track_2"] {
+            let layout = Level::load_layout(map).unwrap_or_else(|| panic!("no layout for {}", map));
+            assert!(layout.finish_line.is_some());
+            // The racing line stays between the walls.
+            let waypoints = &layout.waypoints;
+            assert!(waypoints.len() > 3);
+            for (i, waypoint) in waypoints.iter().enumerate() {
+                let next = &waypoints[(i + 1) % waypoints.len()];
+                assert_eq!(layout.track.raycast(waypoint, next), None, "{} waypoint {}", map, i);
+            }
+            // The starting grid is clear of the walls.
+            for slot in 0..8 {
+                let pose = StartGrid::default().slot(&layout.spawns[0], slot);
+                let mut car = Car::with_half_extents(Vector3::new(0.9, 1.6, 0.5), pose.translation.vector, 1000.0);
+                car.rotation[2] = pose.rotation.euler_angles().2;
+                assert!(!layout.track.collide(&mut car), "{} slot {}", map, slot);
+            }
+        }
     }
 }
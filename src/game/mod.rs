@@ -13,41 +13,111 @@
 // You should have received a copy of the GNU General Public License
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
 
+/// Computer controlled opponents.
+pub mod ai;
 /// GameObject, currently only a car.
 pub mod car;
 /// User input handling.
 pub mod controller;
+/// Lines of the colliders, walls and racing line drawn over the scene.
+pub mod debug_lines;
+/// Starting grid lining up the cars.
+pub mod grid;
+/// Textured plane around the level.
+pub mod ground;
+/// Lap counting and timing.
+pub mod lap;
 /// Environment of a `Scene`.
 pub mod level;
+/// Skid smoke and other particle effects.
+pub mod particles;
+/// Freeze the race to take pictures.
+pub mod photo;
+/// Swappable driving models of the cars.
+pub mod physics;
+/// Record and play back the input of a car.
+pub mod replay;
+/// Turn back time in practice.
+pub mod rewind;
 /// Actual runtime data.
 pub mod scene;
+/// Points for hitting other cars.
+pub mod score;
+/// Shadows under the cars.
+pub mod shadow;
+/// Cars and track physics without rendering.
+pub mod simulation;
+/// Tire marks on the ground behind sliding cars.
+pub mod skidmarks;
+/// Smooth curves for the racing line.
+pub mod spline;
+/// Screens of the game and the transitions between them.
+pub mod state;
+/// Walls around the race track.
+pub mod track;
 /// 3D translation, rotation and scale.
 pub mod transform;
 
 use imgui_glfw_rs::glfw;
 
-use self::controller::{Controller, ControllerLayout};
+use self::ai::Difficulty;
+use self::car::CarTuning;
+use self::controller::{Controller, ControllerLayout, Gamepads, KeyBindings};
+use self::lap::Countdown;
+use self::photo::PhotoMode;
+use self::replay::{CarConfig, Recorder, Recording, Run, RunError};
 use self::scene::Scene;
-use crate::grphx::Screen;
+use self::simulation::CollisionEvent;
+use self::state::{GameState, StateInput};
+use crate::audio::AudioEngine;
+use crate::grphx::{sample_count, save_screenshot, CameraMode, DebugCamera, Screen, Viewport};
 use crate::gui::AppUI;
-use crate::util::FrameLimiter;
+use crate::net::{Client, Server, CLIENT_PLAYER, NET_PLAYERS};
+use crate::util::{FixedTimestep, FrameCounter, FrameLimiter};
 use glfw::{Context, Glfw, Window};
-use log::{debug, info};
-use nalgebra::Perspective3;
+use log::{debug, error, info, warn};
+use nalgebra::{clamp, Matrix4, Orthographic3, Perspective3};
+use serde_derive::{Deserialize, Serialize};
 use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::sync::mpsc::Receiver;
 use std::thread::sleep;
 use time::Duration;
 
 type Event = Receiver<(f64, glfw::WindowEvent)>;
 
+/// Time step [s] of the simulation, independent of the frame rate.
+pub const FIXED_TIMESTEP: f32 = 1. / 120.;
+
+/// Settings file next to the executable.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Change of the master volume per key press.
+const VOLUME_STEP: f32 = 0.1;
+
+/// Number of frames the shown frame rate is averaged over.
+const FRAME_COUNTER_FRAMES: usize = 60;
+
+/// Maximum number of local players, one for each gamepad.
+pub const MAX_PLAYERS: usize = 4;
+/// Maximum number of computer players, so the grid fits behind the start of every track.
+const MAX_OPPONENTS: usize = 7;
+
 pub(crate) struct Game {
     // Glfw and GL
     glfw: Glfw,
     window: Window,
     events: Event,
     frame_limiter: FrameLimiter,
+    frame_counter: FrameCounter,
+    is_frame_rate_visible: bool,
+    timestep: FixedTimestep,
     gui: AppUI,
+    audio: AudioEngine,
 
     screen: Screen,
 
@@ -55,14 +125,112 @@ pub(crate) struct Game {
     settings: GameSettings,
     scene: Scene,
     controller: Vec<Controller>,
+    gamepads: Gamepads,
+    recorder: Option<Recorder>,
+    photo_mode: PhotoMode,
+    /// Host of an online race, it simulates the car of the client too.
+    server: Option<Server>,
+    /// Client of an online race, it follows the cars of the host.
+    client: Option<Client>,
+    /// Collisions between the cars of `scene`, for the crash sounds.
+    collisions: Receiver<CollisionEvent>,
+    state: GameState,
+    is_key_esc: bool,
+    is_key_enter: bool,
+    is_key_f3: bool,
+    is_key_f4: bool,
+    is_key_f5: bool,
+    is_key_f6: bool,
+    is_key_f12: bool,
+    is_key_photo: bool,
+    is_key_straighten: bool,
+    is_key_camera: bool,
+    is_key_mute: bool,
+    is_key_volume_down: bool,
+    is_key_volume_up: bool,
+    /// Set by F12 to save the next frame once it is drawn.
+    is_screenshot_requested: bool,
+    /// Last cursor position to turn the debug camera by the mouse movement.
+    cursor: Option<(f64, f64)>,
 }
 
+/// How the scene is projected onto the screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectionMode {
+    /// Things further away look smaller.
+    Perspective,
+    /// Parallel view without foreshortening, e.g. for a clean overhead view.
+    Orthographic,
+}
+
+/// How the window is shared between local players.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitScreen {
+    /// A single camera keeps all cars in view.
+    Off,
+    /// Two players one above the other, each with a camera behind the car.
+    Horizontal,
+    /// Two players side by side, each with a camera behind the car.
+    Vertical,
+}
+
+/// Settings of the game, loaded from a config file and the command line.
+///
+/// Settings missing in the config file keep their default.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GameSettings {
+    #[serde(rename = "fullscreen")]
     pub is_fullscreen: bool,
     pub width: u32,
     pub height: u32,
     pub map: u32,
+    /// Frame rate cap, `0` draws as many frames as possible. The physics steps at `FIXED_TIMESTEP` anyway.
     pub fps: u32,
+    /// Wait for the display with every new frame, against tearing but with a frame more latency.
+    pub vsync: bool,
+    /// Number of local players, between 1 and `MAX_PLAYERS`.
+    pub players: usize,
+    /// Computer players racing a single local player, up to `MAX_OPPONENTS`.
+    pub opponents: usize,
+    /// How well the computer players drive.
+    pub difficulty: Difficulty,
+    /// Laps of a race on tracks with a finish line, `None` leaves it to the track.
+    pub laps: Option<usize>,
+    /// Length [s] of the countdown before the start.
+    pub countdown: f32,
+    /// Arcade handbrake turns for all cars instead of realistic ones.
+    pub arcade_drift: bool,
+    /// Parts every simulation step is split into, more substeps keep fast cars from passing through walls.
+    pub substeps: u32,
+    /// Seed of the random numbers, the same seed and inputs play out the same race.
+    pub seed: u64,
+    /// Master volume of the sounds between 0.0 and 1.0.
+    pub volume: f32,
+    /// Start the game without sound.
+    pub muted: bool,
+    /// Samples per pixel for anti-aliasing, 0 or 1 disables it.
+    pub msaa: u32,
+    /// Perspective or orthographic view of the scene.
+    pub projection: ProjectionMode,
+    /// Point of view of the cameras following the cars, `C` cycles through the modes.
+    pub camera: CameraMode,
+    /// A view for each local player instead of one for all, three or four players get a quarter each.
+    pub split_screen: SplitScreen,
+    /// Speed of the simulation, e.g. 0.5 for slow motion or 2.0 for fast forward.
+    pub time_scale: f32,
+    /// Recording to race against as a ghost.
+    pub ghost: Option<String>,
+    /// File to record the input of player 1 to.
+    pub record: Option<String>,
+    /// Address to host an online race on, e.g. "0.0.0.0:7878".
+    pub host: Option<String>,
+    /// Address of the host of an online race to join.
+    pub join: Option<String>,
+    /// Tuning of all cars, in a `[car]` table after the other settings.
+    pub car: CarTuning,
 }
 
 impl Default for GameSettings {
@@ -73,10 +241,104 @@ impl Default for GameSettings {
             height: 480,
             map: 1,
             fps: 60,
+            vsync: true,
+            players: 2,
+            opponents: 3,
+            difficulty: Difficulty::Medium,
+            laps: None,
+            countdown: 3.,
+            arcade_drift: false,
+            substeps: 1,
+            seed: 0,
+            volume: 1.,
+            muted: false,
+            msaa: 4,
+            projection: ProjectionMode::Perspective,
+            camera: CameraMode::Chase,
+            split_screen: SplitScreen::Off,
+            time_scale: 1.,
+            ghost: None,
+            record: None,
+            host: None,
+            join: None,
+            car: CarTuning::default(),
+        }
+    }
+}
+
+/// Error while saving `GameSettings`.
+#[derive(Debug)]
+pub enum SettingsError {
+    /// The file could not be read or written.
+    Io(io::Error),
+    /// The file is no valid config, it is left alone instead of being overwritten.
+    Parse(toml::de::Error),
+    /// The settings could not be written as toml.
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "failed to access settings: {}", e),
+            SettingsError::Parse(e) => write!(f, "failed to parse settings: {}", e),
+            SettingsError::Serialize(e) => write!(f, "failed to write settings: {}", e),
         }
     }
 }
 
+impl Error for SettingsError {}
+
+impl From<io::Error> for SettingsError {
+    fn from(e: io::Error) -> SettingsError {
+        SettingsError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for SettingsError {
+    fn from(e: toml::de::Error) -> SettingsError {
+        SettingsError::Parse(e)
+    }
+}
+
+impl From<toml::ser::Error> for SettingsError {
+    fn from(e: toml::ser::Error) -> SettingsError {
+        SettingsError::Serialize(e)
+    }
+}
+
+impl GameSettings {
+    /// Load the settings from a toml file. If the file is missing or broken, the defaults are used.
+    pub fn load(file: &str) -> GameSettings {
+        let content = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(_) => return Default::default(),
+        };
+        match toml::from_str(&content) {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!("Could not load settings from {}: {}", file, e);
+                Default::default()
+            }
+        }
+    }
+
+    /// Apply `change` to the settings in a toml file and write them back, e.g. after turning the volume
+    /// in the game. A missing file is created with the defaults.
+    ///
+    /// Only the settings in the file are changed, so the overrides of the command line don't stick.
+    pub fn save(file: &str, change: impl FnOnce(&mut GameSettings)) -> Result<(), SettingsError> {
+        let mut settings = match fs::read_to_string(file) {
+            Ok(content) => toml::from_str(&content)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Default::default(),
+            Err(e) => return Err(e.into()),
+        };
+        change(&mut settings);
+        fs::write(file, toml::to_string(&settings)?)?;
+        Ok(())
+    }
+}
+
 impl Game {
     pub(crate) fn new(settings: GameSettings) -> Game {
         info!("Initializing game");
@@ -87,6 +349,9 @@ impl Game {
         glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
         glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
         glfw.window_hint(glfw::WindowHint::SRgbCapable(true));
+        if settings.msaa > 1 {
+            glfw.window_hint(glfw::WindowHint::Samples(Some(settings.msaa)));
+        }
         glfw.set_error_callback(Some(glfw::Callback {
             f: error_callback,
             data: Cell::new(0),
@@ -105,6 +370,7 @@ impl Game {
             .expect("Failed to create GLFW window");
 
         window.make_current();
+        set_vsync(&mut glfw, settings.vsync, settings.fps);
         window.set_all_polling(true);
         window.set_cursor_mode(glfw::CursorMode::Normal);
 
@@ -119,26 +385,114 @@ impl Game {
             gl::DepthFunc(gl::LESS);
         }
 
-        let screen = Screen::new(settings.width, settings.height);
+        // Fall back to the most samples the driver supports.
+        let mut max_samples = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+        }
+        let samples = sample_count(settings.msaa, max_samples);
+        if samples != settings.msaa && settings.msaa > 1 {
+            warn!("{}x anti-aliasing is not supported, using {}x", settings.msaa, samples);
+        }
+        if samples > 0 {
+            unsafe {
+                gl::Enable(gl::MULTISAMPLE);
+            }
+        }
 
-        let controller = vec![
-            Controller::new(true, &ControllerLayout::WASD),
-            Controller::new(true, &ControllerLayout::Arrows),
-        ];
-        let scene = Scene::new(settings.map);
+        let screen = Screen::new(settings.width, settings.height, samples);
+
+        // Odd players share the WASD and even players the arrow keys, unless they are remapped.
+        let is_online = settings.host.is_some() || settings.join.is_some();
+        let players = if is_online {
+            NET_PLAYERS
+        } else {
+            clamp(settings.players, 1, MAX_PLAYERS)
+        };
+        let mut controller = Vec::with_capacity(players);
+        for id in 0..players {
+            let layout = if id % 2 == 0 {
+                ControllerLayout::WASD
+            } else {
+                ControllerLayout::Arrows
+            };
+            let mut ctrl = Controller::new(true, &layout);
+            ctrl.set_gamepad(id);
+            let player = format!("player{}", id + 1);
+            ctrl.set_key_bindings(KeyBindings::load("keybindings.toml", &player, ctrl.key_bindings()));
+            controller.push(ctrl);
+        }
+        let server = settings.host.as_ref().and_then(|address| match Server::bind(address) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                error!("Could not host on {}: {}", address, e);
+                None
+            }
+        });
+        let client = settings
+            .join
+            .as_ref()
+            .and_then(|address| match Client::connect(address, CLIENT_PLAYER) {
+                Ok(client) => Some(client),
+                Err(e) => {
+                    error!("Could not join {}: {}", address, e);
+                    None
+                }
+            });
+        // The client drives its car with the keys and gamepad of player 1.
+        if client.is_some() {
+            controller.swap(0, CLIENT_PLAYER as usize);
+        }
+        let mut scene = new_scene(&settings, players);
+        let collisions = scene.simulation.subscribe();
+        let recorder = settings.record.as_ref().map(|_| Recorder::new(FIXED_TIMESTEP));
+        let mut timestep = FixedTimestep::new(FIXED_TIMESTEP);
+        timestep.time_scale = settings.time_scale;
+        let mut audio = AudioEngine::new(players);
+        audio.set_master_volume(settings.volume);
+        audio.set_muted(settings.muted);
+        // The game starts in the menu.
+        timestep.is_paused = true;
+        audio.set_paused(true);
 
         Game {
             glfw,
             window,
             events,
             frame_limiter,
+            frame_counter: FrameCounter::new(FRAME_COUNTER_FRAMES),
+            is_frame_rate_visible: false,
+            timestep,
             gui,
+            audio,
 
             screen,
 
             settings,
             scene,
             controller,
+            gamepads: Gamepads::new(),
+            recorder,
+            photo_mode: PhotoMode::new(),
+            server,
+            client,
+            collisions,
+            state: GameState::Menu,
+            is_key_esc: false,
+            is_key_enter: false,
+            is_key_f3: false,
+            is_key_f4: false,
+            is_key_f5: false,
+            is_key_f6: false,
+            is_key_f12: false,
+            is_key_photo: false,
+            is_key_straighten: false,
+            is_key_camera: false,
+            is_key_mute: false,
+            is_key_volume_down: false,
+            is_key_volume_up: false,
+            is_screenshot_requested: false,
+            cursor: None,
         }
     }
 
@@ -147,35 +501,142 @@ impl Game {
 
         while !self.window.should_close() {
             let dt = self.frame_limiter.start();
+            self.frame_counter.add(dt);
             self.window.make_current();
             self.glfw.poll_events();
             self.process_events();
-            self.process_input(dt);
+            self.process_input();
+            if let Some(input) = self.state_input() {
+                self.apply(input);
+            }
+            if self.state.is_running() && self.scene.is_race_finished() {
+                self.apply(StateInput::RaceFinished);
+            }
 
-            self.scene.update(dt, &self.controller);
+            if let Some(server) = &mut self.server {
+                server.receive();
+            }
+            if let Some(client) = &mut self.client {
+                client.receive();
+            }
+
+            // No time passes in the simulation outside of a race.
+            let steps = self.timestep.advance(dt);
+            for _ in 0..steps {
+                for ctrl in &mut self.controller {
+                    ctrl.update(FIXED_TIMESTEP);
+                }
+                if let Some(server) = &mut self.server {
+                    server.apply_inputs(&mut self.controller);
+                }
+                if let Some(client) = &mut self.client {
+                    client.send_input(&self.controller[client.player()]);
+                }
+                // Recordings start with the race, just like the ghosts driving them.
+                let is_started = self.scene.simulation.is_started();
+                if let Some(recorder) = self.recorder.as_mut().filter(|_| is_started) {
+                    recorder.record(&self.controller[0]);
+                }
+                // Holding backspace in practice turns back time instead.
+                let is_rewind = self.window.get_key(glfw::Key::Backspace) == glfw::Action::Press;
+                if is_rewind && self.recorder.is_none() && self.scene.rewind(FIXED_TIMESTEP) {
+                    continue;
+                }
+                self.scene.update(FIXED_TIMESTEP, &self.controller);
+            }
+            if steps > 0 {
+                if let Some(server) = &mut self.server {
+                    server.send_snapshot(&self.scene.simulation.cars);
+                }
+                if let Some(client) = &mut self.client {
+                    client.update(&mut self.scene.simulation, dt);
+                }
+            }
+            self.update_debug_camera(dt);
+            self.audio.update(dt, &self.scene.simulation.cars, &self.controller);
+            for impact in self.collisions.try_iter() {
+                self.audio.play_impact(&impact);
+            }
 
             self.screen.first_step();
-            let projection = Perspective3::new(self.settings.width as f32 / self.settings.height as f32, 70., 1.0, 200.).into_inner();
-            self.scene.draw(&projection);
+            let viewports = self.scene.viewports(self.settings.width, self.settings.height);
+            for (player, viewport) in viewports.iter().enumerate() {
+                viewport.apply();
+                let projection = projection(
+                    viewport.width,
+                    viewport.height,
+                    self.scene.fov_of(player),
+                    self.settings.projection,
+                    self.scene.focus_distance_of(player),
+                    self.scene.near_plane_of(player),
+                );
+                self.scene.draw(player, &projection, self.timestep.alpha());
+            }
+            Viewport::full(self.settings.width, self.settings.height).apply();
 
             self.screen.second_step();
 
-            self.gui.draw(&mut self.window, &mut self.scene);
+            let frame_time = self.frame_counter.frame_time();
+            self.gui
+                .set_frame_time(if self.is_frame_rate_visible { Some(frame_time) } else { None });
+            // Photo mode shows the scene alone.
+            if !self.photo_mode.is_active() {
+                let vsync = self.settings.vsync;
+                let input = self
+                    .gui
+                    .draw(&mut self.window, &mut self.scene, self.state, &mut self.settings.vsync);
+                if self.settings.vsync != vsync {
+                    set_vsync(&mut self.glfw, self.settings.vsync, self.settings.fps);
+                }
+                if let Some(input) = input {
+                    self.apply(input);
+                }
+            }
+            if std::mem::take(&mut self.is_screenshot_requested) {
+                save_screenshot(self.settings.width, self.settings.height);
+            }
 
             self.window.swap_buffers();
 
+            if let Some(rest) = self.frame_limiter.sleep_time() {
+                sleep(rest);
+            }
             while self.frame_limiter.stop() {
                 self.glfw.poll_events();
                 self.process_events();
                 sleep(nano_sec);
             }
         }
+
+        if let (Some(recorder), Some(file)) = (self.recorder.take(), &self.settings.record) {
+            let path = Path::new(file);
+            let recording = recorder.finish();
+            let result = if Run::is_run_file(path) {
+                let simulation = &self.scene.simulation;
+                let car = CarConfig::of(&simulation.cars[0]);
+                Run::new(self.settings.map, self.settings.seed, simulation.substeps, car, recording).save(path)
+            } else {
+                recording.save(path).map_err(RunError::from)
+            };
+            if let Err(e) = result {
+                warn!("Could not save recording to {}: {}", file, e);
+            }
+        }
     }
 
     pub fn process_events(&mut self) {
         for (_, event) in glfw::flush_messages(&self.events) {
             self.gui.handle_event(&event);
+            if let glfw::WindowEvent::Key(key, _, action, _) = event {
+                for ctrl in &mut self.controller {
+                    ctrl.handle_key(key, action);
+                }
+            }
             if let glfw::WindowEvent::FramebufferSize(width, height) = event {
+                // Keep the last size while minimized, there is nothing to render into.
+                if width <= 0 || height <= 0 {
+                    continue;
+                }
                 unsafe {
                     gl::Viewport(0, 0, width, height);
                 }
@@ -186,11 +647,297 @@ impl Game {
         }
     }
 
-    pub fn process_input(&mut self, dt: f32) {
+    /// Fly the debug camera with the keys and turn it with the mouse while the right button is held.
+    ///
+    /// It moves in real time `dt`, so it can look around a paused race too.
+    fn update_debug_camera(&mut self, dt: f32) {
+        let camera = match &mut self.scene.debug_camera {
+            Some(camera) => camera,
+            None => return,
+        };
+        let window = &self.window;
+        let axis = |positive, negative| {
+            let is_pressed = |key| window.get_key(key) == glfw::Action::Press;
+            is_pressed(positive) as i32 as f32 - is_pressed(negative) as i32 as f32
+        };
+        let (x, y) = window.get_cursor_pos();
+        if window.get_mouse_button(glfw::MouseButtonRight) == glfw::Action::Press {
+            if let Some((last_x, last_y)) = self.cursor {
+                camera.rotate((x - last_x) as f32, (y - last_y) as f32);
+            }
+        }
+        self.cursor = Some((x, y));
+        camera.update(
+            dt,
+            axis(glfw::Key::W, glfw::Key::S),
+            axis(glfw::Key::D, glfw::Key::A),
+            axis(glfw::Key::E, glfw::Key::Q),
+        );
+    }
+
+    /// Return the input of the keys that switch between the screens of the game, on key press only.
+    fn state_input(&mut self) -> Option<StateInput> {
+        let is_esc = self.window.get_key(glfw::Key::Escape) == glfw::Action::Press;
+        let is_enter = self.window.get_key(glfw::Key::Enter) == glfw::Action::Press;
+        let input = if is_esc && !self.is_key_esc {
+            Some(StateInput::Back)
+        } else if is_enter && !self.is_key_enter {
+            Some(StateInput::Confirm)
+        } else {
+            None
+        };
+        self.is_key_esc = is_esc;
+        self.is_key_enter = is_enter;
+        input
+    }
+
+    /// Move on to the next screen after an `input`.
+    fn apply(&mut self, input: StateInput) {
+        let state = self.state.next(input);
+        if state == self.state {
+            return;
+        }
+        debug!("{:?} -> {:?}", self.state, state);
+        self.photo_mode.exit(&mut self.timestep, &mut self.scene.debug_camera);
+
+        // Back in the menu, the next race starts from the grid.
+        if state == GameState::Menu {
+            self.scene = new_scene(&self.settings, self.controller.len());
+            self.collisions = self.scene.simulation.subscribe();
+            self.recorder = self.settings.record.as_ref().map(|_| Recorder::new(FIXED_TIMESTEP));
+        }
+        if state == GameState::Results {
+            let splits = self
+                .scene
+                .simulation
+                .lap_timers
+                .iter()
+                .map(|timer| timer.splits())
+                .collect::<Vec<_>>();
+            self.gui.set_results(lap::race_results(&splits, self.scene.simulation.laps));
+        }
+        self.timestep.is_paused = !state.is_running();
+        self.audio.set_paused(!state.is_running());
+        self.state = state;
+    }
+
+    pub fn process_input(&mut self) {
+        // F3 shows and hides the frame rate.
+        let is_f3 = self.window.get_key(glfw::Key::F3) == glfw::Action::Press;
+        if is_f3 && !self.is_key_f3 {
+            self.is_frame_rate_visible = !self.is_frame_rate_visible;
+            info!("Frame rate: {:.1} FPS", self.frame_counter.fps());
+        }
+        self.is_key_f3 = is_f3;
+
+        // F4 detaches the camera from the race to fly around.
+        let is_f4 = self.window.get_key(glfw::Key::F4) == glfw::Action::Press;
+        if is_f4 && !self.is_key_f4 {
+            self.scene.debug_camera = match self.scene.debug_camera {
+                Some(_) => None,
+                None => Some(DebugCamera::from_view(&self.scene.view())),
+            };
+            info!("Debug camera: {}", self.scene.debug_camera.is_some());
+        }
+        self.is_key_f4 = is_f4;
+
+        // F5 draws the colliders, walls and waypoints over the scene.
+        let is_f5 = self.window.get_key(glfw::Key::F5) == glfw::Action::Press;
+        if is_f5 && !self.is_key_f5 {
+            self.scene.is_debug_lines = !self.scene.is_debug_lines;
+            info!("Debug lines: {}", self.scene.is_debug_lines);
+        }
+        self.is_key_f5 = is_f5;
+
+        // F6 reloads the shaders after editing them.
+        let is_f6 = self.window.get_key(glfw::Key::F6) == glfw::Action::Press;
+        if is_f6 && !self.is_key_f6 {
+            info!("Reloading shaders");
+            self.scene.reload_shaders();
+            if let Err(e) = self.screen.reload_shader() {
+                error!("Shader reload failed, keeping the previous program: {}", e);
+            }
+        }
+        self.is_key_f6 = is_f6;
+
+        // F12 saves a screenshot of the next frame.
+        let is_f12 = self.window.get_key(glfw::Key::F12) == glfw::Action::Press;
+        if is_f12 && !self.is_key_f12 {
+            self.is_screenshot_requested = true;
+        }
+        self.is_key_f12 = is_f12;
+
+        // P freezes the race and frees the camera for a photo.
+        let is_photo = self.window.get_key(glfw::Key::P) == glfw::Action::Press;
+        if is_photo && !self.is_key_photo {
+            if self.photo_mode.is_active() {
+                self.photo_mode.exit(&mut self.timestep, &mut self.scene.debug_camera);
+                self.audio.set_paused(self.timestep.is_paused);
+            } else {
+                let view = self.scene.view();
+                self.photo_mode.enter(&mut self.timestep, &mut self.scene.debug_camera, &view);
+                self.audio.set_paused(true);
+            }
+            info!("Photo mode: {}", self.photo_mode.is_active());
+        }
+        self.is_key_photo = is_photo;
+
+        // R turns the car of player 1 along the track, online the host decides where the cars are.
+        let is_straighten = self.window.get_key(glfw::Key::R) == glfw::Action::Press;
+        if is_straighten && !self.is_key_straighten && !self.timestep.is_paused && self.client.is_none() {
+            self.scene.simulation.straighten(0);
+        }
+        self.is_key_straighten = is_straighten;
+
+        // C cycles the cameras following the cars between chase, hood and top-down.
+        let is_camera = self.window.get_key(glfw::Key::C) == glfw::Action::Press;
+        if is_camera && !self.is_key_camera {
+            let camera = self.settings.camera.next();
+            self.settings.camera = camera;
+            self.scene.set_camera_mode(camera);
+            info!("Camera: {:?}", camera);
+            save_settings(|settings| settings.camera = camera);
+        }
+        self.is_key_camera = is_camera;
+
+        // M mutes the sound, minus and plus turn it down and up.
+        let is_mute = self.window.get_key(glfw::Key::M) == glfw::Action::Press;
+        if is_mute && !self.is_key_mute {
+            self.audio.set_muted(!self.audio.is_muted());
+            let muted = self.audio.is_muted();
+            self.settings.muted = muted;
+            save_settings(|settings| settings.muted = muted);
+        }
+        self.is_key_mute = is_mute;
+        let is_volume_down = self.window.get_key(glfw::Key::Minus) == glfw::Action::Press;
+        let is_volume_up = self.window.get_key(glfw::Key::Equal) == glfw::Action::Press;
+        let step = if is_volume_down && !self.is_key_volume_down {
+            -VOLUME_STEP
+        } else if is_volume_up && !self.is_key_volume_up {
+            VOLUME_STEP
+        } else {
+            0.
+        };
+        if step != 0. {
+            self.audio.set_master_volume(self.settings.volume + step);
+            let volume = self.audio.master_volume();
+            self.settings.volume = volume;
+            info!("Volume: {:.0}%", volume * 100.);
+            save_settings(|settings| settings.volume = volume);
+        }
+        self.is_key_volume_down = is_volume_down;
+        self.is_key_volume_up = is_volume_up;
+
+        self.gamepads.poll(&mut self.controller);
         for ctrl in &mut self.controller.iter_mut() {
-            ctrl.process_input(&self.window, dt);
+            ctrl.process_input(&self.window, &self.gamepads);
+        }
+    }
+}
+
+/// Return the aspect ratio of a framebuffer of `width` x `height` pixels.
+///
+/// A minimized window has a size of 0, which counts as a single pixel.
+fn aspect_ratio(width: u32, height: u32) -> f32 {
+    width.max(1) as f32 / height.max(1) as f32
+}
+
+/// Return the projection matrix for a framebuffer of `width` x `height` pixels with a vertical field of
+/// view `fov` [rad].
+///
+/// The orthographic projection shows as much as the perspective one at the `focus_distance` [m] from the camera.
+/// Nothing closer than `near` [m] is drawn.
+fn projection(width: u32, height: u32, fov: f32, mode: ProjectionMode, focus_distance: f32, near: f32) -> Matrix4<f32> {
+    let aspect = aspect_ratio(width, height);
+    match mode {
+        ProjectionMode::Perspective => Perspective3::new(aspect, fov, near, 200.).into_inner(),
+        ProjectionMode::Orthographic => {
+            let top = focus_distance * (fov * 0.5).tan();
+            let right = top * aspect;
+            Orthographic3::new(-right, right, -top, top, near, 200.).into_inner()
+        }
+    }
+}
+
+/// Keep a change of the settings in game for the next start, see `GameSettings::save()`.
+fn save_settings(change: impl FnOnce(&mut GameSettings)) {
+    if let Err(e) = GameSettings::save(CONFIG_FILE, change) {
+        warn!("Could not save settings to {}: {}", CONFIG_FILE, e);
+    }
+}
+
+/// Create the scene of a race for `players` with the ghost of the `settings`.
+///
+/// A single player races against the opponents of the `settings`, several players only race each other.
+fn new_scene(settings: &GameSettings, players: usize) -> Scene {
+    let opponents = if players == 1 { settings.opponents.min(MAX_OPPONENTS) } else { 0 };
+    let tuning = CarTuning {
+        arcade_drift: settings.arcade_drift,
+        ..settings.car
+    };
+    let mut scene = Scene::new(settings.map, players, opponents, tuning);
+    scene.split_screen = settings.split_screen;
+    scene.set_camera_mode(settings.camera);
+    scene.simulation.countdown = Countdown::new(settings.countdown);
+    scene.simulation.seed(settings.seed);
+    scene.simulation.substeps = settings.substeps.max(1);
+    if let Some(laps) = settings.laps {
+        scene.simulation.laps = laps.max(1);
+    }
+    for driver in scene.simulation.drivers.iter_mut().flatten() {
+        driver.set_difficulty(settings.difficulty);
+    }
+    if let Some(file) = &settings.ghost {
+        if let Err(e) = load_ghost(&mut scene, settings.map, Path::new(file)) {
+            warn!("Could not load ghost from {}: {}", file, e);
         }
     }
+    scene
+}
+
+/// Race against the ghost of the recording or run in the file at `path`, in a `scene` of `map`.
+///
+/// A run has to be driven on the same map with the same car as player 1, and its seed and substeps
+/// replace those of the scene.
+fn load_ghost(scene: &mut Scene, map: u32, path: &Path) -> Result<(), RunError> {
+    if !Run::is_run_file(path) {
+        scene.set_ghost(Recording::load(path)?);
+        return Ok(());
+    }
+    let run = Run::load(path)?;
+    run.check(map, &CarConfig::of(&scene.simulation.cars[0]))?;
+    scene.simulation.seed(run.seed);
+    scene.simulation.substeps = run.substeps.max(1);
+    scene.set_ghost(run.recording);
+    Ok(())
+}
+
+/// Sets the swap interval of the current GL context, so tests can do without a window.
+pub(crate) trait SwapControl {
+    fn set_swap_interval(&mut self, interval: glfw::SwapInterval);
+}
+
+impl SwapControl for glfw::Glfw {
+    fn set_swap_interval(&mut self, interval: glfw::SwapInterval) {
+        glfw::Glfw::set_swap_interval(self, interval);
+    }
+}
+
+/// Swap the buffers of the current context in sync with the display if `vsync` is set.
+///
+/// Drivers may ignore this, e.g. if vsync is forced on or off in their settings, without an error. The
+/// frame rate cap of `fps` still holds then.
+fn set_vsync(context: &mut impl SwapControl, vsync: bool, fps: u32) {
+    info!("Vertical sync: {}", vsync);
+    let interval = if vsync {
+        glfw::SwapInterval::Sync(1)
+    } else {
+        glfw::SwapInterval::None
+    };
+    context.set_swap_interval(interval);
+    if !vsync && fps == 0 {
+        warn!("Neither vertical sync nor a frame rate cap, the game draws as many frames as it can");
+    }
 }
 
 #[allow(clippy::needless_pass_by_value)]
@@ -198,3 +945,117 @@ fn error_callback(_: glfw::Error, description: String, error_count: &Cell<usize>
     println!("GLFW error {}: {}", error_count.get(), description);
     error_count.set(error_count.get() + 1);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{aspect_ratio, projection, set_vsync, Difficulty, GameSettings, ProjectionMode, SettingsError, SwapControl};
+    use crate::grphx::{CameraMode, BASE_FOV, NEAR_PLANE};
+    use imgui_glfw_rs::glfw::SwapInterval;
+    use nalgebra::Point3;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn projection_aspect() {
+        for mode in &[ProjectionMode::Perspective, ProjectionMode::Orthographic] {
+            for (width, height) in &[(640, 480), (1920, 1080), (1080, 1920)] {
+                let m = projection(*width, *height, BASE_FOV, *mode, 50., NEAR_PLANE);
+                // The x-axis is scaled down by the aspect ratio, so the scene isn't stretched.
+                let aspect = m[(1, 1)] / m[(0, 0)];
+                assert!((aspect - *width as f32 / *height as f32).abs() < 1e-5);
+            }
+            assert!(projection(800, 0, BASE_FOV, *mode, 50., NEAR_PLANE).iter().all(|x| x.is_finite()));
+        }
+    }
+
+    #[test]
+    fn orthographic_extents() {
+        let distance = 40.;
+        let m = projection(1600, 800, BASE_FOV, ProjectionMode::Orthographic, distance, NEAR_PLANE);
+        let top = distance * (BASE_FOV * 0.5).tan();
+        // The corner of the view at any depth lands on the corner of the screen.
+        for depth in &[2., distance, 150.] {
+            let ndc = m.transform_point(&Point3::new(2. * top, top, -depth));
+            assert!((ndc[0] - 1.).abs() < 1e-5 && (ndc[1] - 1.).abs() < 1e-5);
+            assert!(ndc[2] > -1. && ndc[2] < 1.);
+        }
+        let ndc = m.transform_point(&Point3::new(-top, -0.5 * top, -distance));
+        assert!((ndc[0] + 0.5).abs() < 1e-5 && (ndc[1] + 0.5).abs() < 1e-5);
+
+        // At the focus distance it shows as much as the perspective projection.
+        let perspective = projection(1600, 800, BASE_FOV, ProjectionMode::Perspective, distance, NEAR_PLANE);
+        let ndc = perspective.transform_point(&Point3::new(2. * top, top, -distance));
+        assert!((ndc[0] - 1.).abs() < 1e-4 && (ndc[1] - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn minimized_aspect_ratio() {
+        assert_eq!(aspect_ratio(1600, 900), 1600. / 900.);
+        assert_eq!(aspect_ratio(800, 0), 800.);
+        assert_eq!(aspect_ratio(0, 600), 1. / 600.);
+        assert_eq!(aspect_ratio(0, 0), 1.);
+    }
+
+    #[test]
+    fn settings_from_toml() {
+        let settings: GameSettings = toml::from_str("width = 1280\nheight = 720\nfullscreen = true\n").unwrap();
+        assert_eq!((settings.width, settings.height), (1280, 720));
+        assert!(settings.is_fullscreen);
+        assert_eq!(settings.fps, GameSettings::default().fps);
+        assert_eq!(settings.camera, CameraMode::Chase);
+        let settings: GameSettings = toml::from_str("camera = \"top_down\"\nopponents = 5\ndifficulty = \"hard\"\n").unwrap();
+        assert_eq!(settings.camera, CameraMode::TopDown);
+        assert_eq!(settings.opponents, 5);
+        assert_eq!(settings.difficulty, Difficulty::Hard);
+        let settings: GameSettings = toml::from_str("map = 2\n[car]\naxles = [1.2, 0.9]\nmax_speed = 40\n").unwrap();
+        assert_eq!(settings.car.axles, Some((1.2, 0.9)));
+        assert_eq!(settings.car.max_speed, Some(40.));
+        assert_eq!(settings.car.engine_force, None);
+
+        assert!(!GameSettings::load("missing-config.toml").is_fullscreen);
+    }
+
+    #[test]
+    fn save_settings_to_file() {
+        let path = env::temp_dir().join("carambolage-config.toml");
+        let file = path.to_str().unwrap();
+        let _ = fs::remove_file(&path);
+        // A missing file is created, an existing one keeps its other settings.
+        GameSettings::save(file, |settings| settings.volume = 0.5).unwrap();
+        fs::write(&path, fs::read_to_string(&path).unwrap().replace("width = 640", "width = 1280")).unwrap();
+        GameSettings::save(file, |settings| settings.muted = true).unwrap();
+        GameSettings::save(file, |settings| settings.camera = CameraMode::Hood).unwrap();
+        GameSettings::save(file, |settings| settings.car.axles = Some((1.2, 0.9))).unwrap();
+        let settings = GameSettings::load(file);
+        assert_eq!((settings.volume, settings.muted, settings.width), (0.5, true, 1280));
+        assert_eq!(settings.camera, CameraMode::Hood);
+        assert_eq!(settings.car.axles, Some((1.2, 0.9)));
+
+        // A broken file isn't overwritten.
+        fs::write(&path, "volume = ").unwrap();
+        assert!(matches!(
+            GameSettings::save(file, |settings| settings.volume = 1.),
+            Err(SettingsError::Parse(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "volume = ");
+    }
+
+    /// Remembers the swap intervals instead of setting them on a GL context.
+    struct FakeContext(Vec<SwapInterval>);
+
+    impl SwapControl for FakeContext {
+        fn set_swap_interval(&mut self, interval: SwapInterval) {
+            self.0.push(interval);
+        }
+    }
+
+    #[test]
+    fn vsync_swap_interval() {
+        let settings: GameSettings = toml::from_str("vsync = false\n").unwrap();
+        assert!(GameSettings::default().vsync);
+        let mut context = FakeContext(Vec::new());
+        set_vsync(&mut context, settings.vsync, settings.fps);
+        set_vsync(&mut context, true, 0);
+        assert_eq!(context.0, vec![SwapInterval::None, SwapInterval::Sync(1)]);
+    }
+}
@@ -0,0 +1,220 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::car::Car;
+use crate::grphx::{Mesh, Shader, ShaderError, Vertex};
+use nalgebra::{Matrix4, Vector3};
+use rand::Rng;
+
+/// A short-lived puff of smoke.
+#[derive(Debug, Copy, Clone)]
+pub struct Particle {
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    /// Time [s] since the particle was emitted.
+    pub age: f32,
+    /// Time [s] until the particle is gone.
+    pub lifetime: f32,
+}
+
+impl Particle {
+    /// Return the opacity, fading from 1.0 to 0.0 over the lifetime.
+    pub fn alpha(&self) -> f32 {
+        (1. - self.age / self.lifetime).max(0.)
+    }
+}
+
+/// All particles of a scene.
+#[derive(Debug, Default)]
+pub struct ParticleSystem {
+    pub particles: Vec<Particle>,
+}
+
+impl ParticleSystem {
+    pub fn new() -> ParticleSystem {
+        Default::default()
+    }
+
+    /// Age and move all particles, removing the ones that faded out.
+    pub fn update(&mut self, dt: f32) {
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.position += particle.velocity * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+    }
+}
+
+/// Emits skid smoke at the rear wheels of a car while it slides.
+#[derive(Debug, Copy, Clone)]
+pub struct SkidEmitter {
+    pub is_enabled: bool,
+    /// The car has to slide at a slip angle above this threshold [rad] to emit smoke.
+    pub slip_threshold: f32,
+    /// Particles per second and wheel.
+    pub rate: f32,
+    pub lifetime: f32,
+    /// Largest random speed [m/s] a particle drifts off in the x-y plane, so the smoke spreads out.
+    pub spread: f32,
+    /// Emitting a fraction of a particle is carried over to the next update.
+    pending: f32,
+}
+
+impl Default for SkidEmitter {
+    fn default() -> SkidEmitter {
+        SkidEmitter {
+            is_enabled: true,
+            slip_threshold: 0.2,
+            rate: 30.,
+            lifetime: 0.8,
+            spread: 0.4,
+            pending: 0.,
+        }
+    }
+}
+
+impl SkidEmitter {
+    /// Return true if `car` slides enough to emit smoke.
+    pub fn is_emitting(&self, car: &Car) -> bool {
+        // Standing cars don't slide, however small a velocity points sideways.
+        self.is_enabled && car.velocity().norm() > 1. && car.slip_angle() > self.slip_threshold
+    }
+
+    /// Emit the particles of a time step `dt` for `car` into `system`.
+    pub fn update(&mut self, car: &Car, dt: f32, system: &mut ParticleSystem, rng: &mut impl Rng) {
+        if !self.is_emitting(car) {
+            self.pending = 0.;
+            return;
+        }
+        self.pending += self.rate * dt;
+        while self.pending >= 1. {
+            self.pending -= 1.;
+            for wheel in &car.rear_wheels() {
+                let mut random = || (rng.gen::<f32>() * 2. - 1.) * self.spread;
+                let drift = Vector3::new(random(), random(), 0.);
+                system.particles.push(Particle {
+                    position: wheel + Vector3::new(0., 0., 0.1),
+                    // The smoke lags behind the car and rises slowly.
+                    velocity: car.velocity() * 0.2 + Vector3::new(0., 0., 0.5) + drift,
+                    age: 0.,
+                    lifetime: self.lifetime,
+                });
+            }
+        }
+    }
+}
+
+/// Renders particles as billboarded quads.
+pub struct ParticleRenderer {
+    mesh: Mesh,
+    /// `None` if the shader failed to load, nothing is drawn then.
+    shader: Option<Shader>,
+    /// Edge length [m] of a particle.
+    pub size: f32,
+}
+
+impl ParticleRenderer {
+    pub fn new() -> ParticleRenderer {
+        let vertex = |x: f32, y: f32| Vertex {
+            position: [x - 0.5, y - 0.5, 0.],
+            normal: [0., 0., 1.],
+            uv: [x, y],
+        };
+        let vertices = vec![vertex(0., 0.), vertex(1., 0.), vertex(1., 1.), vertex(0., 1.)];
+        ParticleRenderer {
+            mesh: Mesh::new(vertices, vec![0, 1, 2, 0, 2, 3]),
+            shader: Shader::for_effect("particle", "particle", "Particles"),
+            size: 0.6,
+        }
+    }
+
+    /// Compile the shader again from its files, a shader that failed to load stays off.
+    pub fn reload_shader(&mut self) -> Result<(), ShaderError> {
+        match &mut self.shader {
+            Some(shader) => shader.reload(),
+            None => Ok(()),
+        }
+    }
+
+    /// Draw all particles of `system` to the bound framebuffer.
+    pub fn draw(&self, system: &ParticleSystem, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        let shader = match &self.shader {
+            Some(shader) => shader,
+            None => return,
+        };
+        unsafe {
+            // Particles are translucent, so they must not hide each other.
+            gl::DepthMask(gl::FALSE);
+            shader.bind();
+            Shader::set_uniform_mat4(1, view);
+            Shader::set_uniform_mat4(2, projection);
+            for particle in &system.particles {
+                let model = Matrix4::new_translation(&particle.position) * Matrix4::new_scaling(self.size);
+                Shader::set_uniform_mat4(0, &model);
+                Shader::set_uniform_float(6, particle.alpha());
+                self.mesh.draw();
+            }
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParticleSystem, SkidEmitter};
+    use crate::game::car::Car;
+    use nalgebra::Vector3;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn sliding_car(lateral: f32) -> Car {
+        let mut car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        car.set_velocity(Vector3::new(lateral, 10., 0.));
+        car
+    }
+
+    #[test]
+    fn emit_above_slip_threshold() {
+        let mut emitter = SkidEmitter::default();
+        let mut system = ParticleSystem::new();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // A slip angle of about 0.1 rad.
+        emitter.update(&sliding_car(1.), 1., &mut system, &mut rng);
+        assert!(system.particles.is_empty());
+
+        // A slip angle of about 0.46 rad.
+        emitter.update(&sliding_car(5.), 1., &mut system, &mut rng);
+        assert_eq!(system.particles.len(), 2 * 30);
+
+        emitter.is_enabled = false;
+        emitter.update(&sliding_car(5.), 1., &mut system, &mut rng);
+        assert_eq!(system.particles.len(), 2 * 30);
+    }
+
+    #[test]
+    fn particles_fade_out() {
+        let mut emitter = SkidEmitter::default();
+        let mut system = ParticleSystem::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        emitter.update(&sliding_car(5.), 0.1, &mut system, &mut rng);
+        assert!(!system.particles.is_empty());
+        assert_eq!(system.particles[0].alpha(), 1.);
+
+        system.update(emitter.lifetime * 0.5);
+        assert!((system.particles[0].alpha() - 0.5).abs() < 1e-5);
+        system.update(emitter.lifetime * 0.5);
+        assert!(system.particles.is_empty());
+    }
+}
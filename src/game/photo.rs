@@ -0,0 +1,101 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::grphx::DebugCamera;
+use crate::util::FixedTimestep;
+use nalgebra::Matrix4;
+
+/// What photo mode changed, to put it back when leaving.
+#[derive(Debug, Clone, Copy)]
+struct Saved {
+    is_paused: bool,
+    debug_camera: Option<DebugCamera>,
+}
+
+/// Freezes the race and frees the camera to take pictures.
+///
+/// The simulation stays paused and the HUD hidden while it is active. Leaving restores the pause
+/// and camera from before, e.g. a race paused in the menu stays paused.
+#[derive(Debug, Default)]
+pub struct PhotoMode {
+    saved: Option<Saved>,
+}
+
+impl PhotoMode {
+    pub fn new() -> PhotoMode {
+        PhotoMode::default()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.saved.is_some()
+    }
+
+    /// Pause the `timestep` and fly a debug camera starting at the current `view`.
+    ///
+    /// No time accumulates while paused, so nothing catches up when the race resumes.
+    /// A debug camera that is already flying is kept where it is.
+    pub fn enter(&mut self, timestep: &mut FixedTimestep, debug_camera: &mut Option<DebugCamera>, view: &Matrix4<f32>) {
+        if self.is_active() {
+            return;
+        }
+        self.saved = Some(Saved {
+            is_paused: timestep.is_paused,
+            debug_camera: *debug_camera,
+        });
+        timestep.is_paused = true;
+        if debug_camera.is_none() {
+            *debug_camera = Some(DebugCamera::from_view(view));
+        }
+    }
+
+    /// Put back the pause and camera from before `enter()`.
+    pub fn exit(&mut self, timestep: &mut FixedTimestep, debug_camera: &mut Option<DebugCamera>) {
+        if let Some(saved) = self.saved.take() {
+            timestep.is_paused = saved.is_paused;
+            *debug_camera = saved.debug_camera;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::FIXED_TIMESTEP;
+
+    #[test]
+    fn restore_pause() {
+        let view = Matrix4::identity();
+        let mut photo = PhotoMode::new();
+        let mut timestep = FixedTimestep::new(FIXED_TIMESTEP);
+        let mut camera = None;
+
+        photo.enter(&mut timestep, &mut camera, &view);
+        assert!(photo.is_active());
+        assert!(timestep.is_paused);
+        assert!(camera.is_some());
+        assert_eq!(timestep.advance(1.), 0);
+        photo.exit(&mut timestep, &mut camera);
+        assert!(!photo.is_active());
+        assert!(!timestep.is_paused);
+        assert!(camera.is_none());
+        // The frozen second doesn't have to be caught up.
+        assert_eq!(timestep.advance(FIXED_TIMESTEP), 1);
+
+        timestep.is_paused = true;
+        photo.enter(&mut timestep, &mut camera, &view);
+        photo.exit(&mut timestep, &mut camera);
+        assert!(timestep.is_paused);
+    }
+}
@@ -0,0 +1,108 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::car::CarState;
+use super::controller::Controller;
+
+/// Driving model moving a car by the input of its player, to try others than the one built into `Car`.
+///
+/// Set it as `Car::physics`, the car then hands its state to `step()` on every update instead of
+/// driving with its own model, and takes back all of the state including the mass. Collisions, damage
+/// and the track still act on the car as usual.
+pub trait VehiclePhysics {
+    /// Advance the `state` of the car by the time step `dt` [s] with the `input` of its player, `None`
+    /// while the car isn't driven, e.g. during the countdown.
+    fn step(&mut self, state: &mut CarState, input: Option<Controller>, dt: f32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::car::Car;
+    use crate::game::controller::ControllerLayout;
+    use nalgebra::Vector3;
+
+    fn test_car() -> Car {
+        Car::test_car(Vector3::new(0., 0., 0.), 1000.0)
+    }
+
+    /// Moves with the throttle straight along y at `speed` [m/s] per unit of throttle.
+    struct Rail {
+        speed: f32,
+    }
+
+    impl VehiclePhysics for Rail {
+        fn step(&mut self, state: &mut CarState, input: Option<Controller>, dt: f32) {
+            let throttle = input.map(|input| input.get_y_axis()).unwrap_or(0.);
+            state.velocity = Vector3::y() * throttle * self.speed;
+            state.position += state.velocity * dt;
+        }
+    }
+
+    /// Turns on the spot with the steering and never moves.
+    struct Turntable;
+
+    impl VehiclePhysics for Turntable {
+        fn step(&mut self, state: &mut CarState, input: Option<Controller>, dt: f32) {
+            state.rotation[2] -= input.map(|input| input.get_x_axis()).unwrap_or(0.) * dt;
+        }
+    }
+
+    /// Burns `rate` [kg/s] of fuel and leaves the rest of the state alone.
+    struct FuelBurn {
+        rate: f32,
+    }
+
+    impl VehiclePhysics for FuelBurn {
+        fn step(&mut self, state: &mut CarState, _: Option<Controller>, dt: f32) {
+            state.mass -= self.rate * dt;
+        }
+    }
+
+    #[test]
+    fn swap_physics() {
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        controller.set_axis(0.5, 1.);
+        let drive = |physics: Option<Box<dyn VehiclePhysics>>| {
+            let mut car = test_car();
+            car.physics = physics;
+            for _ in 0..100 {
+                car.update(0.01, Some(controller));
+            }
+            car
+        };
+
+        let rail = drive(Some(Box::new(Rail { speed: 10. })));
+        assert!((rail.position - Vector3::new(0., 10., 0.)).norm() < 1e-4);
+        assert_eq!(rail.rotation[2], 0.);
+        let turntable = drive(Some(Box::new(Turntable)));
+        assert_eq!(turntable.position, Vector3::new(0., 0., 0.));
+        assert!((turntable.rotation[2] + 0.5).abs() < 1e-4);
+
+        // The car's own model accelerates and steers at once.
+        let built_in = drive(None);
+        assert!((built_in.position - rail.position).norm() > 1.);
+        assert!(built_in.rotation[2] != turntable.rotation[2]);
+    }
+
+    #[test]
+    fn take_back_mass() {
+        let mut car = test_car();
+        car.physics = Some(Box::new(FuelBurn { rate: 2. }));
+        for _ in 0..100 {
+            car.update(0.01, None);
+        }
+        assert!((car.state().mass - 998.).abs() < 0.01);
+    }
+}
@@ -0,0 +1,414 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::car::Car;
+use super::controller::{Controller, ControllerLayout};
+use super::simulation::Simulation;
+use super::track::TrackLayout;
+use log::debug;
+use nalgebra::Vector3;
+use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// Version of the `Run` file format, runs of other versions aren't loaded.
+pub const RUN_VERSION: u32 = 1;
+
+/// Input of a controller during a single simulation step.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub x: f32,
+    pub y: f32,
+    pub boost: bool,
+    pub handbrake: bool,
+}
+
+impl InputFrame {
+    /// Take a snapshot of the current input of a `controller`.
+    pub fn from_controller(controller: &Controller) -> InputFrame {
+        InputFrame {
+            x: controller.get_x_axis(),
+            y: controller.get_y_axis(),
+            boost: controller.get_boost(),
+            handbrake: controller.get_handbrake(),
+        }
+    }
+
+    /// Return a controller with exactly this input.
+    pub fn to_controller(self) -> Controller {
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        controller.set_axis(self.x, self.y);
+        controller.set_boost(self.boost);
+        controller.set_handbrake(self.handbrake);
+        controller
+    }
+}
+
+/// Input stream of a single car, recorded with a fixed `timestep` [s].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub timestep: f32,
+    pub frames: Vec<InputFrame>,
+}
+
+impl Recording {
+    /// Load a recording from a json file.
+    pub fn load(path: &Path) -> serde_json::Result<Recording> {
+        debug!("Loading recording from {}", path.display());
+        let file = File::open(path).map_err(serde_json::Error::io)?;
+        serde_json::from_reader(BufReader::new(file))
+    }
+
+    /// Save the recording to a json file.
+    pub fn save(&self, path: &Path) -> serde_json::Result<()> {
+        debug!("Saving {} frames to {}", self.frames.len(), path.display());
+        let file = File::create(path).map_err(serde_json::Error::io)?;
+        serde_json::to_writer(BufWriter::new(file), self)
+    }
+}
+
+/// Physical setup of the recorded car, a run only plays out the same with the same car.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CarConfig {
+    pub half_extents: Vector3<f32>,
+    pub mass: f32,
+    pub engine_force: f32,
+    pub max_speed: f32,
+    pub drag_coefficient: f32,
+    pub rolling_resistance: f32,
+    pub tire_grip: f32,
+    pub arcade_drift: bool,
+}
+
+impl CarConfig {
+    /// Return the setup of `car`.
+    pub fn of(car: &Car) -> CarConfig {
+        CarConfig {
+            half_extents: *car.cuboid.half_extents(),
+            mass: car.state().mass,
+            engine_force: car.engine_force,
+            max_speed: car.max_speed,
+            drag_coefficient: car.drag_coefficient,
+            rolling_resistance: car.rolling_resistance,
+            tire_grip: car.tire_grip,
+            arcade_drift: car.arcade_drift,
+        }
+    }
+}
+
+/// A `Recording` with everything else needed to replay it the same anywhere, e.g. to share a speedrun.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Run {
+    /// Version of the file format, see `RUN_VERSION`.
+    pub version: u32,
+    /// Id of the map the run was driven on.
+    pub map: u32,
+    /// Seed of the random numbers of the race.
+    pub seed: u64,
+    /// Parts of every simulation step.
+    pub substeps: u32,
+    pub car: CarConfig,
+    pub recording: Recording,
+}
+
+/// Error while loading a `Run` or checking it against the race it is replayed in.
+#[derive(Debug)]
+pub enum RunError {
+    /// The file could not be read or written, or it is no run.
+    Json(serde_json::Error),
+    /// The file was written in another version of the format.
+    Version { found: u32 },
+    /// The run was driven on another map.
+    Map { run: u32, race: u32 },
+    /// The run was driven with another car.
+    Car,
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Json(e) => write!(f, "invalid run file: {}", e),
+            RunError::Version { found } => write!(f, "run file version {} is not supported, expected {}", found, RUN_VERSION),
+            RunError::Map { run, race } => write!(f, "run was driven on map {}, not on map {}", run, race),
+            RunError::Car => write!(f, "run was driven with a different car setup"),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+impl From<serde_json::Error> for RunError {
+    fn from(e: serde_json::Error) -> RunError {
+        RunError::Json(e)
+    }
+}
+
+impl Run {
+    /// Create a run of the `recording` of a car set up like `car` on `map`, with the `seed` and `substeps`
+    /// of the race.
+    pub fn new(map: u32, seed: u64, substeps: u32, car: CarConfig, recording: Recording) -> Run {
+        Run {
+            version: RUN_VERSION,
+            map,
+            seed,
+            substeps,
+            car,
+            recording,
+        }
+    }
+
+    /// Return true if `path` is named like a run file, `*.run`. Other files hold a bare `Recording`.
+    pub fn is_run_file(path: &Path) -> bool {
+        path.extension() == Some(OsStr::new("run"))
+    }
+
+    /// Load a run from a file, runs of another `RUN_VERSION` are an error.
+    pub fn load(path: &Path) -> Result<Run, RunError> {
+        debug!("Loading run from {}", path.display());
+        let file = File::open(path).map_err(serde_json::Error::io)?;
+        Run::from_reader(BufReader::new(file))
+    }
+
+    /// Save the run to a file.
+    pub fn save(&self, path: &Path) -> Result<(), RunError> {
+        debug!("Saving run of {} frames to {}", self.recording.frames.len(), path.display());
+        let file = File::create(path).map_err(serde_json::Error::io)?;
+        Ok(serde_json::to_writer(BufWriter::new(file), self)?)
+    }
+
+    /// Read a run, the version is checked before the rest, whose format may have changed.
+    fn from_reader<R: std::io::Read>(reader: R) -> Result<Run, RunError> {
+        let value: serde_json::Value = serde_json::from_reader(reader)?;
+        let found = value.get("version").and_then(serde_json::Value::as_u64).unwrap_or(0) as u32;
+        if found != RUN_VERSION {
+            return Err(RunError::Version { found });
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Check that the run was driven on `map` with a car set up like `car`, so it plays out the same.
+    pub fn check(&self, map: u32, car: &CarConfig) -> Result<(), RunError> {
+        if self.map != map {
+            return Err(RunError::Map { run: self.map, race: map });
+        }
+        if self.car != *car {
+            return Err(RunError::Car);
+        }
+        Ok(())
+    }
+}
+
+/// Records the input of a controller once per simulation step.
+pub struct Recorder {
+    recording: Recording,
+}
+
+impl Recorder {
+    /// Create a new recorder for a simulation stepped with `timestep` [s].
+    pub fn new(timestep: f32) -> Recorder {
+        Recorder {
+            recording: Recording {
+                timestep,
+                frames: Vec::new(),
+            },
+        }
+    }
+
+    /// Store the input of a step. Call this with the controller passed to `Car::update`.
+    pub fn record(&mut self, controller: &Controller) {
+        self.recording.frames.push(InputFrame::from_controller(controller));
+    }
+
+    /// Stop recording and return the input stream.
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+/// Feeds a recorded input stream back step by step.
+pub struct Playback {
+    recording: Recording,
+    step: usize,
+}
+
+impl Playback {
+    pub fn new(recording: Recording) -> Playback {
+        Playback { recording, step: 0 }
+    }
+
+    /// Return the controller for the next step, or `None` after the last recorded step.
+    pub fn next_controller(&mut self) -> Option<Controller> {
+        let frame = self.recording.frames.get(self.step)?;
+        self.step += 1;
+        Some(frame.to_controller())
+    }
+}
+
+/// A car driven by a recording, e.g. the best run of a time trial.
+///
+/// The ghost races alone in a `Simulation` of its own, so it drives the track of `layout` in the same
+/// `substeps`, slopes and respawns as the recorded car did, but other cars drive through it.
+pub struct Ghost {
+    simulation: Simulation,
+    playback: Playback,
+}
+
+impl Ghost {
+    /// Create a ghost that drives `car` with the input of `recording` on the track of `layout`.
+    pub fn new(car: Car, recording: Recording, layout: Option<TrackLayout>, substeps: u32) -> Ghost {
+        let mut simulation = Simulation::new(vec![car], layout);
+        simulation.substeps = substeps;
+        Ghost {
+            simulation,
+            playback: Playback::new(recording),
+        }
+    }
+
+    pub fn car(&self) -> &Car {
+        &self.simulation.cars[0]
+    }
+
+    pub fn car_mut(&mut self) -> &mut Car {
+        &mut self.simulation.cars[0]
+    }
+
+    /// Step the simulation of the ghost with the input of the next step. It rolls out after the last one.
+    pub fn update(&mut self, dt: f32) {
+        let controller: Vec<Controller> = self.playback.next_controller().into_iter().collect();
+        self.simulation.step(dt, &controller);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CarConfig, Ghost, Playback, Recorder, Recording, Run, RunError, RUN_VERSION};
+    use crate::game::car::Car;
+    use crate::game::controller::{Controller, ControllerLayout};
+    use crate::game::simulation::Simulation;
+    use crate::game::track::TrackLayout;
+    use crate::game::FIXED_TIMESTEP;
+    use nalgebra::Vector3;
+    use std::env;
+
+    fn test_car() -> Car {
+        Car::test_car(Vector3::new(0., 0., 0.), 1000.0)
+    }
+
+    /// Record a car driving a slalom with a handbrake turn at the end.
+    fn record_slalom() -> (Recording, Car) {
+        let mut car = test_car();
+        let mut recorder = Recorder::new(FIXED_TIMESTEP);
+        let mut controller = Controller::new(true, &ControllerLayout::WASD);
+        for step in 0..600 {
+            let steer = if (step / 100) % 2 == 0 { 1. } else { -1. };
+            controller.set_axis(steer, 1.);
+            controller.set_boost(step < 200);
+            controller.set_handbrake(step > 500);
+            recorder.record(&controller);
+            car.update(FIXED_TIMESTEP, Some(controller));
+        }
+        (recorder.finish(), car)
+    }
+
+    #[test]
+    fn replay_reproduces_trajectory() {
+        let (recording, recorded_car) = record_slalom();
+        assert_eq!(recording.frames.len(), 600);
+
+        let mut car = test_car();
+        let mut playback = Playback::new(recording);
+        while let Some(controller) = playback.next_controller() {
+            car.update(FIXED_TIMESTEP, Some(controller));
+        }
+        assert_eq!(playback.step, playback.recording.frames.len());
+        assert_eq!(car.state(), recorded_car.state());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (recording, _) = record_slalom();
+        let path = env::temp_dir().join("carambolage-recording.json");
+        recording.save(&path).unwrap();
+        assert_eq!(Recording::load(&path).unwrap(), recording);
+
+        assert!(Recording::load(&env::temp_dir().join("carambolage-no-recording.json")).is_err());
+    }
+
+    #[test]
+    fn save_and_load_run() {
+        let (recording, _) = record_slalom();
+        let mut car = test_car();
+        car.arcade_drift = true;
+        let run = Run::new(2, 42, 4, CarConfig::of(&car), recording);
+        let path = env::temp_dir().join("carambolage-recording.run");
+        assert!(Run::is_run_file(&path));
+        run.save(&path).unwrap();
+        let loaded = Run::load(&path).unwrap();
+        assert_eq!(loaded, run);
+        assert!(loaded.check(2, &CarConfig::of(&car)).is_ok());
+
+        // Another map or another car wouldn't drive the same.
+        assert!(matches!(
+            loaded.check(1, &CarConfig::of(&car)),
+            Err(RunError::Map { run: 2, race: 1 })
+        ));
+        assert!(matches!(loaded.check(2, &CarConfig::of(&test_car())), Err(RunError::Car)));
+
+        // Files of other versions are rejected before their content is parsed.
+        let mut future = serde_json::to_value(&run).unwrap();
+        future["version"] = (RUN_VERSION + 1).into();
+        future["recording"] = "not a recording".into();
+        std::fs::write(&path, future.to_string()).unwrap();
+        let found = RUN_VERSION + 1;
+        assert!(matches!(Run::load(&path), Err(RunError::Version { found: v }) if v == found));
+        std::fs::write(&path, "{}").unwrap();
+        assert!(matches!(Run::load(&path), Err(RunError::Version { found: 0 })));
+        assert!(!Run::is_run_file(&env::temp_dir().join("carambolage-recording.json")));
+    }
+
+    #[test]
+    fn ghost_follows_recording() {
+        // A slope and the walls of a narrow track, split into substeps.
+        let layout = TrackLayout::from_json(
+            r#"{
+                "boundaries": [[[-6, -10, 0], [6, -10, 0], [6, 100, 0], [-6, 100, 0], [-6, -10, 0]]],
+                "spawns": [{ "position": [0, 0, 0.5], "yaw": 0 }],
+                "slopes": [{ "min": [-6, 20, 0], "max": [6, 60, 0], "normal": [0, -0.3, 1] }]
+            }"#,
+        )
+        .unwrap();
+        let mut simulation = Simulation::new(vec![test_car()], Some(layout.clone()));
+        simulation.substeps = 3;
+        let mut recorder = Recorder::new(FIXED_TIMESTEP);
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        let mut positions = Vec::new();
+        for step in 0..300 {
+            controller.set_axis(if step < 150 { -0.5 } else { 1. }, 1.);
+            recorder.record(&controller);
+            simulation.step(FIXED_TIMESTEP, &[controller]);
+            positions.push(simulation.cars[0].position);
+        }
+
+        let mut ghost = Ghost::new(test_car(), recorder.finish(), Some(layout), 3);
+        for position in positions {
+            ghost.update(FIXED_TIMESTEP);
+            assert_eq!(ghost.car().position, position);
+        }
+    }
+}
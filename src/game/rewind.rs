@@ -0,0 +1,114 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::car::{Car, CarState};
+use std::collections::VecDeque;
+
+/// Recent states of the cars, captured every simulation step, to turn back time in practice.
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    /// States of all cars per step, the oldest first.
+    snapshots: VecDeque<Vec<CarState>>,
+    /// Number of steps kept, older ones are dropped.
+    pub capacity: usize,
+}
+
+impl Rewind {
+    /// Create a rewind buffer for `seconds` [s] of history of a simulation stepped by `timestep` [s].
+    pub fn new(seconds: f32, timestep: f32) -> Rewind {
+        let capacity = (seconds / timestep).round().max(1.) as usize;
+        Rewind {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Capture the state of the `cars` after a simulation step.
+    pub fn record(&mut self, cars: &[Car]) {
+        if self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(cars.iter().map(Car::state).collect());
+    }
+
+    /// Put the `cars` back to where they were one step earlier.
+    ///
+    /// Returns false once the oldest state is reached, the cars stay there then.
+    pub fn rewind(&mut self, cars: &mut [Car]) -> bool {
+        let is_rewound = self.snapshots.len() > 1;
+        if is_rewound {
+            self.snapshots.pop_back();
+        }
+        if let Some(states) = self.snapshots.back() {
+            for (car, state) in cars.iter_mut().zip(states) {
+                car.set_state(state);
+            }
+        }
+        is_rewound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rewind;
+    use crate::game::car::Car;
+    use crate::game::controller::{Controller, ControllerLayout};
+    use crate::game::FIXED_TIMESTEP;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn rewind_to_snapshot() {
+        let mut cars = vec![Car::test_car(Vector3::new(0., 0., 0.), 1000.0)];
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        controller.set_axis(0.5, 1.);
+        let mut rewind = Rewind::new(1., FIXED_TIMESTEP);
+
+        let mut halfway = None;
+        for step in 0..rewind.capacity {
+            cars[0].update(FIXED_TIMESTEP, Some(controller));
+            rewind.record(&cars);
+            if step == rewind.capacity / 2 {
+                halfway = Some(cars[0].state());
+            }
+        }
+        assert_eq!(rewind.snapshots.len(), rewind.capacity);
+
+        // Back to the middle of the drive.
+        for _ in 0..rewind.capacity - 1 - rewind.capacity / 2 {
+            assert!(rewind.rewind(&mut cars));
+        }
+        assert_eq!(Some(cars[0].state()), halfway);
+
+        // The history only goes back so far.
+        while rewind.rewind(&mut cars) {}
+        assert_eq!(rewind.snapshots.len(), 1);
+        assert!(cars[0].position.norm() > 0.);
+        let oldest = cars[0].state();
+        assert!(!rewind.rewind(&mut cars));
+        assert_eq!(cars[0].state(), oldest);
+    }
+
+    #[test]
+    fn drop_oldest_states() {
+        let mut cars = vec![Car::test_car(Vector3::new(0., 0., 0.), 1000.0)];
+        let mut rewind = Rewind::new(0.05, FIXED_TIMESTEP);
+        for step in 0..20 {
+            cars[0].position[1] = step as f32;
+            rewind.record(&cars);
+        }
+        assert_eq!(rewind.snapshots.len(), rewind.capacity);
+        while rewind.rewind(&mut cars) {}
+        assert_eq!(cars[0].position[1], (20 - rewind.capacity) as f32);
+    }
+}
@@ -12,152 +12,564 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
-use super::car::Car;
+use super::ai::AiController;
+use super::car::{Car, CarBuilder, CarTuning};
 use super::controller::Controller;
+use super::debug_lines::{DebugLineRenderer, DebugLines};
+use super::grid::StartGrid;
+use super::ground::Ground;
 use super::level::Level;
-use crate::grphx::Camera;
-use nalgebra::{inf, sup, Isometry3, Matrix4, Vector3};
+use super::particles::{ParticleRenderer, ParticleSystem, SkidEmitter};
+use super::replay::{Ghost, Recording};
+use super::rewind::Rewind;
+use super::score::Score;
+use super::shadow::ShadowBlob;
+use super::simulation::{CollisionEvent, Simulation};
+use super::skidmarks::{SkidMarkRenderer, SkidMarks};
+use super::track::{Spawn, TrackLayout};
+use super::{SplitScreen, FIXED_TIMESTEP};
+use crate::grphx::{
+    split_viewports, Camera, CameraMode, ChaseCamera, DebugCamera, DirectionalLight, Frustum, Model, ShaderError, Viewport, BASE_FOV,
+    NEAR_PLANE,
+};
+use log::{error, warn};
+use nalgebra::{inf, sup, Matrix4, Vector3};
 use ncollide3d::query;
+use std::sync::mpsc::Receiver;
+
+/// Edge length [m] of the ground around the track.
+const GROUND_SIZE: f32 = 600.;
+/// Edge length [m] of a ground texture tile.
+const GROUND_TILE_SIZE: f32 = 8.;
+
+/// Opacity of a ghost car.
+const GHOST_ALPHA: f32 = 0.4;
+
+/// History [s] that can be rewound in practice.
+const REWIND_SECONDS: f32 = 5.;
+
+/// Color palette id and RGB tint of the cars in spawn order, player 1 is red, player 2 blue and so on.
+/// The colors repeat after the last one.
+const CAR_COLORS: [(u32, [f32; 3]); 7] = [
+    (6, [1., 0.3, 0.3]),
+    (1, [0.3, 0.5, 1.]),
+    (2, [0.3, 1., 0.3]),
+    (7, [1., 1., 0.3]),
+    (4, [1., 0.6, 0.2]),
+    (5, [0.7, 0.3, 1.]),
+    (3, [0.3, 1., 1.]),
+];
 
 /// Main application Scene.
 ///
 /// This scene consists of `GameObject`s, an `Environment` and a main `Camera`.
 pub struct Scene {
-    pub cars: Vec<Car>,
+    /// Cars and track physics, independent of the rendering. The cars of the players come first, then
+    /// the ones of the computer players.
+    pub simulation: Simulation,
+    /// Number of local players, the only ones with a view of their own.
+    pub players: usize,
     pub level: Level,
+    pub ground: Ground,
     pub camera: Camera,
+    /// Camera behind the first car, used instead of `camera` if `is_chase_camera` is set.
+    pub chase_camera: ChaseCamera,
+    pub is_chase_camera: bool,
+    /// Split the window between the players, each with a camera of `split_cameras`.
+    pub split_screen: SplitScreen,
+    /// Camera behind each car for the split screen.
+    pub split_cameras: Vec<ChaseCamera>,
+    /// Free camera, used instead of the other cameras while it is set.
+    pub debug_camera: Option<DebugCamera>,
+    /// Sun shining on the level and the cars.
+    pub light: DirectionalLight,
+    /// Recorded run racing alongside the first car.
+    pub ghost: Option<Ghost>,
+    /// Skid smoke of each car.
+    pub skid_emitters: Vec<SkidEmitter>,
+    /// Shadows of the cars and the ghost.
+    pub shadow: ShadowBlob,
+    /// Tire marks of the cars.
+    pub skid_marks: SkidMarks,
+    skid_mark_renderer: SkidMarkRenderer,
+    /// Points of the cars for hitting each other.
+    pub score: Score,
+    /// Recent states of the cars, only in practice with a single player and no ghost.
+    pub rewind: Option<Rewind>,
+    /// Draw the bounding boxes, walls, waypoints and racing line on top of the scene.
+    pub is_debug_lines: bool,
+    debug_line_renderer: DebugLineRenderer,
+    collisions: Receiver<CollisionEvent>,
+    /// Tuning of all cars, the ghost included.
+    tuning: CarTuning,
+    particles: ParticleSystem,
+    particle_renderer: ParticleRenderer,
+    /// Draw cars that look alike with one draw call, turned off if its shader fails to load.
+    is_instancing: bool,
 }
 
 impl Scene {
-    /// Create a new scene. Choose a map via id.
-    pub fn new(map_id: u32) -> Scene {
-        let mut cars = Vec::new();
-        for i in 0..2 {
-            if i % 2 == 0 {
-                cars.push(Car::new(
-                    &Car::model_from_id(3),
-                    &Car::color_from_id(1),
-                    Vector3::new(-1.15, -1.7 * i as f32, 0.5),
-                    1.0,
-                ));
-            } else {
-                cars.push(Car::new(
-                    &Car::model_from_id(4),
-                    &Car::color_from_id(6),
-                    Vector3::new(1.15, -1.7 * i as f32, 0.5),
-                    1.0,
-                ));
-            }
-        }
-
+    /// Create a new scene with a car for each of the `players` and the `opponents`. Choose a map via id.
+    ///
+    /// The opponents are computer players following the waypoints of the track, there are none on tracks
+    /// without waypoints. All cars are set up with the `tuning`.
+    pub fn new(map_id: u32, players: usize, opponents: usize, tuning: CarTuning) -> Scene {
         // Choose the level according to an id.
-        let level = match map_id {
-            1 => Level::new("maps/race_track_1"),
-            2 => Level::new("maps/race_track_2"),
-            _ => Level::new("maps/race_track_1"),
+        let file = match map_id {
+            1 => "maps/race_track_1",
+            2 => "maps/race_track_2",
+            _ => "maps/race_track_1",
         };
+        let level = Level::new(file);
+        let layout = Level::load_layout(file);
+        let waypoints = layout.as_ref().map(|layout| layout.waypoints.clone()).unwrap_or_default();
+        let opponents = if waypoints.is_empty() { 0 } else { opponents };
+        let cars = (0..players + opponents)
+            .map(|player| spawn_car(player, layout.as_ref(), &tuning))
+            .collect::<Vec<_>>();
+        let skid_emitters = vec![Default::default(); cars.len()];
         let camera = Camera::new();
+        let mut chase_camera = ChaseCamera::new();
+        if let Some(car) = cars.first() {
+            chase_camera.set_target(car);
+        }
 
-        Scene { cars, level, camera }
-    }
+        let split_cameras = cars
+            .iter()
+            .take(players)
+            .map(|car| {
+                let mut camera = ChaseCamera::new();
+                camera.set_target(car);
+                camera
+            })
+            .collect();
 
-    /// Update the scene.
-    pub fn update(&mut self, dt: f32, controller: &[Controller]) {
-        // User Input
-        for (id, car) in &mut self.cars.iter_mut().enumerate() {
-            if id < controller.len() {
-                car.update(dt, Some(controller[id]));
+        let score = Score::new(cars.len());
+        let mut simulation = Simulation::new(cars, layout);
+        simulation.drivers = (0..players)
+            .map(|_| None)
+            .chain((0..opponents).map(|_| Some(AiController::with_racing_line(waypoints.clone()))))
+            .collect();
+        let collisions = simulation.subscribe();
+
+        Scene {
+            simulation,
+            players,
+            level,
+            ground: Ground::new(GROUND_SIZE, GROUND_TILE_SIZE, "ground.png"),
+            camera,
+            chase_camera,
+            is_chase_camera: players == 1,
+            split_screen: SplitScreen::Off,
+            split_cameras,
+            debug_camera: None,
+            light: Default::default(),
+            ghost: None,
+            skid_emitters,
+            shadow: ShadowBlob::new(),
+            skid_marks: SkidMarks::new(),
+            skid_mark_renderer: SkidMarkRenderer::new(),
+            score,
+            rewind: if players == 1 {
+                Some(Rewind::new(REWIND_SECONDS, FIXED_TIMESTEP))
             } else {
-                car.update(dt, None);
-            }
+                None
+            },
+            is_debug_lines: false,
+            debug_line_renderer: DebugLineRenderer::new(),
+            collisions,
+            tuning,
+            particles: ParticleSystem::new(),
+            particle_renderer: ParticleRenderer::new(),
+            is_instancing: true,
         }
+    }
 
-        self.update_collisions(dt);
-        self.update_scene_camera(dt);
+    /// Add a ghost at the start of the first car, which drives the `recording` from now on.
+    pub fn set_ghost(&mut self, recording: Recording) {
+        if (recording.timestep - FIXED_TIMESTEP).abs() > f32::EPSILON {
+            warn!(
+                "Ghost was recorded with a time step of {}s and will drive off course",
+                recording.timestep
+            );
+        }
+        let car = spawn_car(0, self.simulation.layout.as_ref(), &self.tuning);
+        let (layout, substeps) = (self.simulation.layout.clone(), self.simulation.substeps);
+        self.ghost = Some(Ghost::new(car, recording, layout, substeps));
+        // The ghost can't drive backwards.
+        self.rewind = None;
     }
 
-    /// Calculate and solve collisions.
-    fn update_collisions(&mut self, dt: f32) {
-        // Update physics/position
-        let mut car_pos = Vec::with_capacity(self.cars.len());
-        for car in &self.cars {
-            car_pos.push(Isometry3::new(car.position, car.rotation));
-        }
-
-        // The whole collision detection is stupid right now. I have learned a lot during my work on this game and
-        // the way it is will do the job. I just want to finish the game so it feels "round" and continue with another
-        // project. Sorry ¯\_(ツ)_/¯
-        let prediction = 0.0;
-        // Cars with cars
-        for i in 0..car_pos.len() {
-            for j in i + 1..car_pos.len() {
-                // Most stupid collision detection, but we'll have a world an maybe max 8 cars, wo who cares.
-                let penetrate = query::contact(&car_pos[i], &self.cars[i].cuboid, &car_pos[j], &self.cars[j].cuboid, prediction);
-                if penetrate.is_some() {
-                    let pen = penetrate.unwrap();
-                    let w1 = pen.world1;
-                    let w2 = pen.world2;
-                    let dir = w1 - w2;
-                    self.cars[i].position -= dir * 0.5;
-                    self.cars[j].position += dir * 0.5;
-                }
-            }
+    /// Update the scene.
+    pub fn update(&mut self, dt: f32, controller: &[Controller]) {
+        step_race(&mut self.simulation, self.ghost.as_mut(), &self.level, dt, controller);
+        if let Some(rewind) = &mut self.rewind {
+            rewind.record(&self.simulation.cars);
+        }
+        self.score.update(dt);
+        for event in self.collisions.try_iter() {
+            self.score.add(&event);
         }
 
-        // Cars with level
-        for (i, cp) in car_pos.iter().enumerate() {
-            self.cars[i].position[2] -= 0.81 * dt;
+        self.particles.update(dt);
+        self.skid_marks.update(&self.simulation.cars, dt);
+        for (car, emitter) in self.simulation.cars.iter().zip(self.skid_emitters.iter_mut()) {
+            emitter.update(car, dt, &mut self.particles, &mut self.simulation.rng);
+        }
 
-            let penetrate_ground = query::contact(&cp, &self.cars[i].cuboid, &self.level.ground.0, &self.level.ground.1, prediction);
-            if penetrate_ground.is_some() {
-                let pen = penetrate_ground.unwrap();
-                let w1 = pen.world1;
-                let w2 = pen.world2;
-                let dir = w1 - w2;
-                self.cars[i].position -= dir;
-            };
+        self.update_scene_camera(dt);
+    }
 
-            let penetrate_border = query::contact(&cp, &self.cars[i].cuboid, &self.level.border.0, &self.level.border.1, prediction);
-            if penetrate_border.is_some() {
-                let pen = penetrate_border.unwrap();
-                let w1 = pen.world1;
-                let w2 = pen.world2;
-                let dir = w1 - w2;
-                self.cars[i].position -= dir;
-            };
+    /// Put the cars back by one step of `dt` [s] instead of updating the scene, in practice only.
+    ///
+    /// Returns false if there is nothing to rewind, the scene isn't changed then.
+    pub fn rewind(&mut self, dt: f32) -> bool {
+        let is_rewound = match &mut self.rewind {
+            Some(rewind) => rewind.rewind(&mut self.simulation.cars),
+            None => false,
+        };
+        if !is_rewound {
+            return false;
         }
+        self.particles.update(dt);
+        self.update_scene_camera(dt);
+        true
+    }
+
+    /// Return true if every car completed all laps. Races on tracks without a finish line never end.
+    pub fn is_race_finished(&self) -> bool {
+        self.simulation.is_race_finished()
     }
 
     /// Calculate the position the camera should move to.
     fn update_scene_camera(&mut self, dt: f32) {
-        let camera_focus = if self.cars.is_empty() {
+        let camera_focus = if self.simulation.cars.is_empty() {
             Vector3::new(0., 0., 0.)
         } else {
-            let mut min = self.cars[0].position;
-            let mut max = self.cars[0].position;
+            let mut min = self.simulation.cars[0].position;
+            let mut max = self.simulation.cars[0].position;
             let mut lerp_pos = Vector3::new(0., 0., 0.);
-            for car in &self.cars {
+            for car in &self.simulation.cars {
                 lerp_pos += car.position;
-                min = inf(&min, &car.position);
-                max = sup(&max, &car.position);
+                let extents = car.bounding_box().world_half_extents();
+                min = inf(&min, &(car.position - extents));
+                max = sup(&max, &(car.position + extents));
             }
-            lerp_pos /= self.cars.len() as f32;
+            lerp_pos /= self.simulation.cars.len() as f32;
             let camera_distance = (max - min).norm() * 1.20 + 10.0;
             self.camera.move_to_height(camera_distance);
             lerp_pos
         };
         self.camera.move_to_focus(camera_focus);
         self.camera.update(dt);
+
+        if let Some(car) = self.simulation.cars.first() {
+            self.chase_camera.update(car, dt);
+            if let Some(layout) = &self.simulation.layout {
+                self.chase_camera.avoid_walls(car, &layout.track);
+            }
+        }
+        if self.split_screen != SplitScreen::Off {
+            for (car, camera) in self.simulation.cars.iter().zip(self.split_cameras.iter_mut()) {
+                camera.update(car, dt);
+                if let Some(layout) = &self.simulation.layout {
+                    camera.avoid_walls(car, &layout.track);
+                }
+            }
+        }
+    }
+
+    /// Compile all shaders of the scene again from their files, e.g. after editing them.
+    ///
+    /// Shaders that fail keep their previous program and the errors are logged.
+    pub fn reload_shaders(&mut self) {
+        let mut results = vec![self.ground.reload_shaders(), self.level.reload_shaders()];
+        let cars = self.simulation.cars.iter_mut().chain(self.ghost.as_mut().map(Ghost::car_mut));
+        results.extend(cars.filter_map(|car| car.model.as_mut()).map(Model::reload_shaders));
+        results.push(self.shadow.reload_shader());
+        results.push(self.skid_mark_renderer.reload_shader());
+        results.push(self.particle_renderer.reload_shader());
+        results.push(self.debug_line_renderer.reload_shader());
+
+        // The cars share their shader files, so report each error once.
+        let mut errors = results
+            .into_iter()
+            .filter_map(Result::err)
+            .map(|e: ShaderError| e.to_string())
+            .collect::<Vec<_>>();
+        errors.dedup();
+        for e in errors {
+            error!("Shader reload failed, keeping the previous program: {}", e);
+        }
+    }
+
+    /// Return the part of a window of `width` x `height` [px] each player is drawn into.
+    ///
+    /// The window is only split for several players and not while the debug camera flies around.
+    pub fn viewports(&self, width: u32, height: u32) -> Vec<Viewport> {
+        if self.debug_camera.is_some() {
+            return vec![Viewport::full(width, height)];
+        }
+        split_viewports(self.players, width, height, self.split_screen)
+    }
+
+    /// Return the camera behind the car of `player` if the window is split, see `viewports()`.
+    fn split_camera(&self, player: usize) -> Option<&ChaseCamera> {
+        if self.debug_camera.is_some() || self.split_screen == SplitScreen::Off || self.split_cameras.len() < 2 {
+            return None;
+        }
+        self.split_cameras.get(player)
+    }
+
+    /// Return the view matrix of the viewport of `player`.
+    pub fn view_of(&self, player: usize) -> Matrix4<f32> {
+        match self.split_camera(player) {
+            Some(camera) => camera.view_matrix(),
+            None => self.view(),
+        }
+    }
+
+    /// Return the vertical field of view [rad] of the viewport of `player`.
+    pub fn fov_of(&self, player: usize) -> f32 {
+        match self.split_camera(player) {
+            Some(camera) => camera.fov(),
+            None => self.fov(),
+        }
     }
 
-    /// Draw the entire `Scene` to the bound framebuffer.
-    pub fn draw(&mut self, projection: &Matrix4<f32>) {
-        let view = self.camera.get_viewmatrix();
+    /// Return the focus distance [m] of the viewport of `player`.
+    pub fn focus_distance_of(&self, player: usize) -> f32 {
+        match self.split_camera(player) {
+            Some(camera) => camera.focus_distance(),
+            None => self.focus_distance(),
+        }
+    }
+
+    /// Return the near plane [m] of the viewport of `player`.
+    pub fn near_plane_of(&self, player: usize) -> f32 {
+        match self.split_camera(player) {
+            Some(camera) => camera.near_plane(),
+            None if self.debug_camera.is_none() && self.is_chase_camera => self.chase_camera.near_plane(),
+            None => NEAR_PLANE,
+        }
+    }
+
+    /// View the cars of the players from `mode`, e.g. from the hood.
+    pub fn set_camera_mode(&mut self, mode: CameraMode) {
+        self.chase_camera.mode = mode;
+        for camera in &mut self.split_cameras {
+            camera.mode = mode;
+        }
+    }
+
+    /// Return the view matrix of the camera in use.
+    pub fn view(&self) -> Matrix4<f32> {
+        if let Some(camera) = &self.debug_camera {
+            camera.view_matrix()
+        } else if self.is_chase_camera {
+            self.chase_camera.view_matrix()
+        } else {
+            self.camera.get_viewmatrix()
+        }
+    }
+
+    /// Return the distance [m] to the point the camera looks at, the debug camera keeps the one of the
+    /// camera before it.
+    pub fn focus_distance(&self) -> f32 {
+        if self.is_chase_camera {
+            self.chase_camera.focus_distance()
+        } else {
+            self.camera.focus_distance()
+        }
+    }
+
+    /// Return the vertical field of view [rad] of the camera in use.
+    pub fn fov(&self) -> f32 {
+        if self.debug_camera.is_none() && self.is_chase_camera {
+            self.chase_camera.fov()
+        } else {
+            BASE_FOV
+        }
+    }
+
+    /// Draw the entire `Scene` as seen in the viewport of `player` to the bound framebuffer.
+    ///
+    /// The cars are drawn `interpolation` of the way from the previous to the current simulation step.
+    pub fn draw(&mut self, player: usize, projection: &Matrix4<f32>, interpolation: f32) {
+        let view = self.view_of(player);
+        let frustum = Frustum::from_matrix(&(projection * view));
         // Draw map.
-        self.level.draw(&view, &projection);
-        // Draw objects.
-        for i in 0..self.cars.len() {
-            self.cars[i].draw(&view, &projection);
+        self.ground.draw(&view, projection, &self.light);
+        self.level.draw(&view, &projection, &self.light);
+        self.skid_mark_renderer.draw(&self.skid_marks, &view, projection);
+        let shadow_casters = self.simulation.cars.iter().chain(self.ghost.as_ref().map(Ghost::car));
+        self.shadow
+            .draw(&shadow_casters.collect::<Vec<_>>(), &view, projection, interpolation);
+        // Draw objects, cars that look alike in one go.
+        let cars = &mut self.simulation.cars;
+        let visible = (0..cars.len()).filter(|id| frustum.intersects_obb(&cars[*id].bounding_box()));
+        for group in alike_groups(cars, visible) {
+            if group.len() > 1 && self.is_instancing {
+                let instances: Vec<_> = group.iter().map(|id| cars[*id].model_matrix(interpolation)).collect();
+                match cars[group[0]].draw_instanced(&view, projection, &self.light, &instances) {
+                    Ok(()) => continue,
+                    Err(e) => {
+                        warn!("Instanced drawing turned off, {}", e);
+                        self.is_instancing = false;
+                    }
+                }
+            }
+            for id in group {
+                cars[id].draw(&view, &projection, &self.light, interpolation);
+            }
+        }
+        self.particle_renderer.draw(&self.particles, &view, &projection);
+        // Draw the ghost last, so the objects behind it are visible.
+        if let Some(ghost) = self
+            .ghost
+            .as_ref()
+            .filter(|ghost| frustum.intersects_obb(&ghost.car().bounding_box()))
+        {
+            ghost
+                .car()
+                .draw_translucent(&view, &projection, &self.light, interpolation, GHOST_ALPHA);
+        }
+        if self.is_debug_lines {
+            let cars = self.simulation.cars.iter().chain(self.ghost.as_ref().map(Ghost::car));
+            let lines = DebugLines::from_scene(cars, self.simulation.layout.as_ref());
+            self.debug_line_renderer.draw(&lines, &view, projection);
+        }
+    }
+}
+
+/// Create the car of a player at its starting position.
+///
+/// The spawn points of the track layout are used if there is one for the player, the other cars line
+/// up on the grid behind the first spawn point. A `tuning` with invalid parameters is left out.
+fn spawn_car(player: usize, layout: Option<&TrackLayout>, tuning: &CarTuning) -> Car {
+    let spawns = layout.map(|layout| &layout.spawns[..]).unwrap_or(&[]);
+    let start = spawns.first().copied().unwrap_or(Spawn {
+        position: Vector3::new(0., 0., 0.5),
+        yaw: 0.,
+    });
+    let (position, yaw) = match spawns.get(player) {
+        Some(spawn) => (spawn.position, spawn.yaw),
+        None => {
+            let pose = StartGrid::default().slot(&start, player);
+            (pose.translation.vector, pose.rotation.euler_angles().2)
+        }
+    };
+    // Even and odd players drive different models.
+    let model_id = if player & 1 == 0 { 3 } else { 4 };
+    let (palette_id, tint) = CAR_COLORS[player % CAR_COLORS.len()];
+    let (model, palette) = (Car::model_from_id(model_id), Car::color_from_id(palette_id));
+    let mut car = tuning
+        .apply(CarBuilder::new(position, 1000.0).model(&model, &palette))
+        .build()
+        .unwrap_or_else(|e| {
+            warn!("Could not tune the car of player {}: {}", player + 1, e);
+            let mut car = Car::new(&model, &palette, position, 1000.0);
+            car.arcade_drift = tuning.arcade_drift;
+            car
+        });
+    car.set_color(Vector3::from(tint));
+    car.rotation[2] = yaw;
+    car
+}
+
+/// Return the ids of the `visible` cars in groups of cars that look alike, see `Car::looks_like()`.
+fn alike_groups<I: Iterator<Item = usize>>(cars: &[Car], visible: I) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for id in visible {
+        match groups.iter_mut().find(|group| cars[group[0]].looks_like(&cars[id])) {
+            Some(group) => group.push(id),
+            None => groups.push(vec![id]),
+        }
+    }
+    groups
+}
+
+/// Step the `simulation` and the `ghost` by `dt` and collide their cars with the colliders of the `level`.
+///
+/// The ghost starts along with the cars and goes through the same steps as the car it recorded.
+fn step_race(simulation: &mut Simulation, ghost: Option<&mut Ghost>, level: &Level, dt: f32, controller: &[Controller]) {
+    let is_started = simulation.is_started();
+    simulation.step(dt, controller);
+    // The whole collision detection is stupid right now. I have learned a lot during my work on this game and
+    // the way it is will do the job. I just want to finish the game so it feels "round" and continue with another
+    // project. Sorry ¯\_(ツ)_/¯
+    // Cars with level, the ghost doesn't touch other cars.
+    for car in &mut simulation.cars {
+        collide_with_level(car, level, dt);
+    }
+    if let Some(ghost) = ghost.filter(|_| is_started) {
+        ghost.update(dt);
+        collide_with_level(ghost.car_mut(), level, dt);
+    }
+}
+
+/// Push a car out of the ground and the race track border.
+fn collide_with_level(car: &mut Car, level: &Level, dt: f32) {
+    let prediction = 0.0;
+    let cp = car.isometry();
+    car.position[2] -= 0.81 * dt;
+
+    let penetrate_ground = query::contact(&cp, &car.cuboid, &level.ground.0, &level.ground.1, prediction);
+    if penetrate_ground.is_some() {
+        let pen = penetrate_ground.unwrap();
+        let w1 = pen.world1;
+        let w2 = pen.world2;
+        let dir = w1 - w2;
+        car.position -= dir;
+    };
+
+    let penetrate_border = query::contact(&cp, &car.cuboid, &level.border.0, &level.border.1, prediction);
+    if penetrate_border.is_some() {
+        let pen = penetrate_border.unwrap();
+        let w1 = pen.world1;
+        let w2 = pen.world2;
+        let dir = w1 - w2;
+        car.position -= dir;
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::step_race;
+    use crate::game::car::Car;
+    use crate::game::controller::{Controller, ControllerLayout};
+    use crate::game::level::Level;
+    use crate::game::replay::{Ghost, Recorder};
+    use crate::game::simulation::Simulation;
+    use crate::game::FIXED_TIMESTEP;
+
+    #[test]
+    fn ghost_follows_scene() {
+        let map = "maps/race_track_1";
+        let (level, layout) = (Level::without_model(map), Level::load_layout(map));
+        let spawn = layout.as_ref().unwrap().spawns[0].position;
+        let test_car = || Car::test_car(spawn, 1000.0);
+
+        // Player 1 drives through the scene, steps split into substeps and collides with the level.
+        let mut simulation = Simulation::new(vec![test_car()], layout.clone());
+        simulation.substeps = 4;
+        let mut recorder = Recorder::new(FIXED_TIMESTEP);
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        let mut positions = Vec::new();
+        for step in 0..120 {
+            controller.set_axis(if step < 60 { 0.3 } else { -0.6 }, 1.);
+            recorder.record(&controller);
+            step_race(&mut simulation, None, &level, FIXED_TIMESTEP, &[controller]);
+            positions.push(simulation.cars[0].position);
+        }
+
+        // The ghost of the run drives the same way in a scene without other cars.
+        let mut simulation = Simulation::new(Vec::new(), layout.clone());
+        simulation.substeps = 4;
+        let mut ghost = Ghost::new(test_car(), recorder.finish(), layout, 4);
+        for (step, position) in positions.iter().enumerate() {
+            step_race(&mut simulation, Some(&mut ghost), &level, FIXED_TIMESTEP, &[]);
+            assert_eq!(ghost.car().position, *position, "step {}", step);
         }
     }
 }
@@ -0,0 +1,140 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::simulation::CollisionEvent;
+
+/// Impulse [N s] a collision needs to score.
+const SCORE_IMPULSE: f32 = 3000.;
+/// Points of a single hit.
+const POINTS_PER_HIT: f32 = 100.;
+/// Time [s] after a hit in which the next one continues the combo.
+const COMBO_WINDOW: f32 = 2.;
+/// Factor of the points for each hit chained onto a combo.
+const COMBO_MULTIPLIER: f32 = 1.5;
+/// Chained hits after which the multiplier doesn't grow anymore.
+const MAX_COMBO: i32 = 4;
+
+/// Running combo of a car.
+#[derive(Debug, Copy, Clone, Default)]
+struct Combo {
+    /// Hits chained onto the first one.
+    chain: i32,
+    /// Time [s] left to continue the combo.
+    time_left: f32,
+}
+
+/// Points of each car for hitting other cars.
+///
+/// Both cars of a hard enough collision score. Hitting again within `COMBO_WINDOW` multiplies
+/// the points by `COMBO_MULTIPLIER` for every hit in the chain.
+#[derive(Debug, Clone, Default)]
+pub struct Score {
+    points: Vec<u32>,
+    combos: Vec<Combo>,
+}
+
+impl Score {
+    /// Create a score of 0 for each of the `cars`.
+    pub fn new(cars: usize) -> Score {
+        Score {
+            points: vec![0; cars],
+            combos: vec![Default::default(); cars],
+        }
+    }
+
+    /// Let the combos run out by the time step `dt`.
+    pub fn update(&mut self, dt: f32) {
+        for combo in &mut self.combos {
+            combo.time_left = (combo.time_left - dt).max(0.);
+        }
+    }
+
+    /// Award the points of a collision to both cars.
+    pub fn add(&mut self, event: &CollisionEvent) {
+        if event.impulse < SCORE_IMPULSE {
+            return;
+        }
+        for &car in &[event.a, event.b] {
+            let combo = match self.combos.get_mut(car) {
+                Some(combo) => combo,
+                None => continue,
+            };
+            combo.chain = if combo.time_left > 0. {
+                (combo.chain + 1).min(MAX_COMBO)
+            } else {
+                0
+            };
+            combo.time_left = COMBO_WINDOW;
+            self.points[car] += (POINTS_PER_HIT * COMBO_MULTIPLIER.powi(combo.chain)).round() as u32;
+        }
+    }
+
+    /// Return the points of a car.
+    pub fn points(&self, car: usize) -> u32 {
+        self.points.get(car).cloned().unwrap_or(0)
+    }
+
+    /// Return the multiplier the next hit of a car scores with, 1.0 outside of a combo.
+    pub fn multiplier(&self, car: usize) -> f32 {
+        match self.combos.get(car) {
+            Some(combo) if combo.time_left > 0. => COMBO_MULTIPLIER.powi((combo.chain + 1).min(MAX_COMBO)),
+            _ => 1.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Score, COMBO_MULTIPLIER, COMBO_WINDOW, POINTS_PER_HIT, SCORE_IMPULSE};
+    use crate::game::simulation::CollisionEvent;
+    use nalgebra::Vector3;
+
+    fn hit(a: usize, b: usize, impulse: f32) -> CollisionEvent {
+        CollisionEvent {
+            a,
+            b,
+            impulse,
+            point: Vector3::new(0., 0., 0.),
+        }
+    }
+
+    #[test]
+    fn combo() {
+        let single = POINTS_PER_HIT as u32;
+        let chained = (POINTS_PER_HIT * COMBO_MULTIPLIER) as u32;
+
+        // Two hits within the window.
+        let mut score = Score::new(3);
+        score.add(&hit(0, 1, 5000.));
+        assert_eq!(score.multiplier(0), COMBO_MULTIPLIER);
+        score.update(COMBO_WINDOW * 0.5);
+        score.add(&hit(0, 2, 5000.));
+        assert_eq!(score.points(0), single + chained);
+        assert_eq!(score.points(1), single);
+        assert_eq!(score.points(2), single);
+
+        // Two spaced-out hits.
+        let mut score = Score::new(2);
+        score.add(&hit(0, 1, 5000.));
+        score.update(COMBO_WINDOW * 1.1);
+        assert_eq!(score.multiplier(0), 1.);
+        score.add(&hit(0, 1, 5000.));
+        assert_eq!(score.points(0), 2 * single);
+
+        // Scraping along doesn't count.
+        score.add(&hit(0, 1, SCORE_IMPULSE * 0.5));
+        assert_eq!(score.points(0), 2 * single);
+        assert_eq!(score.points(5), 0);
+    }
+}
@@ -0,0 +1,112 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::car::Car;
+use crate::grphx::{Mesh, Shader, ShaderError, Vertex};
+use nalgebra::{Matrix4, Vector3};
+
+/// Height [m] of the shadow above the bottom of the car, so it doesn't flicker with the ground.
+const LIFT: f32 = 0.02;
+
+/// Dark soft ellipse on the ground under each car, a cheap replacement for real shadows.
+pub struct ShadowBlob {
+    mesh: Mesh,
+    /// `None` if the shader failed to load, nothing is drawn then.
+    shader: Option<Shader>,
+    /// Opacity [0.0; 1.0] in the center of the shadow.
+    pub opacity: f32,
+    /// Size of the shadow relative to the footprint of the car.
+    pub scale: f32,
+}
+
+impl ShadowBlob {
+    pub fn new() -> ShadowBlob {
+        let vertex = |x: f32, y: f32| Vertex {
+            position: [x - 0.5, y - 0.5, 0.],
+            normal: [0., 0., 1.],
+            uv: [x, y],
+        };
+        let vertices = vec![vertex(0., 0.), vertex(1., 0.), vertex(1., 1.), vertex(0., 1.)];
+        ShadowBlob {
+            mesh: Mesh::new(vertices, vec![0, 1, 2, 0, 2, 3]),
+            shader: Shader::for_effect("shadow", "shadow", "Shadows"),
+            opacity: 0.6,
+            scale: 1.3,
+        }
+    }
+
+    /// Compile the shader again from its files, a shader that failed to load stays off.
+    pub fn reload_shader(&mut self) -> Result<(), ShaderError> {
+        match &mut self.shader {
+            Some(shader) => shader.reload(),
+            None => Ok(()),
+        }
+    }
+
+    /// Draw the shadows of the `cars` to the bound framebuffer, before drawing the cars themselves.
+    ///
+    /// The shadows follow the cars `interpolation` of the way from their previous to their current pose.
+    pub fn draw(&self, cars: &[&Car], view: &Matrix4<f32>, projection: &Matrix4<f32>, interpolation: f32) {
+        let shader = match &self.shader {
+            Some(shader) => shader,
+            None => return,
+        };
+        unsafe {
+            // The shadows lie on the ground and must not hide each other.
+            gl::DepthMask(gl::FALSE);
+            shader.bind();
+            Shader::set_uniform_mat4(1, view);
+            Shader::set_uniform_mat4(2, projection);
+            Shader::set_uniform_float(6, self.opacity);
+            for car in cars {
+                Shader::set_uniform_mat4(0, &transform(car, interpolation, self.scale));
+                self.mesh.draw();
+            }
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+}
+
+/// Return the transform of a unit quad onto the ground under the `car`, `scale` times its footprint.
+fn transform(car: &Car, interpolation: f32, scale: f32) -> Matrix4<f32> {
+    let half_extents = car.cuboid.half_extents();
+    let mut pose = car.interpolated_pose(interpolation);
+    pose.translation.vector[2] += LIFT - half_extents[2];
+    let footprint = Vector3::new(2. * half_extents[0] * scale, 2. * half_extents[1] * scale, 1.);
+    pose.to_homogeneous() * Matrix4::new_nonuniform_scaling(&footprint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::transform;
+    use crate::game::car::Car;
+    use nalgebra::{Point3, Vector3};
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn shadow_follows_car() {
+        let mut car = Car::test_car(Vector3::new(3., -4., 0.5), 1000.0);
+        let center = transform(&car, 1., 1.).transform_point(&Point3::origin());
+        assert!((center - Point3::new(3., -4., 0.22)).norm() < 1e-5);
+
+        // The shadow moves and turns along with the car, the front stays in front.
+        car.position = Vector3::new(-10., 2., 0.5);
+        car.rotation[2] = FRAC_PI_2;
+        let shadow = transform(&car, 1., 1.);
+        let center = shadow.transform_point(&Point3::origin());
+        let front = shadow.transform_point(&Point3::new(0., 0.5, 0.));
+        assert!((center - Point3::new(-10., 2., 0.22)).norm() < 1e-5);
+        assert!((front - Point3::new(-11., 2., 0.22)).norm() < 1e-5);
+    }
+}
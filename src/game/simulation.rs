@@ -0,0 +1,767 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::ai::AiController;
+use super::car::{resolve_collision, Car};
+use super::controller::Controller;
+use super::lap::{Countdown, LapTimer, DEFAULT_LAPS};
+use super::track::{Respawner, TrackLayout};
+use nalgebra::Vector3;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Collision between two cars during a simulation step.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CollisionEvent {
+    /// Index of the first car in `Simulation::cars`, always lower than `b`.
+    pub a: usize,
+    /// Index of the second car in `Simulation::cars`.
+    pub b: usize,
+    /// Impulse [N s] that knocked the cars apart.
+    pub impulse: f32,
+    /// Point in world space where the cars touched.
+    pub point: Vector3<f32>,
+}
+
+/// Cars racing on a track, without any rendering.
+///
+/// Nothing in here needs a window or an OpenGL context, so a race can be stepped in tests, for
+/// training opponents or on a server. Cars built without a model are drawn nowhere either.
+pub struct Simulation {
+    pub cars: Vec<Car>,
+    /// Computer players driving some of the `cars`, at the same index. The other cars take the input of
+    /// their players.
+    pub drivers: Vec<Option<AiController>>,
+    /// Walls, spawn points and more of the track.
+    pub layout: Option<TrackLayout>,
+    /// Lap times of each car, empty if the track has no finish line.
+    pub lap_timers: Vec<LapTimer>,
+    /// Length of the race, a car finishes when it crosses the line at the end of the last lap.
+    pub laps: usize,
+    /// Puts cars back on the track after leaving it.
+    pub respawner: Respawner,
+    /// Countdown to the start, the cars don't take any input before.
+    pub countdown: Countdown,
+    /// Parts every step is split into, so fast cars don't pass through the walls between two steps.
+    /// At least 1.
+    pub substeps: u32,
+    /// Source of all randomness of the race, e.g. the skid smoke, so a run with the same seed and
+    /// the same inputs plays out the same. See `seed()`.
+    pub rng: StdRng,
+    /// Receive the collisions between cars, see `subscribe()`.
+    subscribers: Vec<Sender<CollisionEvent>>,
+}
+
+impl Simulation {
+    /// Create a race of `cars` on the track of `layout`.
+    pub fn new(cars: Vec<Car>, layout: Option<TrackLayout>) -> Simulation {
+        let timer = layout.as_ref().and_then(|layout| {
+            let checkpoints = layout.checkpoints.clone();
+            layout.finish_line.map(|line| LapTimer::new(line, checkpoints))
+        });
+        let lap_timers = match timer {
+            Some(timer) => vec![timer; cars.len()],
+            None => Vec::new(),
+        };
+        let laps = layout.as_ref().and_then(|layout| layout.laps).unwrap_or(DEFAULT_LAPS);
+        Simulation {
+            cars,
+            drivers: Vec::new(),
+            layout,
+            lap_timers,
+            laps,
+            respawner: Default::default(),
+            countdown: Countdown::new(0.),
+            substeps: 1,
+            rng: StdRng::seed_from_u64(0),
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Start the random numbers over from `seed`.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Return true once the countdown elapsed and the cars take the input of their players.
+    pub fn is_started(&self) -> bool {
+        self.countdown.is_finished()
+    }
+
+    /// Advance the race by the time step `dt`, driving every car with the controller of its player or
+    /// its computer player.
+    ///
+    /// During the countdown the input is ignored and the lap timers don't run. The cars move and
+    /// collide in `substeps` parts of the step, all with the same input.
+    pub fn step(&mut self, dt: f32, controller: &[Controller]) {
+        let is_started = self.is_started();
+        self.countdown.update(dt);
+        let inputs = if is_started {
+            car_inputs(&self.cars, &mut self.drivers, controller)
+        } else {
+            Vec::new()
+        };
+        let substeps = self.substeps.max(1);
+        for _ in 0..substeps {
+            self.substep(dt / substeps as f32, &inputs, is_started);
+        }
+    }
+
+    /// Drive the cars with their `inputs` and collide them for one substep of `dt`, the lap timers only
+    /// run once the race `is_started`.
+    fn substep(&mut self, dt: f32, inputs: &[Option<Controller>], is_started: bool) {
+        if let Some(layout) = &self.layout {
+            for car in &mut self.cars {
+                car.surface = layout.surface_at(&car.position);
+            }
+        }
+        // In the wake of the cars ahead the air holds a car back less.
+        let slipstreams: Vec<f32> = self
+            .cars
+            .iter()
+            .enumerate()
+            .map(|(id, car)| {
+                let leaders = self.cars.iter().enumerate().filter(|(other, _)| *other != id);
+                leaders.map(|(_, leader)| car.slipstream_behind(leader)).fold(0., f32::max)
+            })
+            .collect();
+        for (car, slipstream) in self.cars.iter_mut().zip(slipstreams) {
+            car.slipstream = slipstream;
+        }
+        drive_cars(&mut self.cars, inputs, dt);
+        if let Some(layout) = &self.layout {
+            // Gravity pulls the cars down the slopes, flat parts don't change anything.
+            for car in &mut self.cars {
+                if let Some(slope) = layout.slope_at(&car.position) {
+                    car.set_velocity(car.velocity() + slope.acceleration() * dt);
+                }
+            }
+        }
+        self.update_collisions();
+        if let Some(layout) = &self.layout {
+            self.respawner.update(layout, &mut self.cars, dt);
+        }
+        if is_started {
+            // The clock stops for the cars that finished, they can drive on until the race is over.
+            let laps = self.laps;
+            for (car, timer) in self.cars.iter().zip(self.lap_timers.iter_mut()) {
+                if timer.laps() < laps {
+                    timer.update(car.position, dt);
+                }
+            }
+        }
+    }
+
+    /// Advance only the car `id` by `dt`, e.g. to replay the inputs of a predicted car.
+    ///
+    /// The car keeps to the walls and slopes of the track but doesn't hit other cars, and the
+    /// countdown and lap timers stand still.
+    pub fn step_car(&mut self, id: usize, dt: f32, controller: Option<Controller>) {
+        let controller = controller.filter(|_| self.is_started());
+        let substeps = self.substeps.max(1);
+        let dt = dt / substeps as f32;
+        let car = &mut self.cars[id];
+        for _ in 0..substeps {
+            if let Some(layout) = &self.layout {
+                car.surface = layout.surface_at(&car.position);
+            }
+            car.update(dt, controller);
+            if let Some(layout) = &self.layout {
+                if let Some(slope) = layout.slope_at(&car.position) {
+                    car.set_velocity(car.velocity() + slope.acceleration() * dt);
+                }
+                layout.track.collide(car);
+            }
+        }
+    }
+
+    /// Turn the car `id` along the track where it is, facing from the nearest waypoint to the next.
+    ///
+    /// Returns false without a track to follow, the car isn't changed then.
+    pub fn straighten(&mut self, id: usize) -> bool {
+        let car = &mut self.cars[id];
+        match self.layout.as_ref().and_then(|layout| layout.nearest_respawn(&car.position)) {
+            Some((spawn, _)) => {
+                car.straighten(spawn.yaw);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return true if the car `id` completed all laps of the race.
+    pub fn is_finished(&self, id: usize) -> bool {
+        match self.lap_timers.get(id) {
+            Some(timer) => timer.laps() >= self.laps,
+            None => false,
+        }
+    }
+
+    /// Return true if the car `id` is driven by a computer player.
+    pub fn is_computer(&self, id: usize) -> bool {
+        matches!(self.drivers.get(id), Some(Some(_)))
+    }
+
+    /// Return true if every player completed all laps, the computer players don't hold up the end of the
+    /// race unless they race alone. Races on tracks without a finish line never end.
+    pub fn is_race_finished(&self) -> bool {
+        let ids = 0..self.lap_timers.len();
+        let is_alone = ids.clone().all(|id| self.is_computer(id));
+        !self.lap_timers.is_empty() && ids.filter(|id| is_alone || !self.is_computer(*id)).all(|id| self.is_finished(id))
+    }
+
+    /// Return a receiver for the collisions between cars from now on.
+    ///
+    /// Every collision is sent to all receivers, a dropped receiver unsubscribes.
+    pub fn subscribe(&mut self) -> Receiver<CollisionEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Solve the collisions of the cars with each other and with the walls of the track.
+    ///
+    /// Cars hitting each other take damage.
+    fn update_collisions(&mut self) {
+        for j in 1..self.cars.len() {
+            let (left, right) = self.cars.split_at_mut(j);
+            for (i, car) in left.iter_mut().enumerate() {
+                if !car.collides_with(&right[0]) {
+                    continue;
+                }
+                match resolve_collision(car, &mut right[0]) {
+                    Some(contact) if contact.impulse > 0. => {
+                        car.apply_impact(contact.impulse);
+                        right[0].apply_impact(contact.impulse);
+                        let event = CollisionEvent {
+                            a: i,
+                            b: j,
+                            impulse: contact.impulse,
+                            point: contact.point,
+                        };
+                        self.subscribers.retain(|subscriber| subscriber.send(event).is_ok());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(layout) = &self.layout {
+            for car in &mut self.cars {
+                layout.track.collide(car);
+            }
+        }
+    }
+}
+
+/// Return the input of each car for the next step from its computer player in `drivers`, or else from
+/// the controller of its player.
+///
+/// The computer players plan once per step, so they react just as fast with any number of substeps.
+fn car_inputs(cars: &[Car], drivers: &mut [Option<AiController>], controller: &[Controller]) -> Vec<Option<Controller>> {
+    cars.iter()
+        .enumerate()
+        .map(|(id, car)| match drivers.get_mut(id).and_then(Option::as_mut) {
+            Some(driver) => Some(driver.update(car)),
+            None => controller.get(id).cloned(),
+        })
+        .collect()
+}
+
+/// Update each car with its input in `inputs`. Cars without one roll on without input.
+fn drive_cars(cars: &mut [Car], inputs: &[Option<Controller>], dt: f32) {
+    for (id, car) in cars.iter_mut().enumerate() {
+        car.update(dt, inputs.get(id).cloned().flatten());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{drive_cars, Simulation};
+    use crate::game::ai::{AiController, Difficulty};
+    use crate::game::car::Car;
+    use crate::game::controller::{Controller, ControllerLayout};
+    use crate::game::lap::Countdown;
+    use crate::game::particles::{ParticleSystem, SkidEmitter};
+    use crate::game::track::TrackLayout;
+    use crate::game::FIXED_TIMESTEP;
+    use crate::util::FixedTimestep;
+    use nalgebra::Vector3;
+    use rand::Rng;
+
+    /// Return the `controller` of every player as the input of its car.
+    fn inputs(controller: &[Controller]) -> Vec<Option<Controller>> {
+        controller.iter().cloned().map(Some).collect()
+    }
+
+    /// Drive a car for the given frame times and return its final position.
+    fn drive_frames(frames: &[f32]) -> Vector3<f32> {
+        let mut cars = vec![Car::test_car(Vector3::new(0., 0., 0.), 1000.0)];
+        let mut controller = vec![Controller::new(false, &ControllerLayout::WASD)];
+        controller[0].set_axis(0.5, 1.);
+        let mut timestep = FixedTimestep::new(FIXED_TIMESTEP);
+        timestep.max_steps = 1000;
+
+        for dt in frames {
+            for _ in 0..timestep.advance(*dt) {
+                drive_cars(&mut cars, &inputs(&controller), FIXED_TIMESTEP);
+            }
+        }
+        cars[0].position
+    }
+
+    #[test]
+    fn frame_rate_independent() {
+        let one_frame = drive_frames(&[1.]);
+        let smooth = drive_frames(&[0.01; 100]);
+        let uneven = drive_frames(&[0.3, 0.05, 0.15, 0.001, 0.499]);
+        assert!(one_frame.norm() > 1.);
+        assert!((one_frame - smooth).norm() < 1e-4);
+        assert!((one_frame - uneven).norm() < 1e-4);
+    }
+
+    #[test]
+    fn cars_move_independently() {
+        let mut cars = vec![
+            Car::test_car(Vector3::new(-5., 0., 0.), 1000.0),
+            Car::test_car(Vector3::new(5., 0., 0.), 1000.0),
+        ];
+        let mut controller = vec![
+            Controller::new(false, &ControllerLayout::WASD),
+            Controller::new(false, &ControllerLayout::Arrows),
+        ];
+        controller[0].set_axis(-1., 1.);
+        controller[1].set_axis(1., -1.);
+
+        for _ in 0..100 {
+            drive_cars(&mut cars, &inputs(&controller), 0.01);
+        }
+
+        // Player 1 drives forward and turns left, player 2 reverses.
+        assert!(cars[0].position[1] > 0.);
+        assert!(cars[0].rotation[2] > 0.);
+        assert!(cars[1].position[1] < 0.);
+        assert!(cars[0].position[0] < 0. && cars[1].position[0] > 0.);
+    }
+
+    #[test]
+    fn pause_freezes_cars() {
+        let mut cars = vec![Car::test_car(Vector3::new(0., 0., 0.), 1000.0)];
+        let mut controller = vec![Controller::new(false, &ControllerLayout::WASD)];
+        controller[0].set_axis(0., 1.);
+        let mut timestep = FixedTimestep::new(FIXED_TIMESTEP);
+        let drive = |timestep: &mut FixedTimestep, cars: &mut Vec<Car>, dt: f32| {
+            for _ in 0..timestep.advance(dt) {
+                drive_cars(cars, &inputs(&controller), FIXED_TIMESTEP);
+            }
+        };
+
+        drive(&mut timestep, &mut cars, 0.5);
+        let position = cars[0].position;
+        let velocity = cars[0].velocity();
+        timestep.is_paused = true;
+        for _ in 0..100 {
+            drive(&mut timestep, &mut cars, 0.1);
+        }
+        assert_eq!(cars[0].position, position);
+        assert_eq!(cars[0].velocity(), velocity);
+
+        // The car moves on, but without catching up on the paused time.
+        timestep.is_paused = false;
+        drive(&mut timestep, &mut cars, FIXED_TIMESTEP);
+        assert!(cars[0].position[1] > position[1]);
+        assert!(cars[0].position[1] - position[1] < velocity.norm() * 2. * FIXED_TIMESTEP);
+    }
+
+    #[test]
+    fn slow_motion() {
+        // Distance a car coasting without resistance covers in one second of wall-clock time.
+        let distance = |time_scale: f32| {
+            let mut car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+            car.drag_coefficient = 0.;
+            car.rolling_resistance = 0.;
+            car.engine_brake = 0.;
+            car.set_velocity(Vector3::new(0., 10., 0.));
+            let mut cars = vec![car];
+            let mut timestep = FixedTimestep::new(FIXED_TIMESTEP);
+            timestep.time_scale = time_scale;
+            for _ in 0..100 {
+                for _ in 0..timestep.advance(0.01) {
+                    drive_cars(&mut cars, &[], FIXED_TIMESTEP);
+                }
+            }
+            cars[0].position.norm()
+        };
+
+        assert!((distance(1.) - 10.).abs() < 1e-3);
+        assert!((distance(0.5) - 5.).abs() < 1e-3);
+        assert!((distance(2.) - 20.).abs() < 1e-3);
+        assert_eq!(distance(0.), 0.);
+    }
+
+    #[test]
+    fn step_without_rendering() {
+        // A corridor along the y-axis with the finish line just ahead of the car.
+        let layout = TrackLayout::from_json(
+            r#"{
+                "boundaries": [[[-5, -20, 0], [5, -20, 0], [5, 200, 0], [-5, 200, 0], [-5, -20, 0]]],
+                "spawns": [{ "position": [0, 0, 0.5], "yaw": 0 }],
+                "finish_line": [[-5, 10, 0], [5, 10, 0]]
+            }"#,
+        );
+        let car = Car::test_car(Vector3::new(0., 0., 0.5), 1000.0);
+        assert!(car.model.is_none());
+        let mut simulation = Simulation::new(vec![car], Some(layout.unwrap()));
+        let collisions = simulation.subscribe();
+        let mut controller = vec![Controller::new(false, &ControllerLayout::WASD)];
+        controller[0].set_axis(0.3, 1.);
+
+        for _ in 0..(3. / FIXED_TIMESTEP) as usize {
+            simulation.step(FIXED_TIMESTEP, &controller);
+        }
+
+        // The car steered into the right wall, but stayed on the track and crossed the line.
+        let car = &simulation.cars[0];
+        assert!(car.position[1] > 10.);
+        assert!(car.position[0] > 0. && car.position[0] < 5.);
+        assert_eq!(simulation.lap_timers[0].laps(), 0);
+        assert!(collisions.try_recv().is_err());
+    }
+
+    #[test]
+    fn finish_after_laps() {
+        let layout = TrackLayout::from_json(
+            r#"{
+                "boundaries": [[[-50, -50, 0], [50, -50, 0], [50, 50, 0], [-50, 50, 0], [-50, -50, 0]]],
+                "spawns": [{ "position": [0, 0, 0.5], "yaw": 0 }],
+                "finish_line": [[-5, 10, 0], [5, 10, 0]],
+                "laps": 2
+            }"#,
+        );
+        let cars = vec![
+            Car::test_car(Vector3::new(0., 5., 0.5), 1000.0),
+            Car::test_car(Vector3::new(30., -30., 0.5), 1000.0),
+        ];
+        let mut simulation = Simulation::new(cars, Some(layout.unwrap()));
+        assert_eq!(simulation.laps, 2);
+        // Over the line and back around its end, the other car stands still.
+        let lap = |simulation: &mut Simulation| {
+            for (x, y) in &[(0., 15.), (20., 15.), (20., 5.), (0., 5.)] {
+                simulation.cars[0].position = Vector3::new(*x, *y, 0.5);
+                simulation.step(FIXED_TIMESTEP, &[]);
+            }
+        };
+
+        // The first crossing starts the clock.
+        simulation.step(FIXED_TIMESTEP, &[]);
+        lap(&mut simulation);
+        lap(&mut simulation);
+        assert_eq!(simulation.lap_timers[0].laps(), 1);
+        assert!(!simulation.is_finished(0));
+        lap(&mut simulation);
+        assert!(simulation.is_finished(0));
+        assert!(!simulation.is_finished(1));
+        assert!(!simulation.is_race_finished());
+
+        // The clock stopped on the last lap.
+        lap(&mut simulation);
+        assert_eq!(simulation.lap_timers[0].laps(), 2);
+        simulation.lap_timers[1] = simulation.lap_timers[0].clone();
+        assert!(simulation.is_race_finished());
+    }
+
+    #[test]
+    fn computer_players_race() {
+        let layout = TrackLayout::from_json(
+            r#"{
+                "boundaries": [
+                    [[-45, -45, 0], [45, -45, 0], [45, 45, 0], [-45, 45, 0], [-45, -45, 0]],
+                    [[-25, -25, 0], [25, -25, 0], [25, 25, 0], [-25, 25, 0], [-25, -25, 0]]
+                ],
+                "spawns": [{ "position": [35, 0, 0.5], "yaw": 0 }],
+                "finish_line": [[25, 5, 0], [45, 5, 0]],
+                "waypoints": [[35, 35, 0], [-35, 35, 0], [-35, -35, 0], [35, -35, 0]],
+                "laps": 1
+            }"#,
+        )
+        .unwrap();
+        let waypoints = layout.waypoints.clone();
+        let cars = vec![
+            Car::test_car(Vector3::new(30., 0., 0.5), 1000.0),
+            Car::test_car(Vector3::new(38., 0., 0.5), 1000.0),
+        ];
+        let mut simulation = Simulation::new(cars, Some(layout));
+        simulation.drivers = vec![None, Some(AiController::with_racing_line(waypoints))];
+        assert!(simulation.is_computer(1) && !simulation.is_computer(0));
+
+        // The computer player goes around the track on its own, the player without input stays behind.
+        for _ in 0..(60. / FIXED_TIMESTEP) as usize {
+            simulation.step(FIXED_TIMESTEP, &[]);
+        }
+        assert!(simulation.is_finished(1));
+        assert!(!simulation.is_finished(0));
+        // Only the player holds up the end of the race.
+        assert!(!simulation.is_race_finished());
+        simulation.lap_timers[0] = simulation.lap_timers[1].clone();
+        assert!(simulation.is_race_finished());
+    }
+
+    #[test]
+    fn straighten_along_track() {
+        let layout = TrackLayout::from_json(
+            r#"{
+                "boundaries": [[[-30, -30, 0], [30, -30, 0], [30, 30, 0], [-30, 30, 0], [-30, -30, 0]]],
+                "spawns": [{ "position": [20, 0, 0.5], "yaw": 0 }],
+                "waypoints": [[20, -20, 0], [20, 20, 0], [-20, 20, 0], [-20, -20, 0]]
+            }"#,
+        );
+        let position = Vector3::new(-15., 19., 0.5);
+        let mut car = Car::test_car(position, 1000.0);
+        car.rotation = Vector3::new(0.2, -0.1, 1.);
+        car.boost = 10.;
+        let mut simulation = Simulation::new(vec![car], Some(layout.unwrap()));
+        assert!(simulation.straighten(0));
+
+        // Next to the waypoint in the top left corner the track leads towards -y.
+        let car = &simulation.cars[0];
+        assert_eq!(car.position, position);
+        assert_eq!((car.rotation[0], car.rotation[1]), (0., 0.));
+        assert!((car.forward() - Vector3::new(0., -1., 0.)).norm() < 1e-5);
+        assert!(car.velocity().dot(&car.forward()) > 0.);
+        assert_eq!(car.boost, 10.);
+
+        let car = Car::test_car(position, 1000.0);
+        assert!(!Simulation::new(vec![car], None).straighten(0));
+    }
+
+    #[test]
+    fn no_input_during_countdown() {
+        let car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let mut simulation = Simulation::new(vec![car], None);
+        simulation.countdown = Countdown::new(1.);
+        let mut controller = vec![Controller::new(false, &ControllerLayout::WASD)];
+        controller[0].set_axis(0., 1.);
+
+        // The car only moves after the countdown.
+        let mut timestep = FixedTimestep::new(FIXED_TIMESTEP);
+        for _ in 0..99 {
+            for _ in 0..timestep.advance(0.01) {
+                simulation.step(FIXED_TIMESTEP, &controller);
+            }
+        }
+        assert!(!simulation.is_started());
+        assert_eq!(simulation.cars[0].position, Vector3::new(0., 0., 0.));
+        assert_eq!(simulation.cars[0].velocity(), Vector3::new(0., 0., 0.));
+
+        for _ in 0..timestep.advance(0.03) {
+            simulation.step(FIXED_TIMESTEP, &controller);
+        }
+        assert!(simulation.is_started());
+        assert!(simulation.cars[0].velocity()[1] > 0.);
+        // Just a few steps of driving, nothing caught up from the countdown.
+        assert!(simulation.cars[0].position[1] < 0.02);
+    }
+
+    #[test]
+    fn collision_events() {
+        let mut cars = vec![
+            Car::test_car(Vector3::new(0., 0., 0.), 1000.0),
+            Car::test_car(Vector3::new(0., 4., 0.), 1000.0),
+        ];
+        cars[0].set_velocity(Vector3::new(0., 5., 0.));
+        cars[1].set_velocity(Vector3::new(0., -5., 0.));
+        let mut simulation = Simulation::new(cars, None);
+        let collisions = simulation.subscribe();
+        let dropped = simulation.subscribe();
+        drop(dropped);
+
+        for _ in 0..(1. / FIXED_TIMESTEP) as usize {
+            simulation.step(FIXED_TIMESTEP, &[]);
+        }
+
+        // The cars bounce off each other once, touching in the middle.
+        let events = collisions.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].a, events[0].b), (0, 1));
+        assert!(events[0].impulse > 0.);
+        assert!((events[0].point - Vector3::new(0., 2., 0.)).norm() < 0.1);
+        assert_eq!(simulation.subscribers.len(), 1);
+    }
+
+    #[test]
+    fn roll_downhill() {
+        let layout = TrackLayout::from_json(
+            r#"{
+                "boundaries": [[[-20, -20, 0], [20, -20, 0], [20, 200, 0], [-20, 200, 0], [-20, -20, 0]]],
+                "spawns": [{ "position": [0, 0, 0.5], "yaw": 0 }],
+                "slopes": [{ "min": [-20, -10, 0], "max": [0, 200, 0], "normal": [0, 0.5, 1] }]
+            }"#,
+        )
+        .unwrap();
+        // The first car stands on the slope facing downhill, the second one on flat ground.
+        let cars = vec![
+            Car::test_car(Vector3::new(-10., 0., 0.5), 1000.0),
+            Car::test_car(Vector3::new(10., 0., 0.5), 1000.0),
+        ];
+        let mut simulation = Simulation::new(cars, Some(layout));
+
+        for _ in 0..(2. / FIXED_TIMESTEP) as usize {
+            simulation.step(FIXED_TIMESTEP, &[]);
+        }
+
+        assert!(simulation.cars[0].speed() > 1.);
+        assert!(simulation.cars[0].position[1] > 1.);
+        assert_eq!(simulation.cars[1].velocity(), Vector3::new(0., 0., 0.));
+        assert_eq!(simulation.cars[1].position, Vector3::new(10., 0., 0.5));
+    }
+
+    #[test]
+    fn slower_on_grass() {
+        let layout = TrackLayout::from_json(
+            r#"{
+                "boundaries": [[[-20, -20, 0], [20, -20, 0], [20, 200, 0], [-20, 200, 0], [-20, -20, 0]]],
+                "spawns": [{ "position": [0, 0, 0.5], "yaw": 0 }],
+                "surfaces": [
+                    { "min": [-20, -20, 0], "max": [0, 200, 0], "surface": "grass" },
+                    { "min": [0, -20, 0], "max": [20, 200, 0], "surface": "dirt", "grip": 2 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(layout.surface_at(&Vector3::new(-10., 0., 0.)).grip, 0.6);
+        assert_eq!(layout.surface_at(&Vector3::new(10., 0., 0.)).grip, 2.);
+        assert_eq!(layout.surface_at(&Vector3::new(30., 0., 0.)).grip, 1.);
+
+        // Both cars roll out from 20 m/s, the first one on grass and the second one on tarmac.
+        let driving = |x: f32, layout: Option<TrackLayout>| {
+            let mut car = Car::test_car(Vector3::new(x, 0., 0.5), 1000.0);
+            car.set_velocity(Vector3::new(0., 20., 0.));
+            let mut simulation = Simulation::new(vec![car], layout);
+            for _ in 0..(1. / FIXED_TIMESTEP) as usize {
+                simulation.step(FIXED_TIMESTEP, &[]);
+            }
+            simulation.cars[0].speed()
+        };
+        let grass = driving(-10., Some(layout));
+        let tarmac = driving(-10., None);
+        assert!(grass < tarmac - 1., "{} on grass, {} on tarmac", grass, tarmac);
+    }
+
+    #[test]
+    fn no_tunneling() {
+        // The car races into the wall at the end of a corridor, moving further than its length per step.
+        // The substeps and the swept collision with the walls both stop it.
+        let end_position = |substeps: u32| {
+            let layout = TrackLayout::from_json(
+                r#"{
+                    "boundaries": [[[-5, -20, 0], [5, -20, 0], [5, 20, 0], [-5, 20, 0], [-5, -20, 0]]],
+                    "spawns": [{ "position": [0, 0, 0.5], "yaw": 0 }]
+                }"#,
+            );
+            let mut car = Car::test_car(Vector3::new(0., 18.7, 0.5), 1000.0);
+            car.set_velocity(Vector3::new(0., 60., 0.));
+            let mut simulation = Simulation::new(vec![car], Some(layout.unwrap()));
+            simulation.substeps = substeps;
+            simulation.step(0.05, &[]);
+            simulation.cars[0].position[1]
+        };
+
+        for substeps in 1..=4 {
+            assert!(end_position(substeps) < 20.);
+        }
+        // Without substeps the car is stopped right where it hit the wall.
+        assert!((end_position(1) + 1. - 20.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn substeps_stop_cars_passing_through() {
+        // Two cars race head-on, in a single step they move past each other without ever touching.
+        let collisions = |substeps: u32| {
+            let mut cars = vec![
+                Car::test_car(Vector3::new(0., 0., 0.5), 1000.0),
+                Car::test_car(Vector3::new(0., 3.5, 0.5), 1000.0),
+            ];
+            cars[0].set_velocity(Vector3::new(0., 60., 0.));
+            cars[1].set_velocity(Vector3::new(0., -60., 0.));
+            let mut simulation = Simulation::new(cars, None);
+            let collisions = simulation.subscribe();
+            simulation.substeps = substeps;
+            simulation.step(0.05, &[]);
+            collisions.try_iter().count()
+        };
+
+        assert_eq!(collisions(1), 0);
+        assert!(collisions(4) > 0);
+    }
+
+    #[test]
+    fn ai_reacts_per_step() {
+        // Easy opponents wait 30 steps before they react, no matter how many substeps each step has.
+        let mut ai = AiController::new(vec![Vector3::new(0., 100., 0.)]);
+        ai.set_difficulty(Difficulty::Easy);
+        let mut simulation = Simulation::new(vec![Car::test_car(Vector3::new(0., 0., 0.5), 1000.0)], None);
+        simulation.drivers = vec![Some(ai)];
+        simulation.substeps = 4;
+        for _ in 0..30 {
+            simulation.step(FIXED_TIMESTEP, &[]);
+        }
+        assert_eq!(simulation.cars[0].velocity(), Vector3::new(0., 0., 0.));
+        simulation.step(FIXED_TIMESTEP, &[]);
+        assert!(simulation.cars[0].speed() > 0.);
+    }
+
+    /// Race two sliding cars into each other with scripted inputs, return the positions of the cars
+    /// and of their skid smoke, and the next random number.
+    fn seeded_run(seed: u64) -> (Vec<Vector3<f32>>, Vec<Vector3<f32>>, u64) {
+        let cars = vec![
+            Car::test_car(Vector3::new(-3., 0., 0.), 1000.0),
+            Car::test_car(Vector3::new(3., 0., 0.), 1000.0),
+        ];
+        let mut simulation = Simulation::new(cars, None);
+        simulation.seed(seed);
+        // Sliding towards each other.
+        simulation.cars[0].set_velocity(Vector3::new(8., 5., 0.));
+        simulation.cars[1].set_velocity(Vector3::new(-8., 5., 0.));
+        let mut controller = [Controller::new(false, &ControllerLayout::WASD); 2];
+        let mut emitters = [SkidEmitter::default(); 2];
+        let mut particles = ParticleSystem::new();
+
+        for step in 0..100 {
+            let steer = (step as f32 * 0.05).sin();
+            controller[0].set_axis(steer, 1.);
+            controller[1].set_axis(-steer, 1.);
+            simulation.step(FIXED_TIMESTEP, &controller);
+            particles.update(FIXED_TIMESTEP);
+            for (car, emitter) in simulation.cars.iter().zip(emitters.iter_mut()) {
+                emitter.update(car, FIXED_TIMESTEP, &mut particles, &mut simulation.rng);
+            }
+        }
+        let cars = simulation.cars.iter().map(|car| car.position).collect();
+        let smoke = particles.particles.iter().map(|particle| particle.position).collect();
+        (cars, smoke, simulation.rng.gen())
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        let (cars, smoke, next) = seeded_run(42);
+        assert!(!smoke.is_empty());
+        assert_eq!(seeded_run(42), (cars.clone(), smoke.clone(), next));
+
+        // Another seed changes the random parts only.
+        let (other_cars, other_smoke, other_next) = seeded_run(7);
+        assert_eq!(other_cars, cars);
+        assert_ne!(other_smoke, smoke);
+        assert_ne!(other_next, next);
+    }
+}
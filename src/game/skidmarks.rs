@@ -0,0 +1,239 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::car::Car;
+use crate::grphx::{Mesh, Shader, ShaderError, Vertex};
+use nalgebra::{Matrix4, Vector3};
+use std::collections::VecDeque;
+
+/// Height [m] of the marks above the bottom of the car, below the shadows.
+const LIFT: f32 = 0.01;
+
+/// Piece of a skid mark between two successive positions of a wheel, on the ground.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SkidSegment {
+    pub start: Vector3<f32>,
+    pub end: Vector3<f32>,
+    /// Time [s] since the segment was laid down.
+    pub age: f32,
+}
+
+/// Black marks the rear wheels of sliding cars leave on the ground, fading over time.
+#[derive(Debug, Clone)]
+pub struct SkidMarks {
+    /// Segments from the oldest to the newest.
+    pub segments: VecDeque<SkidSegment>,
+    /// Maximum number of segments, the oldest ones make room for new ones.
+    pub capacity: usize,
+    /// Time [s] until a segment faded out.
+    pub lifetime: f32,
+    /// The car has to slide at a slip angle above this threshold [rad] to leave marks.
+    pub slip_threshold: f32,
+    /// Positions of the rear wheels of each car after the last update, if it was sliding.
+    last_wheels: Vec<Option<[Vector3<f32>; 2]>>,
+}
+
+impl Default for SkidMarks {
+    fn default() -> SkidMarks {
+        SkidMarks {
+            segments: VecDeque::new(),
+            capacity: 2000,
+            lifetime: 20.,
+            slip_threshold: 0.2,
+            last_wheels: Vec::new(),
+        }
+    }
+}
+
+impl SkidMarks {
+    pub fn new() -> SkidMarks {
+        Default::default()
+    }
+
+    /// Age the marks by the time step `dt` and lay down new ones behind the sliding `cars`.
+    pub fn update(&mut self, cars: &[Car], dt: f32) {
+        for segment in &mut self.segments {
+            segment.age += dt;
+        }
+        let lifetime = self.lifetime;
+        self.segments.retain(|segment| segment.age < lifetime);
+
+        self.last_wheels.resize(cars.len(), None);
+        for (id, car) in cars.iter().enumerate() {
+            // Standing cars don't slide, however small a velocity points sideways.
+            if car.velocity().norm() <= 1. || car.slip_angle() <= self.slip_threshold {
+                self.last_wheels[id] = None;
+                continue;
+            }
+            let ground = Vector3::new(0., 0., LIFT - car.cuboid.half_extents()[2]);
+            let wheels = car.rear_wheels();
+            if let Some(last) = self.last_wheels[id].replace(wheels) {
+                for (start, end) in last.iter().zip(wheels.iter()) {
+                    self.push(SkidSegment {
+                        start: start + ground,
+                        end: end + ground,
+                        age: 0.,
+                    });
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, segment: SkidSegment) {
+        if self.segments.len() >= self.capacity {
+            self.segments.pop_front();
+        }
+        self.segments.push_back(segment);
+    }
+
+    /// Return the opacity of a `segment`, fading from 1.0 to 0.0 over the lifetime.
+    pub fn alpha(&self, segment: &SkidSegment) -> f32 {
+        (1. - segment.age / self.lifetime).max(0.)
+    }
+}
+
+/// Renders skid marks as dark quads on the ground.
+pub struct SkidMarkRenderer {
+    mesh: Mesh,
+    /// `None` if the shader failed to load, nothing is drawn then.
+    shader: Option<Shader>,
+    /// Width [m] of a mark.
+    pub width: f32,
+    /// Opacity [0.0; 1.0] of a fresh mark.
+    pub opacity: f32,
+}
+
+impl SkidMarkRenderer {
+    pub fn new() -> SkidMarkRenderer {
+        let vertex = |x: f32, y: f32| Vertex {
+            position: [x - 0.5, y, 0.],
+            normal: [0., 0., 1.],
+            uv: [x, y],
+        };
+        let vertices = vec![vertex(0., 0.), vertex(1., 0.), vertex(1., 1.), vertex(0., 1.)];
+        SkidMarkRenderer {
+            mesh: Mesh::new(vertices, vec![0, 1, 2, 0, 2, 3]),
+            shader: Shader::for_effect("shadow", "skid", "Skid marks"),
+            width: 0.25,
+            opacity: 0.7,
+        }
+    }
+
+    /// Compile the shader again from its files, a shader that failed to load stays off.
+    pub fn reload_shader(&mut self) -> Result<(), ShaderError> {
+        match &mut self.shader {
+            Some(shader) => shader.reload(),
+            None => Ok(()),
+        }
+    }
+
+    /// Draw the `marks` to the bound framebuffer, after the ground and before anything on top of it.
+    pub fn draw(&self, marks: &SkidMarks, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        let shader = match &self.shader {
+            Some(shader) => shader,
+            None => return,
+        };
+        unsafe {
+            // The marks lie on the ground and must not hide each other.
+            gl::DepthMask(gl::FALSE);
+            shader.bind();
+            Shader::set_uniform_mat4(1, view);
+            Shader::set_uniform_mat4(2, projection);
+            for segment in &marks.segments {
+                Shader::set_uniform_mat4(0, &transform(segment, self.width));
+                Shader::set_uniform_float(6, marks.alpha(segment) * self.opacity);
+                self.mesh.draw();
+            }
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+}
+
+/// Return the transform of a unit quad from (-0.5, 0) to (0.5, 1) onto a `segment` `width` wide.
+fn transform(segment: &SkidSegment, width: f32) -> Matrix4<f32> {
+    let direction = segment.end - segment.start;
+    let yaw = (-direction[0]).atan2(direction[1]);
+    Matrix4::new_translation(&segment.start)
+        * Matrix4::from_euler_angles(0., 0., yaw)
+        * Matrix4::new_nonuniform_scaling(&Vector3::new(width, direction.norm(), 1.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{transform, SkidMarks, SkidSegment};
+    use crate::game::car::Car;
+    use nalgebra::{Point3, Vector3};
+
+    fn sliding_car(lateral: f32) -> Car {
+        let mut car = Car::test_car(Vector3::new(0., 0., 0.3), 1000.0);
+        car.set_velocity(Vector3::new(lateral, 10., 0.));
+        car
+    }
+
+    #[test]
+    fn marks_above_slip_threshold() {
+        let mut marks = SkidMarks::new();
+
+        // A slip angle of about 0.1 rad.
+        let mut cars = vec![sliding_car(1.)];
+        marks.update(&cars, 0.01);
+        marks.update(&cars, 0.01);
+        assert!(marks.segments.is_empty());
+
+        // A slip angle of about 0.46 rad, the first update only notes where the wheels are.
+        cars[0] = sliding_car(5.);
+        marks.update(&cars, 0.01);
+        assert!(marks.segments.is_empty());
+        cars[0].position += Vector3::new(0.05, 0.1, 0.);
+        marks.update(&cars, 0.01);
+        assert_eq!(marks.segments.len(), 2);
+        let segment = marks.segments[0];
+        assert!((segment.end - segment.start - Vector3::new(0.05, 0.1, 0.)).norm() < 1e-6);
+        // On the ground under the car.
+        assert!(segment.start[2] > 0. && segment.start[2] < 0.05);
+
+        // The marks fade out.
+        assert_eq!(marks.alpha(&segment), 1.);
+        marks.update(&[], marks.lifetime);
+        assert!(marks.segments.is_empty());
+    }
+
+    #[test]
+    fn capacity() {
+        let mut marks = SkidMarks::new();
+        marks.capacity = 5;
+        let mut cars = vec![sliding_car(5.)];
+        for step in 0..10 {
+            cars[0].position[1] = step as f32 * 0.1;
+            marks.update(&cars, 0.01);
+        }
+        assert_eq!(marks.segments.len(), 5);
+        // The newest segments are kept, the ones up to the last three steps.
+        let newest = marks.segments.back().unwrap().end[1];
+        assert!((newest - (0.9 - cars[0].dist_rear_axle)).abs() < 1e-6);
+        assert!((newest - marks.segments.front().unwrap().end[1] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn quad_covers_segment() {
+        let segment = SkidSegment {
+            start: Vector3::new(1., 2., 0.),
+            end: Vector3::new(4., 6., 0.),
+            age: 0.,
+        };
+        let m = transform(&segment, 0.25);
+        assert!((m.transform_point(&Point3::new(0., 0., 0.)).coords - segment.start).norm() < 1e-5);
+        assert!((m.transform_point(&Point3::new(0., 1., 0.)).coords - segment.end).norm() < 1e-5);
+    }
+}
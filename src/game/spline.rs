@@ -0,0 +1,153 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use nalgebra::{zero, Vector3};
+
+/// Number of straight pieces a segment is split into to measure distances along the spline.
+const STEPS_PER_SEGMENT: usize = 16;
+
+/// Smooth closed curve through control points, e.g. the racing line through the waypoints of a track.
+///
+/// The curve is a uniform Catmull-Rom spline. It passes through control point `i` at the parameter
+/// `t = i` and goes on from the last point back to the first one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spline {
+    points: Vec<Vector3<f32>>,
+}
+
+impl Spline {
+    /// Create a closed spline through the control `points`.
+    pub fn new(points: Vec<Vector3<f32>>) -> Spline {
+        Spline { points }
+    }
+
+    /// Return the number of segments, the parameter of the spline wraps around after it.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Return the point at the parameter `t`, which wraps around after `len()`.
+    pub fn sample(&self, t: f32) -> Vector3<f32> {
+        if self.is_empty() {
+            return zero();
+        }
+        let n = self.points.len();
+        let t = t.rem_euclid(n as f32);
+        // Rounding could wrap `t` up to `n`.
+        let i = (t.floor() as usize).min(n - 1);
+        let u = t - i as f32;
+        let p = |offset: usize| self.points[(i + n + offset - 1) % n];
+        catmull_rom(&p(0), &p(1), &p(2), &p(3), u)
+    }
+
+    /// Return the point on the spline `distance` [m] ahead of the parameter `current_t`.
+    pub fn point_ahead(&self, current_t: f32, distance: f32) -> Vector3<f32> {
+        self.sample(self.parameter_ahead(current_t, distance))
+    }
+
+    /// Return the parameter `distance` [m] ahead of `current_t` along the spline.
+    ///
+    /// The distance is measured along short straight pieces, so it is a little shorter than the arc.
+    pub fn parameter_ahead(&self, current_t: f32, distance: f32) -> f32 {
+        let step = 1. / STEPS_PER_SEGMENT as f32;
+        let mut t = current_t;
+        let mut previous = self.sample(t);
+        let mut remaining = distance;
+        // Once around the whole spline at most.
+        for _ in 0..self.len() * STEPS_PER_SEGMENT {
+            let next = self.sample(t + step);
+            let length = (next - previous).norm();
+            if length >= remaining {
+                return t + step * remaining / length;
+            }
+            remaining -= length;
+            t += step;
+            previous = next;
+        }
+        t
+    }
+
+    /// Return the parameter of the point on the spline closest to `position` in the xy-plane,
+    /// between 0 and `len()`.
+    pub fn closest_parameter(&self, position: &Vector3<f32>) -> f32 {
+        let steps = self.len() * STEPS_PER_SEGMENT;
+        let distance = |t: f32| {
+            let mut d = self.sample(t) - position;
+            d[2] = 0.;
+            d.norm_squared()
+        };
+        let closest = (0..steps).min_by(|&a, &b| {
+            let (a, b) = (a as f32 / STEPS_PER_SEGMENT as f32, b as f32 / STEPS_PER_SEGMENT as f32);
+            distance(a).partial_cmp(&distance(b)).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        closest.map_or(0., |i| i as f32 / STEPS_PER_SEGMENT as f32)
+    }
+}
+
+/// Interpolate between `p1` at `u = 0` and `p2` at `u = 1`, with the tangents given by `p0` and `p3`.
+fn catmull_rom(p0: &Vector3<f32>, p1: &Vector3<f32>, p2: &Vector3<f32>, p3: &Vector3<f32>, u: f32) -> Vector3<f32> {
+    let (u2, u3) = (u * u, u * u * u);
+    (p1 * 2. + (p2 - p0) * u + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * u2 + (p1 * 3. - p0 - p2 * 3. + p3) * u3) * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Spline;
+    use nalgebra::Vector3;
+
+    fn square() -> Spline {
+        Spline::new(vec![
+            Vector3::new(0., 0., 0.),
+            Vector3::new(100., 0., 0.),
+            Vector3::new(100., 100., 0.),
+            Vector3::new(0., 100., 0.),
+        ])
+    }
+
+    #[test]
+    fn passes_through_control_points() {
+        let spline = square();
+        for (i, point) in spline.points.iter().enumerate() {
+            assert!((spline.sample(i as f32) - point).norm() < 1e-4);
+            // The parameter wraps around in both directions.
+            assert!((spline.sample(i as f32 + 4.) - point).norm() < 1e-3);
+            assert!((spline.sample(i as f32 - 4.) - point).norm() < 1e-3);
+        }
+        // Between the control points the curve bulges out around the corners.
+        let halfway = spline.sample(0.5);
+        assert!((halfway[0] - 50.).abs() < 1e-4 && halfway[1] < 0.);
+    }
+
+    #[test]
+    fn point_ahead() {
+        let spline = Spline::new(vec![
+            Vector3::new(0., 0., 0.),
+            Vector3::new(0., 10., 0.),
+            Vector3::new(0., 20., 0.),
+            Vector3::new(0., 30., 0.),
+        ]);
+        // In the middle the spline is a straight line.
+        assert!((spline.point_ahead(1., 5.) - Vector3::new(0., 15., 0.)).norm() < 1e-3);
+        assert!((spline.point_ahead(1., 0.) - Vector3::new(0., 10., 0.)).norm() < 1e-5);
+
+        let spline = square();
+        let t = spline.closest_parameter(&Vector3::new(102., 50., 3.));
+        assert!((t - 1.5).abs() < 1e-5);
+        assert!(spline.parameter_ahead(t, 20.) > t);
+    }
+}
@@ -0,0 +1,96 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+/// Screen the game is on, it decides what is updated and drawn each frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GameState {
+    /// Main menu in front of the starting grid.
+    Menu,
+    Playing,
+    /// The race is frozen behind the ingame menu.
+    Paused,
+    /// All players finished the race.
+    Results,
+}
+
+/// Input moving the game from one screen to another.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StateInput {
+    /// Enter or a button like "Continue" was pressed.
+    Confirm,
+    /// Escape was pressed.
+    Back,
+    /// Every car completed the laps of the race.
+    RaceFinished,
+}
+
+impl GameState {
+    /// Return the state after the `input`. Inputs that don't apply to a screen are ignored.
+    pub fn next(self, input: StateInput) -> GameState {
+        use self::GameState::*;
+        use self::StateInput::*;
+
+        match (self, input) {
+            (Menu, Confirm) => Playing,
+            (Playing, Back) => Paused,
+            (Playing, RaceFinished) => Results,
+            (Paused, Confirm) | (Paused, Back) => Playing,
+            (Results, Confirm) | (Results, Back) => Menu,
+            (state, _) => state,
+        }
+    }
+
+    /// Return true if the simulation runs in this state.
+    pub fn is_running(self) -> bool {
+        self == GameState::Playing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GameState::{self, *};
+    use super::StateInput::*;
+
+    #[test]
+    fn transitions() {
+        let inputs = [
+            (Back, Menu),
+            (RaceFinished, Menu),
+            (Confirm, Playing),
+            (Confirm, Playing),
+            (Back, Paused),
+            (RaceFinished, Paused),
+            (Back, Playing),
+            (Back, Paused),
+            (Confirm, Playing),
+            (RaceFinished, Results),
+            (Back, Menu),
+            (Confirm, Playing),
+            (RaceFinished, Results),
+            (Confirm, Menu),
+        ];
+        let mut state = GameState::Menu;
+        for (input, expected) in inputs.iter() {
+            state = state.next(*input);
+            assert_eq!(state, *expected, "after {:?}", input);
+        }
+    }
+
+    #[test]
+    fn simulate_only_while_playing() {
+        assert!(Playing.is_running());
+        assert!(!Menu.is_running() && !Paused.is_running() && !Results.is_running());
+    }
+}
@@ -0,0 +1,768 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::car::{Car, Obb};
+use super::lap::FinishLine;
+use log::debug;
+use nalgebra::{clamp, inf, sup, zero, Vector3};
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Gravitational acceleration [m/s²].
+const GRAVITY: f32 = 9.81;
+
+/// A straight wall between two points in the x-y plane.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Wall {
+    pub a: Vector3<f32>,
+    pub b: Vector3<f32>,
+}
+
+impl Wall {
+    pub fn new(a: Vector3<f32>, b: Vector3<f32>) -> Wall {
+        Wall { a, b }
+    }
+}
+
+/// First contact of a box moving into a wall.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Impact {
+    /// Fraction [0.0; 1.0] of the movement at which the box touches the wall.
+    pub time: f32,
+    /// Unit normal of the contact, pointing from the wall back to the box.
+    pub normal: Vector3<f32>,
+}
+
+/// Walls that keep the cars on the race track.
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    pub walls: Vec<Wall>,
+}
+
+impl Track {
+    pub fn new(walls: Vec<Wall>) -> Track {
+        Track { walls }
+    }
+
+    /// Create a track from closed boundaries, e.g. the outer border and the inner borders around islands.
+    ///
+    /// Each boundary is a list of corners, the last corner is connected to the first one.
+    pub fn from_boundaries(boundaries: &[Vec<Vector3<f32>>]) -> Track {
+        let mut walls = Vec::new();
+        for corners in boundaries {
+            for (i, a) in corners.iter().enumerate() {
+                let b = corners[(i + 1) % corners.len()];
+                if *a != b {
+                    walls.push(Wall::new(*a, b));
+                }
+            }
+        }
+        Track::new(walls)
+    }
+
+    /// Return the minimum and maximum corner of the axis aligned box around all walls, `None` without walls.
+    pub fn bounds(&self) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let first = self.walls.first()?.a;
+        Some(self.walls.iter().fold((first, first), |(min, max), wall| {
+            (inf(&inf(&min, &wall.a), &wall.b), sup(&sup(&max, &wall.a), &wall.b))
+        }))
+    }
+
+    /// Push `car` out of all walls it overlaps and stop its movement into them,
+    /// while it keeps sliding along the walls. Returns true on a collision.
+    ///
+    /// A car that moved through a wall since its last update is put back to where it hit the wall first,
+    /// so fast cars can't pass through walls between two updates.
+    pub fn collide(&self, car: &mut Car) -> bool {
+        let mut is_collision = false;
+        if let Some(from) = car.previous_position() {
+            let obb = Obb {
+                center: from,
+                ..car.bounding_box()
+            };
+            let motion = car.position - from;
+            if let Some(impact) = self.sweep(&obb, &motion) {
+                car.position = from + motion * impact.time;
+                let velocity = car.velocity();
+                let into_wall = velocity.dot(&impact.normal);
+                if into_wall < 0. {
+                    car.set_velocity(velocity - impact.normal * into_wall);
+                }
+                is_collision = true;
+            }
+        }
+        for wall in &self.walls {
+            if let Some((normal, depth)) = penetration(car, wall) {
+                car.position += normal * depth;
+                let velocity = car.velocity();
+                let into_wall = velocity.dot(&normal);
+                if into_wall < 0. {
+                    car.set_velocity(velocity - normal * into_wall);
+                }
+                is_collision = true;
+            }
+        }
+        is_collision
+    }
+
+    /// Return the first wall contact of the box `obb` moving by `motion` in the x-y plane, `None` if
+    /// it doesn't hit any wall on the way.
+    ///
+    /// The box keeps its rotation, and walls it touches right at the start don't count.
+    pub fn sweep(&self, obb: &Obb, motion: &Vector3<f32>) -> Option<Impact> {
+        self.walls
+            .iter()
+            .filter_map(|wall| sweep(obb, motion, wall))
+            .fold(None, |first: Option<Impact>, impact| match first {
+                Some(first) if first.time <= impact.time => Some(first),
+                _ => Some(impact),
+            })
+    }
+
+    /// Return the fraction of the way from `from` to `to` at which the line between them first hits
+    /// a wall in the x-y plane, `None` if it doesn't hit any wall.
+    pub fn raycast(&self, from: &Vector3<f32>, to: &Vector3<f32>) -> Option<f32> {
+        self.walls
+            .iter()
+            .filter_map(|wall| segment_intersection(from, to, &wall.a, &wall.b))
+            .fold(None, |first, t| Some(first.map_or(t, |first: f32| first.min(t))))
+    }
+}
+
+/// Starting position and z-rotation [rad] of a car.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Spawn {
+    pub position: Vector3<f32>,
+    pub yaw: f32,
+}
+
+/// Inclined part of a track, between the corners `min` and `max` of a rectangle in the x-y plane.
+///
+/// The cars stay in the x-y plane, the incline only pulls them downhill.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Slope {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+    /// Unit normal of the surface, pointing up.
+    pub normal: Vector3<f32>,
+}
+
+impl Slope {
+    /// Return true if `position` lies above or below the slope.
+    pub fn contains(&self, position: &Vector3<f32>) -> bool {
+        (0..2).all(|i| position[i] >= self.min[i] && position[i] <= self.max[i])
+    }
+
+    /// Return the acceleration [m/s²] of a car in the x-y plane by gravity pulling it down the slope.
+    pub fn acceleration(&self) -> Vector3<f32> {
+        // The part of gravity along the surface, the rest is carried by the ground.
+        let gravity = Vector3::new(0., 0., -GRAVITY);
+        let along = gravity - self.normal * gravity.dot(&self.normal);
+        Vector3::new(along[0], along[1], 0.)
+    }
+}
+
+/// Grip of the ground under a car, relative to tarmac.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Surface {
+    /// Multiplier of the tire grip, lower values make the car slide and spin its wheels earlier.
+    pub grip: f32,
+    /// Multiplier of the rolling resistance, higher values slow the car down faster.
+    pub rolling_resistance: f32,
+}
+
+impl Default for Surface {
+    fn default() -> Surface {
+        SurfaceKind::Tarmac.surface()
+    }
+}
+
+/// What the ground of a part of the track is made of.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SurfaceKind {
+    Tarmac,
+    Dirt,
+    Grass,
+}
+
+impl SurfaceKind {
+    /// Return the grip of this kind of ground.
+    pub fn surface(self) -> Surface {
+        let (grip, rolling_resistance) = match self {
+            SurfaceKind::Tarmac => (1., 1.),
+            SurfaceKind::Dirt => (0.75, 1.5),
+            SurfaceKind::Grass => (0.6, 2.),
+        };
+        Surface { grip, rolling_resistance }
+    }
+}
+
+/// Part of a track with another surface than tarmac, between the corners `min` and `max` of a
+/// rectangle in the x-y plane.
+///
+/// The grip of the `surface` kind can be tuned for each region.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceRegion {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+    pub surface: SurfaceKind,
+    #[serde(default)]
+    pub grip: Option<f32>,
+    #[serde(default)]
+    pub rolling_resistance: Option<f32>,
+}
+
+impl SurfaceRegion {
+    /// Return true if `position` lies above or below the region.
+    pub fn contains(&self, position: &Vector3<f32>) -> bool {
+        (0..2).all(|i| position[i] >= self.min[i] && position[i] <= self.max[i])
+    }
+
+    /// Return the grip of the region, the one of its kind unless it is tuned.
+    pub fn surface(&self) -> Surface {
+        let surface = self.surface.surface();
+        Surface {
+            grip: self.grip.unwrap_or(surface.grip),
+            rolling_resistance: self.rolling_resistance.unwrap_or(surface.rolling_resistance),
+        }
+    }
+}
+
+/// Error while loading a `TrackLayout`.
+#[derive(Debug)]
+pub enum TrackError {
+    /// The file could not be read or is no valid json.
+    Json(serde_json::Error),
+    /// The file was parsed, but the track can't be driven.
+    Invalid(String),
+}
+
+impl fmt::Display for TrackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrackError::Json(e) => write!(f, "failed to load track: {}", e),
+            TrackError::Invalid(msg) => write!(f, "invalid track: {}", msg),
+        }
+    }
+}
+
+impl Error for TrackError {}
+
+impl From<serde_json::Error> for TrackError {
+    fn from(e: serde_json::Error) -> TrackError {
+        TrackError::Json(e)
+    }
+}
+
+/// Track definition as stored in a json file. Points are `[x, y, z]` arrays.
+///
+/// A boundary is a list of corners, which has to end at its first corner.
+#[derive(Debug, Deserialize)]
+struct TrackFile {
+    boundaries: Vec<Vec<Vector3<f32>>>,
+    spawns: Vec<Spawn>,
+    #[serde(default)]
+    finish_line: Option<(Vector3<f32>, Vector3<f32>)>,
+    #[serde(default)]
+    checkpoints: Vec<(Vector3<f32>, Vector3<f32>)>,
+    #[serde(default)]
+    waypoints: Vec<Vector3<f32>>,
+    #[serde(default)]
+    slopes: Vec<Slope>,
+    #[serde(default)]
+    surfaces: Vec<SurfaceRegion>,
+    #[serde(default)]
+    laps: Option<usize>,
+}
+
+/// Everything needed to race on a track: walls, starting positions, finish line, waypoints for the AI
+/// and slopes.
+#[derive(Debug, Clone)]
+pub struct TrackLayout {
+    pub track: Track,
+    pub spawns: Vec<Spawn>,
+    pub finish_line: Option<FinishLine>,
+    /// Lines to cross in order before crossing the finish line counts as a lap.
+    pub checkpoints: Vec<FinishLine>,
+    pub waypoints: Vec<Vector3<f32>>,
+    /// Inclined parts of the track, the rest of it is flat.
+    pub slopes: Vec<Slope>,
+    /// Parts of the track off the tarmac, the first one containing a point counts.
+    pub surfaces: Vec<SurfaceRegion>,
+    /// Laps of a race on this track, unless the settings ask for another number.
+    pub laps: Option<usize>,
+}
+
+impl TrackLayout {
+    /// Load a track layout from a json file.
+    pub fn load(path: &Path) -> Result<TrackLayout, TrackError> {
+        debug!("Loading track from {}", path.display());
+        TrackLayout::from_json(&fs::read_to_string(path).map_err(serde_json::Error::io)?)
+    }
+
+    /// Parse a track layout from a json string.
+    pub fn from_json(json: &str) -> Result<TrackLayout, TrackError> {
+        TrackLayout::from_file(serde_json::from_str(json)?)
+    }
+
+    fn from_file(file: TrackFile) -> Result<TrackLayout, TrackError> {
+        if file.spawns.is_empty() {
+            return Err(TrackError::Invalid(String::from("there is no spawn point")));
+        }
+        if file.boundaries.is_empty() {
+            return Err(TrackError::Invalid(String::from("there is no boundary")));
+        }
+        for (i, corners) in file.boundaries.iter().enumerate() {
+            if corners.len() < 4 || corners.first() != corners.last() {
+                return Err(TrackError::Invalid(format!("boundary {} is not closed", i)));
+            }
+        }
+        let mut slopes = file.slopes;
+        for (i, slope) in slopes.iter_mut().enumerate() {
+            // The ground has to face up, the normal doesn't have to be normalized in the file.
+            if slope.normal[2] <= 0. || slope.normal[2].is_nan() {
+                return Err(TrackError::Invalid(format!("slope {} doesn't face up", i)));
+            }
+            slope.normal.normalize_mut();
+        }
+
+        Ok(TrackLayout {
+            track: Track::from_boundaries(&file.boundaries),
+            spawns: file.spawns,
+            finish_line: file.finish_line.map(|(a, b)| FinishLine::new(a, b)),
+            checkpoints: file.checkpoints.into_iter().map(|(a, b)| FinishLine::new(a, b)).collect(),
+            waypoints: file.waypoints,
+            slopes,
+            surfaces: file.surfaces,
+            laps: file.laps,
+        })
+    }
+
+    /// Return the slope at `position`, `None` on flat parts of the track.
+    pub fn slope_at(&self, position: &Vector3<f32>) -> Option<&Slope> {
+        self.slopes.iter().find(|slope| slope.contains(position))
+    }
+
+    /// Return the grip of the ground at `position`, tarmac outside of the surface regions.
+    pub fn surface_at(&self, position: &Vector3<f32>) -> Surface {
+        match self.surfaces.iter().find(|region| region.contains(position)) {
+            Some(region) => region.surface(),
+            None => Surface::default(),
+        }
+    }
+
+    /// Return the point closest to `position` to put a lost car back on the track, and the distance
+    /// of `position` from the track.
+    ///
+    /// With waypoints the car respawns at the nearest waypoint facing the next one, and the distance is
+    /// measured to the line through all waypoints. Without waypoints the nearest spawn point is used.
+    pub fn nearest_respawn(&self, position: &Vector3<f32>) -> Option<(Spawn, f32)> {
+        let flat = |v: &Vector3<f32>| Vector3::new(v[0], v[1], 0.);
+        let p = flat(position);
+        if self.waypoints.is_empty() {
+            return self
+                .spawns
+                .iter()
+                .map(|spawn| (*spawn, (flat(&spawn.position) - p).norm()))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+        }
+
+        let n = self.waypoints.len();
+        let mut distance = f32::MAX;
+        let mut nearest = 0;
+        for (i, waypoint) in self.waypoints.iter().enumerate() {
+            let (a, b) = (flat(waypoint), flat(&self.waypoints[(i + 1) % n]));
+            distance = distance.min(distance_to_segment(&p, &a, &b));
+            if (a - p).norm() < (flat(&self.waypoints[nearest]) - p).norm() {
+                nearest = i;
+            }
+        }
+        let direction = self.waypoints[(nearest + 1) % n] - self.waypoints[nearest];
+        let spawn = Spawn {
+            position: self.waypoints[nearest],
+            yaw: (-direction[0]).atan2(direction[1]),
+        };
+        Some((spawn, distance))
+    }
+}
+
+/// Puts cars back on the track after they went out of bounds.
+pub struct Respawner {
+    /// Distance [m] from the track at which a car counts as lost.
+    pub max_distance: f32,
+    /// Time [s] after a respawn in which a car isn't respawned again.
+    pub cooldown: f32,
+    cooldowns: Vec<f32>,
+}
+
+impl Default for Respawner {
+    fn default() -> Respawner {
+        Respawner {
+            max_distance: 30.,
+            cooldown: 2.,
+            cooldowns: Vec::new(),
+        }
+    }
+}
+
+impl Respawner {
+    /// Respawn the cars after a time step `dt` that are too far away from the track of the `layout`.
+    ///
+    /// Returns the indices of the respawned cars.
+    pub fn update(&mut self, layout: &TrackLayout, cars: &mut [Car], dt: f32) -> Vec<usize> {
+        self.cooldowns.resize(cars.len(), 0.);
+        let mut respawned = Vec::new();
+        for (id, (car, cooldown)) in cars.iter_mut().zip(self.cooldowns.iter_mut()).enumerate() {
+            *cooldown = (*cooldown - dt).max(0.);
+            if *cooldown > 0. {
+                continue;
+            }
+            if let Some((spawn, distance)) = layout.nearest_respawn(&car.position) {
+                if distance > self.max_distance {
+                    debug!("Car {} is {}m off track, respawning at {:?}", id, distance, spawn.position);
+                    // The boost isn't refilled, so leaving the track doesn't pay off.
+                    let boost = car.boost;
+                    car.reset(spawn.position);
+                    car.rotation[2] = spawn.yaw;
+                    car.boost = boost;
+                    *cooldown = self.cooldown;
+                    respawned.push(id);
+                }
+            }
+        }
+        respawned
+    }
+}
+
+/// Return the distance of `p` to the segment from `a` to `b`.
+fn distance_to_segment(p: &Vector3<f32>, a: &Vector3<f32>, b: &Vector3<f32>) -> f32 {
+    let ab = b - a;
+    let length_squared = ab.norm_squared();
+    let t = if length_squared > 0. {
+        clamp((p - a).dot(&ab) / length_squared, 0., 1.)
+    } else {
+        0.
+    };
+    (p - (a + ab * t)).norm()
+}
+
+/// Return the fraction of the way from `p` to `q` at which the segment between them crosses the
+/// segment from `a` to `b` in the x-y plane, `None` if they don't cross or are parallel.
+fn segment_intersection(p: &Vector3<f32>, q: &Vector3<f32>, a: &Vector3<f32>, b: &Vector3<f32>) -> Option<f32> {
+    let cross = |u: &Vector3<f32>, v: &Vector3<f32>| u[0] * v[1] - u[1] * v[0];
+    let (pq, ab, pa) = (q - p, b - a, a - p);
+    let denominator = cross(&pq, &ab);
+    if denominator.abs() < f32::EPSILON {
+        return None;
+    }
+    let t = cross(&pa, &ab) / denominator;
+    let u = cross(&pa, &pq) / denominator;
+    if (0. ..=1.).contains(&t) && (0. ..=1.).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Return the normal pointing from `wall` to the car and the penetration depth [m] if the
+/// collider of the car overlaps the wall in the x-y plane.
+fn penetration(car: &Car, wall: &Wall) -> Option<(Vector3<f32>, f32)> {
+    let obb = car.bounding_box();
+    let [right, forward] = obb.axes();
+    let along = (wall.b - wall.a).normalize();
+    let normal = Vector3::new(-along[1], along[0], 0.);
+
+    // Separating axis theorem with the axes of the car and the normal of the wall.
+    let mut contact = (zero(), f32::MAX);
+    for axis in &[right, forward, normal] {
+        let center = obb.center.dot(axis);
+        let radius = obb.radius(axis);
+        let (wall_a, wall_b) = (wall.a.dot(axis), wall.b.dot(axis));
+        let (wall_min, wall_max) = (wall_a.min(wall_b), wall_a.max(wall_b));
+
+        let depth = (center + radius - wall_min).min(wall_max - (center - radius));
+        if depth < 0. {
+            return None;
+        }
+        if depth < contact.1 {
+            // Push the car to the side of the wall its center is on.
+            let normal = if center < (wall_min + wall_max) * 0.5 { -axis } else { *axis };
+            contact = (normal, depth);
+        }
+    }
+    Some(contact)
+}
+
+/// Return the first contact of the box `obb` moving by `motion` with `wall` in the x-y plane.
+///
+/// Separating axis theorem over time: on each of the axes of the box and the normal of the wall,
+/// the projections overlap during an interval of the movement. The box hits the wall when the
+/// last of these intervals starts, if that is before the first one ends.
+fn sweep(obb: &Obb, motion: &Vector3<f32>, wall: &Wall) -> Option<Impact> {
+    let [right, forward] = obb.axes();
+    let along = (wall.b - wall.a).normalize();
+    let wall_normal = Vector3::new(-along[1], along[0], 0.);
+
+    let (mut enter, mut exit) = (f32::NEG_INFINITY, f32::INFINITY);
+    let mut normal = zero();
+    for axis in &[right, forward, wall_normal] {
+        // The center of the box overlaps the wall widened by the radius of the box.
+        let center = obb.center.dot(axis);
+        let radius = obb.radius(axis);
+        let (wall_a, wall_b) = (wall.a.dot(axis), wall.b.dot(axis));
+        let (min, max) = (wall_a.min(wall_b) - radius, wall_a.max(wall_b) + radius);
+        let speed = motion.dot(axis);
+        if speed.abs() < f32::EPSILON {
+            if center < min || center > max {
+                return None;
+            }
+            continue;
+        }
+        let (t_min, t_max) = ((min - center) / speed, (max - center) / speed);
+        let (t_enter, t_exit) = (t_min.min(t_max), t_min.max(t_max));
+        if t_enter > enter {
+            enter = t_enter;
+            normal = if speed > 0. { -axis } else { *axis };
+        }
+        exit = exit.min(t_exit);
+    }
+    if enter > 0. && enter <= 1. && enter <= exit {
+        Some(Impact { time: enter, normal })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{penetration, segment_intersection, Respawner, Spawn, Track, TrackError, TrackLayout, Wall};
+    use crate::game::car::{Car, Obb};
+    use crate::game::controller::{Controller, ControllerLayout};
+    use nalgebra::Vector3;
+    use std::f32::consts::FRAC_PI_4;
+
+    /// A wall along the x-axis at y = 5.
+    fn test_track() -> Track {
+        Track::new(vec![Wall::new(Vector3::new(-50., 5., 0.), Vector3::new(50., 5., 0.))])
+    }
+
+    #[test]
+    fn sweep_into_wall() {
+        let track = test_track();
+        let mut car = Car::test_car(Vector3::new(0., 2.7, 0.), 1000.0);
+        car.set_velocity(Vector3::new(0., 100., 0.));
+        car.update(0.05, None);
+
+        // The car moved from in front of the wall to behind it without ever touching it.
+        let from = car.previous_position().unwrap();
+        assert!(car.position[1] - 1. > 5.);
+        assert!(penetration(&car, &track.walls[0]).is_none());
+
+        let obb = Obb {
+            center: from,
+            ..car.bounding_box()
+        };
+        let impact = track.sweep(&obb, &(car.position - from)).unwrap();
+        assert!(impact.time > 0. && impact.time < 1.);
+        assert!((impact.normal - Vector3::new(0., -1., 0.)).norm() < 1e-5);
+        assert!(track.sweep(&obb, &Vector3::new(10., 0., 0.)).is_none());
+
+        // Stopped with the front at the wall.
+        assert!(track.collide(&mut car));
+        assert!((car.position[1] + 1. - 5.).abs() < 1e-4);
+        assert!(car.velocity()[1].abs() < 1e-4);
+    }
+
+    #[test]
+    fn stop_at_wall_and_slide() {
+        let track = test_track();
+        let mut car = Car::test_car(Vector3::new(0., 4.5, 0.), 1000.0);
+        car.set_velocity(Vector3::new(-4., 10., 0.));
+
+        assert!(track.collide(&mut car));
+        // The front of the car touches the wall, only the velocity along the wall is left.
+        assert!((car.position[1] + 1. - 5.).abs() < 1e-5);
+        assert_eq!(car.velocity(), Vector3::new(-4., 0., 0.));
+
+        // Moving away from the wall isn't stopped.
+        let mut car = Car::test_car(Vector3::new(0., 4.5, 0.), 1000.0);
+        car.set_velocity(Vector3::new(0., -3., 0.));
+        track.collide(&mut car);
+        assert_eq!(car.velocity(), Vector3::new(0., -3., 0.));
+
+        assert!(!track.collide(&mut Car::test_car(Vector3::new(0., 0., 0.), 1000.0)));
+    }
+
+    #[test]
+    fn drive_into_wall() {
+        let track = test_track();
+        let mut car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        // Hit the wall at an angle of 45°.
+        car.rotation[2] = FRAC_PI_4;
+        let mut controller = Controller::new(false, &ControllerLayout::WASD);
+        controller.set_axis(0., 1.);
+
+        for _ in 0..300 {
+            car.update(0.01, Some(controller));
+            track.collide(&mut car);
+            assert!(car.position[1] < 5.);
+        }
+        // The car is stuck at the wall, but slides along it.
+        assert!(car.position[1] > 3.);
+        assert!(car.position[0] < -2.);
+        assert!(car.velocity()[1] <= 1e-5);
+    }
+
+    #[test]
+    fn ray_hits_wall() {
+        let (a, b) = (Vector3::new(-5., 5., 0.), Vector3::new(5., 5., 0.));
+        // Crossing the wall halfway, the height doesn't matter.
+        let t = segment_intersection(&Vector3::new(0., 0., 0.5), &Vector3::new(2., 10., 4.), &a, &b).unwrap();
+        assert!((t - 0.5).abs() < 1e-6);
+        // Stopping short of the wall, passing its end and running parallel to it.
+        assert_eq!(
+            segment_intersection(&Vector3::new(0., 0., 0.), &Vector3::new(0., 4., 0.), &a, &b),
+            None
+        );
+        assert_eq!(
+            segment_intersection(&Vector3::new(6., 0., 0.), &Vector3::new(6., 10., 0.), &a, &b),
+            None
+        );
+        assert_eq!(
+            segment_intersection(&Vector3::new(-5., 4., 0.), &Vector3::new(5., 4., 0.), &a, &b),
+            None
+        );
+
+        // The first of several walls is hit.
+        let mut track = test_track();
+        track.walls.push(Wall::new(Vector3::new(-50., 2., 0.), Vector3::new(50., 2., 0.)));
+        let t = track.raycast(&Vector3::new(0., 0., 0.), &Vector3::new(0., 8., 0.)).unwrap();
+        assert!((t - 0.25).abs() < 1e-6);
+        assert_eq!(track.raycast(&Vector3::new(0., 0., 0.), &Vector3::new(0., -8., 0.)), None);
+    }
+
+    #[test]
+    fn closed_boundaries() {
+        let outer = vec![
+            Vector3::new(-10., -10., 0.),
+            Vector3::new(10., -10., 0.),
+            Vector3::new(10., 10., 0.),
+            Vector3::new(-10., 10., 0.),
+        ];
+        let inner = vec![Vector3::new(-2., -2., 0.), Vector3::new(2., -2., 0.), Vector3::new(0., 2., 0.)];
+        let track = Track::from_boundaries(&[outer, inner]);
+        assert_eq!(track.walls.len(), 7);
+        assert_eq!(track.walls[3], Wall::new(Vector3::new(-10., 10., 0.), Vector3::new(-10., -10., 0.)));
+
+        // A car on the inner boundary is pushed outside of the island.
+        let mut car = Car::test_car(Vector3::new(0., -2.5, 0.), 1000.0);
+        assert!(track.collide(&mut car));
+        assert!(car.position[1] < -2.9);
+    }
+
+    #[test]
+    fn load_layout() {
+        let json = r#"{
+            "boundaries": [
+                [[-20, -20, 0], [20, -20, 0], [20, 20, 0], [-20, 20, 0], [-20, -20, 0]],
+                [[-5, -5, 0], [5, -5, 0], [5, 5, 0], [-5, 5, 0], [-5, -5, 0]]
+            ],
+            "spawns": [
+                { "position": [-12, 0, 0.5], "yaw": 0 },
+                { "position": [-14, 0, 0.5], "yaw": 0 }
+            ],
+            "finish_line": [[-20, 0, 0], [-5, 0, 0]],
+            "waypoints": [[-12, 12, 0], [12, 12, 0], [12, -12, 0], [-12, -12, 0]]
+        }"#;
+        let layout = TrackLayout::from_json(json).unwrap();
+        assert_eq!(layout.spawns.len(), 2);
+        assert_eq!(layout.track.walls.len(), 8);
+        assert_eq!(
+            layout.spawns[1],
+            Spawn {
+                position: Vector3::new(-14., 0., 0.5),
+                yaw: 0.
+            }
+        );
+        assert!(layout.finish_line.is_some());
+        assert_eq!(layout.waypoints.len(), 4);
+    }
+
+    #[test]
+    fn reject_invalid_layout() {
+        let open = r#"{ "boundaries": [[[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]]], "spawns": [{ "position": [0, 0, 0], "yaw": 0 }] }"#;
+        assert!(matches!(TrackLayout::from_json(open), Err(TrackError::Invalid(_))));
+
+        let no_spawn = r#"{ "boundaries": [[[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 0, 0]]], "spawns": [] }"#;
+        assert!(matches!(TrackLayout::from_json(no_spawn), Err(TrackError::Invalid(_))));
+
+        assert!(matches!(TrackLayout::from_json("{ \"spawns\": 1 }"), Err(TrackError::Json(_))));
+    }
+
+    /// A square loop of waypoints driven counterclockwise.
+    fn loop_layout() -> TrackLayout {
+        let layout = TrackLayout::from_json(
+            r#"{
+                "boundaries": [[[-50, -50, 0], [50, -50, 0], [50, 50, 0], [-50, 50, 0], [-50, -50, 0]]],
+                "spawns": [{ "position": [0, -20, 0.5], "yaw": 0 }],
+                "waypoints": [[20, -20, 0], [20, 20, 0], [-20, 20, 0], [-20, -20, 0]]
+            }"#,
+        );
+        layout.unwrap()
+    }
+
+    #[test]
+    fn respawn_off_track() {
+        let layout = loop_layout();
+        let mut respawner = Respawner {
+            max_distance: 10.,
+            ..Default::default()
+        };
+        let mut cars = vec![
+            Car::test_car(Vector3::new(25., 18., 0.5), 1000.0),
+            Car::test_car(Vector3::new(60., 30., 0.5), 1000.0),
+        ];
+        cars[1].set_velocity(Vector3::new(5., 20., 0.));
+        cars[1].boost = 40.;
+
+        // Only the car far away from the line through the waypoints is lost.
+        assert_eq!(respawner.update(&layout, &mut cars, 0.1), vec![1]);
+        assert_eq!(cars[0].position, Vector3::new(25., 18., 0.5));
+        assert_eq!(cars[1].position, Vector3::new(20., 20., 0.));
+        assert_eq!(cars[1].velocity(), Vector3::new(0., 0., 0.));
+        assert_eq!(cars[1].boost, 40.);
+        // Facing the next waypoint towards -x.
+        assert!((cars[1].forward() - Vector3::new(-1., 0., 0.)).norm() < 1e-5);
+
+        // Thrown off again right away, the car gets a moment to find back.
+        cars[1].position = Vector3::new(60., 30., 0.5);
+        assert!(respawner.update(&layout, &mut cars, 1.).is_empty());
+        assert_eq!(respawner.update(&layout, &mut cars, 1.), vec![1]);
+    }
+
+    #[test]
+    fn respawn_at_spawn_without_waypoints() {
+        let mut layout = loop_layout();
+        layout.waypoints.clear();
+        let (spawn, distance) = layout.nearest_respawn(&Vector3::new(0., -60., 0.)).unwrap();
+        assert_eq!(spawn, layout.spawns[0]);
+        assert!((distance - 40.).abs() < 1e-5);
+    }
+}
@@ -12,10 +12,36 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use crate::game::car::Car;
+use crate::game::track::Track;
 use crate::util::Lerp;
 use log::info;
 use nalgebra::{clamp, Matrix4, Point3, Vector3};
 use serde_derive::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+
+/// Vertical field of view [rad] of the cameras, about 50°.
+pub const BASE_FOV: f32 = 0.87;
+
+/// Closest distance [m] to the camera that is drawn.
+pub const NEAR_PLANE: f32 = 1.0;
+
+/// Vertical field of view [rad] of the hood camera, wider to see the road right in front, about 60°.
+const HOOD_FOV: f32 = 1.05;
+/// Near plane [m] of the hood camera, it sits right above the body and must not cut off the road.
+const HOOD_NEAR_PLANE: f32 = 0.1;
+/// Height [m] of the hood camera above the top of the car.
+const HOOD_HEIGHT: f32 = 0.3;
+/// Distance [m] ahead of the hood camera it looks at.
+const HOOD_LOOK_AHEAD: f32 = 10.;
+/// Height [m] of the top-down camera above the car.
+const TOP_DOWN_HEIGHT: f32 = 40.;
+
+/// Largest pitch [rad] of the `DebugCamera` up or down, short of straight up so the view doesn't flip.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Gap [m] the `ChaseCamera` keeps in front of a wall, so the wall doesn't clip the view.
+const WALL_MARGIN: f32 = 0.3;
 
 /// Camera to calculate the view matrix and follow ingame objects.
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -98,6 +124,11 @@ impl Camera {
         self.height = self.height_goal;
     }
 
+    /// Return the height [m] of the camera above its focus.
+    pub fn focus_distance(&self) -> f32 {
+        self.height
+    }
+
     /// Get the view matrix, calculated from camera values.
     pub fn get_viewmatrix(&self) -> Matrix4<f32> {
         Matrix4::look_at_rh(
@@ -107,3 +138,397 @@ impl Camera {
         )
     }
 }
+
+/// Where the `ChaseCamera` views its car from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraMode {
+    /// From behind and above, swinging around after the car.
+    Chase,
+    /// From the front of the car, looking ahead.
+    Hood,
+    /// From straight above with north up, the car turns below the camera.
+    TopDown,
+}
+
+impl CameraMode {
+    /// Return the mode after this one, to cycle through all of them.
+    pub fn next(self) -> CameraMode {
+        match self {
+            CameraMode::Chase => CameraMode::Hood,
+            CameraMode::Hood => CameraMode::TopDown,
+            CameraMode::TopDown => CameraMode::Chase,
+        }
+    }
+}
+
+/// Camera that chases a car from behind and above, or from one of the other `CameraMode`s.
+///
+/// Position and focus follow the car with a lerp, so the camera swings around smoothly instead of
+/// snapping behind the car when it turns sharply.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct ChaseCamera {
+    position: Vector3<f32>,
+    focus: Vector3<f32>,
+
+    /// Distance behind the car [m].
+    pub distance: f32,
+    /// Height above the car [m].
+    pub height: f32,
+    /// Factor per second the camera closes in on its goal.
+    pub speed: f32,
+    /// Closest distance [m] to the car when a wall pushes the camera towards it.
+    pub min_distance: f32,
+
+    /// Vertical field of view [rad], it widens with the speed of the car.
+    fov: f32,
+    /// Field of view [rad] at a standstill.
+    pub base_fov: f32,
+    /// Field of view [rad] at `max_fov_speed` and faster.
+    pub max_fov: f32,
+    /// Speed [m/s] of the car at which the field of view is widest.
+    pub max_fov_speed: f32,
+    /// Fastest change of the field of view [rad/s], so a crash doesn't jerk the view.
+    pub max_fov_rate: f32,
+    /// Point of view on the car, only the chase mode follows it smoothly.
+    pub mode: CameraMode,
+}
+
+impl ChaseCamera {
+    /// Create a new `ChaseCamera` with fixed values, looking at the origin.
+    pub fn new() -> ChaseCamera {
+        info!("ChaseCamera::new()");
+        let distance = 8.;
+        let height = 4.;
+        ChaseCamera {
+            position: Vector3::new(0., -distance, height),
+            focus: Vector3::new(0., 0., 0.),
+            distance,
+            height,
+            speed: 3.,
+            min_distance: 3.,
+            fov: BASE_FOV,
+            base_fov: BASE_FOV,
+            max_fov: BASE_FOV + 0.25,
+            max_fov_speed: 40.,
+            max_fov_rate: 0.2,
+            mode: CameraMode::Chase,
+        }
+    }
+
+    /// Move the camera towards its goal behind `target` relative to the delta time `dt`.
+    pub fn update(&mut self, target: &Car, dt: f32) {
+        let (position_goal, focus_goal) = self.goal(target);
+        if self.mode == CameraMode::Chase {
+            self.position = Vector3::lerp(&self.position, &position_goal, self.speed * dt);
+            self.focus = Vector3::lerp(&self.focus, &focus_goal, self.speed * dt);
+        } else {
+            // A lagging hood camera would end up inside the car.
+            self.position = position_goal;
+            self.focus = focus_goal;
+        }
+        self.update_fov(target.speed(), dt);
+    }
+
+    /// Pull the camera in front of the first wall of `track` between it and `target`, so the car stays visible.
+    ///
+    /// Only the chase camera is behind the car, the others see it anyway.
+    pub fn avoid_walls(&mut self, target: &Car, track: &Track) {
+        if self.mode != CameraMode::Chase {
+            return;
+        }
+        if let Some(t) = track.raycast(&target.position, &self.position) {
+            let offset = self.position - target.position;
+            let length = offset.norm();
+            let distance = (length * t - WALL_MARGIN).max(self.min_distance).min(length);
+            self.position = target.position + offset * (distance / length);
+        }
+    }
+
+    /// Return the vertical field of view [rad] to build the projection with.
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    /// Return the closest distance [m] to the camera that is drawn, to build the projection with.
+    pub fn near_plane(&self) -> f32 {
+        match self.mode {
+            CameraMode::Hood => HOOD_NEAR_PLANE,
+            CameraMode::Chase | CameraMode::TopDown => NEAR_PLANE,
+        }
+    }
+
+    /// Return the distance [m] of the camera to the point it looks at.
+    pub fn focus_distance(&self) -> f32 {
+        (self.position - self.focus).norm()
+    }
+
+    /// Return the field of view [rad] at a standstill in the current mode.
+    fn mode_base_fov(&self) -> f32 {
+        match self.mode {
+            CameraMode::Hood => HOOD_FOV,
+            CameraMode::Chase | CameraMode::TopDown => self.base_fov,
+        }
+    }
+
+    /// Widen the field of view towards the goal for a car at `speed` [m/s] relative to the delta time `dt`.
+    fn update_fov(&mut self, speed: f32, dt: f32) {
+        let factor = clamp(speed / self.max_fov_speed, 0., 1.);
+        let base_fov = self.mode_base_fov();
+        let goal = base_fov + (self.max_fov - self.base_fov) * factor;
+        let max_change = self.max_fov_rate * dt;
+        let change = f32::lerp(&self.fov, &goal, self.speed * dt) - self.fov;
+        self.fov += clamp(change, -max_change, max_change);
+    }
+
+    /// Put the camera behind `target` instantaneously.
+    pub fn set_target(&mut self, target: &Car) {
+        let (position, focus) = self.goal(target);
+        self.position = position;
+        self.focus = focus;
+        self.fov = self.mode_base_fov();
+    }
+
+    /// Get the view matrix, calculated from camera values.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        // Looking straight down, z can't be up.
+        let up = match self.mode {
+            CameraMode::TopDown => Vector3::y(),
+            CameraMode::Chase | CameraMode::Hood => Vector3::z(),
+        };
+        Matrix4::look_at_rh(&Point3::from(self.position), &Point3::from(self.focus), &up)
+    }
+
+    /// Return the position and focus the camera moves to for the pose of `target` in the current mode.
+    fn goal(&self, target: &Car) -> (Vector3<f32>, Vector3<f32>) {
+        match self.mode {
+            CameraMode::Chase => {
+                let position = target.position - target.forward() * self.distance + Vector3::new(0., 0., self.height);
+                (position, target.position)
+            }
+            CameraMode::Hood => {
+                // Above the front edge of the body, so none of the car is in front of the near plane.
+                let extents = target.bounding_box().half_extents;
+                let position = target.position + target.forward() * extents[1] + Vector3::z() * (extents[2] + HOOD_HEIGHT);
+                (position, position + target.forward() * HOOD_LOOK_AHEAD)
+            }
+            CameraMode::TopDown => (target.position + Vector3::z() * TOP_DOWN_HEIGHT, target.position),
+        }
+    }
+}
+
+/// Camera flying freely through the level, detached from the cars to debug the track and physics.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct DebugCamera {
+    pub position: Vector3<f32>,
+    /// Rotation around the z-axis [rad], 0 looks along the y-axis like the cars.
+    pub yaw: f32,
+    /// Rotation up (positive) or down [rad], between -`MAX_PITCH` and `MAX_PITCH`.
+    pub pitch: f32,
+
+    /// Movement speed [m/s].
+    pub speed: f32,
+    /// Rotation per moved pixel of the mouse [rad].
+    pub sensitivity: f32,
+}
+
+impl DebugCamera {
+    /// Create a new `DebugCamera` at the eye of the `view` matrix, looking in the same direction.
+    pub fn from_view(view: &Matrix4<f32>) -> DebugCamera {
+        info!("DebugCamera::from_view()");
+        let inverse = view.try_inverse().unwrap_or_else(Matrix4::identity);
+        let position = inverse.column(3).xyz();
+        // The view looks along its negative z-axis.
+        let direction = -inverse.column(2).xyz();
+        DebugCamera {
+            position,
+            yaw: (-direction[0]).atan2(direction[1]),
+            pitch: clamp(direction[2].asin(), -MAX_PITCH, MAX_PITCH),
+            speed: 20.,
+            sensitivity: 0.003,
+        }
+    }
+
+    /// Return the unit vector the camera looks along.
+    pub fn direction(&self) -> Vector3<f32> {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Vector3::new(-sin_yaw * cos_pitch, cos_yaw * cos_pitch, sin_pitch)
+    }
+
+    /// Turn the camera by the mouse movement `dx` to the right and `dy` down [px].
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * self.sensitivity;
+        self.pitch = clamp(self.pitch - dy * self.sensitivity, -MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Move the camera along its direction by `forward`, to the side by `right` and along the z-axis
+    /// by `up` relative to the delta time `dt`. The inputs are between -1.0 and 1.0.
+    pub fn update(&mut self, dt: f32, forward: f32, right: f32, up: f32) {
+        let direction = self.direction();
+        let side = direction.cross(&Vector3::z()).normalize();
+        self.position += (direction * forward + side * right + Vector3::z() * up) * self.speed * dt;
+    }
+
+    /// Get the view matrix, calculated from camera values.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(
+            &Point3::from(self.position),
+            &Point3::from(self.position + self.direction()),
+            &Vector3::z(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CameraMode, ChaseCamera, DebugCamera, BASE_FOV, MAX_PITCH, NEAR_PLANE};
+    use crate::game::car::Car;
+    use crate::game::track::{Track, Wall};
+    use nalgebra::{Matrix4, Point3, Vector3};
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    #[test]
+    fn chase_camera_converges_behind_car() {
+        let mut car = Car::test_car(Vector3::new(10., 5., 0.), 1000.0);
+        // Facing along -x, so behind is +x.
+        car.rotation[2] = FRAC_PI_2;
+        let mut camera = ChaseCamera::new();
+        let expected = Vector3::new(10. + camera.distance, 5., camera.height);
+
+        let mut last_error = (camera.position - expected).norm();
+        for _ in 0..20 {
+            camera.update(&car, 0.1);
+            let error = (camera.position - expected).norm();
+            assert!(error < last_error);
+            last_error = error;
+        }
+        for _ in 0..100 {
+            camera.update(&car, 0.1);
+        }
+        assert!((camera.position - expected).norm() < 1e-3);
+        assert!((camera.focus - car.position).norm() < 1e-3);
+    }
+
+    #[test]
+    fn camera_modes() {
+        let mut car = Car::test_car(Vector3::new(10., 5., 0.), 1000.0);
+        car.rotation[2] = FRAC_PI_4;
+        let mut camera = ChaseCamera::new();
+        let mut positions: Vec<Vector3<f32>> = Vec::new();
+        for mode in &[CameraMode::Chase, CameraMode::Hood, CameraMode::TopDown] {
+            camera.mode = *mode;
+            camera.set_target(&car);
+            assert!(positions.iter().all(|position| (position - camera.position).norm() > 1.));
+            positions.push(camera.position);
+            assert!(camera.view_matrix().iter().all(|x| x.is_finite()));
+            assert_eq!(camera.mode.next().next().next(), camera.mode);
+        }
+        let (chase, hood, top_down) = (positions[0], positions[1], positions[2]);
+
+        // Behind, in front of and above the car.
+        assert!((chase - car.position).dot(&car.forward()) < 0.);
+        assert!((hood - car.position).dot(&car.forward()) > 0.);
+        assert!((top_down - Vector3::new(10., 5., 40.)).norm() < 1e-5);
+
+        // The hood camera sits above the body and draws the road just in front of it.
+        camera.mode = CameraMode::Hood;
+        camera.set_target(&car);
+        assert!(hood[2] > car.position[2] + car.bounding_box().half_extents[2]);
+        assert!(camera.near_plane() < NEAR_PLANE);
+        assert!(camera.fov() > BASE_FOV);
+        let view = camera.view_matrix();
+        let ahead = view.transform_point(&Point3::from(hood + car.forward() * 5.));
+        assert!(ahead[2] < 0. && ahead[0].abs() < 1e-4);
+    }
+
+    #[test]
+    fn chase_camera_avoids_walls() {
+        let car = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let mut camera = ChaseCamera::new();
+        camera.set_target(&car);
+        let behind = camera.position;
+
+        // A wall 4m behind the car pulls the camera in front of it.
+        let wall = |y| Track::new(vec![Wall::new(Vector3::new(-10., y, 0.), Vector3::new(10., y, 0.))]);
+        camera.avoid_walls(&car, &wall(-4.));
+        assert!(camera.position[1] > -4.);
+        assert!(camera.position[1] < -camera.min_distance);
+        assert!((camera.position.normalize() - behind.normalize()).norm() < 1e-5);
+
+        // A wall right behind the car doesn't push the camera closer than the minimum distance.
+        camera.set_target(&car);
+        camera.avoid_walls(&car, &wall(-1.));
+        assert!((camera.position.norm() - camera.min_distance).abs() < 1e-4);
+
+        // Without a wall in between, the camera stays where it is.
+        camera.set_target(&car);
+        camera.avoid_walls(&car, &wall(-20.));
+        assert_eq!(camera.position, behind);
+    }
+
+    #[test]
+    fn fov_widens_with_speed() {
+        let fov_after = |speed: f32, seconds: usize| {
+            let mut camera = ChaseCamera::new();
+            for _ in 0..seconds * 100 {
+                camera.update_fov(speed, 0.01);
+            }
+            camera.fov()
+        };
+        assert_eq!(fov_after(0., 10), BASE_FOV);
+        assert!(fov_after(10., 10) > BASE_FOV);
+        assert!(fov_after(30., 10) > fov_after(10., 10));
+        assert!((fov_after(100., 10) - ChaseCamera::new().max_fov).abs() < 1e-3);
+
+        // The change is limited, even when it would close in faster.
+        let mut camera = ChaseCamera::new();
+        camera.speed = 100.;
+        camera.update_fov(40., 0.1);
+        assert!((camera.fov() - BASE_FOV - camera.max_fov_rate * 0.1).abs() < 1e-6);
+
+        // Back at a standstill the view returns to the base.
+        for _ in 0..1000 {
+            camera.update_fov(0., 0.01);
+        }
+        assert!((camera.fov() - BASE_FOV).abs() < 1e-4);
+    }
+
+    #[test]
+    fn debug_camera_direction() {
+        let mut camera = DebugCamera::from_view(&Matrix4::look_at_rh(
+            &Point3::new(1., 2., 3.),
+            &Point3::new(1., 3., 3.),
+            &Vector3::z(),
+        ));
+        assert!((camera.position - Vector3::new(1., 2., 3.)).norm() < 1e-5);
+        assert!(camera.yaw.abs() < 1e-5 && camera.pitch.abs() < 1e-5);
+
+        let expected = [
+            (0., 0., Vector3::new(0., 1., 0.)),
+            (FRAC_PI_2, 0., Vector3::new(-1., 0., 0.)),
+            (-FRAC_PI_2, 0., Vector3::new(1., 0., 0.)),
+            (0., FRAC_PI_4, Vector3::new(0., 1., 1.).normalize()),
+        ];
+        for (yaw, pitch, direction) in &expected {
+            camera.yaw = *yaw;
+            camera.pitch = *pitch;
+            assert!((camera.direction() - direction).norm() < 1e-5);
+            assert!((camera.direction().norm() - 1.).abs() < 1e-5);
+        }
+
+        // Looking all the way up stops short of flipping over.
+        camera.rotate(100., -1e6);
+        assert_eq!(camera.pitch, MAX_PITCH);
+        assert!(camera.direction()[2] < 1.);
+        assert!(camera.view_matrix().iter().all(|x| x.is_finite()));
+        // Moving the mouse to the right turns right.
+        assert!(camera.yaw < 0.);
+
+        camera.pitch = 0.;
+        camera.yaw = 0.;
+        camera.update(0.5, 1., 1., 0.);
+        let step = camera.speed * 0.5;
+        assert!((camera.position - Vector3::new(1. + step, 2. + step, 3.)).norm() < 1e-4);
+    }
+}
@@ -17,23 +17,43 @@ use log::{debug, error};
 
 use std::ptr;
 
+/// Return the number of samples per pixel to use for a `requested` count and the `max` the driver supports.
+///
+/// 0 and 1 disable multisampling, larger counts are limited to `max`.
+pub(crate) fn sample_count(requested: u32, max: i32) -> u32 {
+    if requested <= 1 || max <= 1 {
+        0
+    } else {
+        requested.min(max as u32)
+    }
+}
+
 pub(crate) struct FrameBuffer {
     fbo: u32,
     rbo: u32,
 
     pub color_buffer: u32,
 
+    // Multisampled buffers drawn to instead, they are resolved into `color_buffer`.
+    msaa_fbo: u32,
+    msaa_color: u32,
+    msaa_depth: u32,
+    samples: u32,
+
     width: i32,
     height: i32,
 }
 
 impl FrameBuffer {
-    pub(crate) fn new(width: i32, height: i32) -> FrameBuffer {
-        debug!("FrameBuffer::new({}, {})", width, height);
-        let mut frame_buffer: FrameBuffer = Default::default();
-
-        frame_buffer.width = width;
-        frame_buffer.height = height;
+    /// Create a frame buffer, which is multisampled with `samples` per pixel unless it is 0.
+    pub(crate) fn new(width: i32, height: i32, samples: u32) -> FrameBuffer {
+        debug!("FrameBuffer::new({}, {}, {})", width, height, samples);
+        let mut frame_buffer = FrameBuffer {
+            width,
+            height,
+            samples,
+            ..Default::default()
+        };
 
         frame_buffer.init();
 
@@ -70,13 +90,62 @@ impl FrameBuffer {
             if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
                 error!("Framebuffer not complete!");
             }
+
+            if self.samples > 0 {
+                gl::GenFramebuffers(1, &mut self.msaa_fbo);
+                gl::BindFramebuffer(gl::FRAMEBUFFER, self.msaa_fbo);
+                gl::GenRenderbuffers(1, &mut self.msaa_color);
+                gl::GenRenderbuffers(1, &mut self.msaa_depth);
+                self.alloc_msaa_storage();
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, self.msaa_color);
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, self.msaa_depth);
+
+                if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                    error!("Multisampled framebuffer not complete!");
+                }
+            }
             gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
         }
     }
 
+    /// (Re)allocate the multisampled buffers with the current size.
+    unsafe fn alloc_msaa_storage(&self) {
+        let samples = self.samples as i32;
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.msaa_color);
+        gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, gl::SRGB8_ALPHA8, self.width, self.height);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.msaa_depth);
+        gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, gl::DEPTH24_STENCIL8, self.width, self.height);
+    }
+
     /// Bind the this `FrameBuffer` as thew current active one.
+    ///
+    /// A multisampled `FrameBuffer` draws to its multisampled buffers until it is resolved.
     pub unsafe fn bind(&self) {
-        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        if self.samples > 0 {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.msaa_fbo);
+        } else {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        }
+    }
+
+    /// Average the samples of each pixel into `color_buffer`. Does nothing without multisampling.
+    pub unsafe fn resolve(&self) {
+        if self.samples > 0 {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.msaa_fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                self.width,
+                self.height,
+                0,
+                0,
+                self.width,
+                self.height,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+        }
     }
 
     /// Unbind any bound `FrameBuffer` and bind the default one.
@@ -106,6 +175,9 @@ impl FrameBuffer {
         );
         gl::BindRenderbuffer(gl::RENDERBUFFER, self.rbo);
         gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, self.width, self.height);
+        if self.samples > 0 {
+            self.alloc_msaa_storage();
+        }
 
         gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
     }
@@ -117,6 +189,11 @@ impl Drop for FrameBuffer {
             gl::DeleteBuffers(1, self.fbo as *const u32);
             gl::DeleteBuffers(1, self.rbo as *const u32);
             gl::DeleteTextures(1, self.color_buffer as *const u32);
+            if self.samples > 0 {
+                gl::DeleteFramebuffers(1, &self.msaa_fbo);
+                gl::DeleteRenderbuffers(1, &self.msaa_color);
+                gl::DeleteRenderbuffers(1, &self.msaa_depth);
+            }
         }
     }
 }
@@ -129,8 +206,30 @@ impl Default for FrameBuffer {
 
             color_buffer: 0,
 
+            msaa_fbo: 0,
+            msaa_color: 0,
+            msaa_depth: 0,
+            samples: 0,
+
             width: 0,
             height: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::sample_count;
+
+    #[test]
+    fn clamp_sample_count() {
+        assert_eq!(sample_count(4, 8), 4);
+        // Counts above the driver limit fall back to the limit.
+        assert_eq!(sample_count(16, 8), 8);
+        // 0 and 1 disable multisampling, as does a driver without support.
+        assert_eq!(sample_count(0, 8), 0);
+        assert_eq!(sample_count(1, 8), 0);
+        assert_eq!(sample_count(4, 0), 0);
+        assert_eq!(sample_count(4, 1), 0);
+    }
+}
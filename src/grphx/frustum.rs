@@ -0,0 +1,115 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use crate::game::car::Obb;
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+/// The volume visible to the camera, bounded by six planes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far plane. The normal `xyz` of a plane points inwards and
+    /// a point `p` is in front of it for `normal.dot(p) + w >= 0`.
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extract the frustum planes in world space from the combined `projection * view` matrix.
+    pub fn from_matrix(m: &Matrix4<f32>) -> Frustum {
+        let row = |i: usize| m.row(i).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let mut planes = [w + x, w - x, w + y, w - y, w + z, w - z];
+        for plane in planes.iter_mut() {
+            let length = plane.xyz().norm();
+            if length > 0. {
+                *plane /= length;
+            }
+        }
+        Frustum { planes }
+    }
+
+    /// Return whether the oriented box `obb` is at least partially inside.
+    pub fn intersects_obb(&self, obb: &Obb) -> bool {
+        let [x, y] = obb.axes();
+        self.intersects_box(&obb.center, &[x, y, Vector3::z()], &obb.half_extents)
+    }
+
+    /// Only boxes completely behind one of the planes are outside. Boxes close to the corners
+    /// of the frustum can be reported inside, that's fine to skip drawing.
+    fn intersects_box(&self, center: &Vector3<f32>, axes: &[Vector3<f32>; 3], half_extents: &Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.xyz();
+            let radius: f32 = axes
+                .iter()
+                .zip(half_extents.iter())
+                .map(|(axis, h)| h * normal.dot(axis).abs())
+                .sum();
+            normal.dot(center) + plane[3] >= -radius
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Frustum;
+    use crate::game::car::Obb;
+    use nalgebra::{Matrix4, Perspective3, Point3, Vector3, Vector4};
+
+    #[test]
+    fn extract_planes() {
+        // Without any transformation the frustum is the cube of the normalized device coordinates.
+        let frustum = Frustum::from_matrix(&Matrix4::identity());
+        assert_eq!(
+            frustum.planes,
+            [
+                Vector4::new(1., 0., 0., 1.),
+                Vector4::new(-1., 0., 0., 1.),
+                Vector4::new(0., 1., 0., 1.),
+                Vector4::new(0., -1., 0., 1.),
+                Vector4::new(0., 0., 1., 1.),
+                Vector4::new(0., 0., -1., 1.),
+            ]
+        );
+
+        // The near and far plane of a camera looking down -z.
+        let projection = Perspective3::new(1., 1., 1., 200.).to_homogeneous();
+        let frustum = Frustum::from_matrix(&projection);
+        assert!((frustum.planes[4] - Vector4::new(0., 0., -1., -1.)).norm() < 1e-4);
+        assert!((frustum.planes[5] - Vector4::new(0., 0., 1., 200.)).norm() < 1e-2);
+    }
+
+    #[test]
+    fn cull_boxes() {
+        let projection = Perspective3::new(16. / 9., 70f32.to_radians(), 1., 200.).to_homogeneous();
+        let view = Matrix4::look_at_rh(&Point3::new(0., -20., 30.), &Point3::origin(), &Vector3::z());
+        let frustum = Frustum::from_matrix(&(projection * view));
+        let half_extents = Vector3::new(1., 2., 0.5);
+        let aabb = |x: f32, y: f32, z: f32, half_extents: &Vector3<f32>| {
+            frustum.intersects_box(&Vector3::new(x, y, z), &[Vector3::x(), Vector3::y(), Vector3::z()], half_extents)
+        };
+
+        assert!(aabb(0., 0., 0., &half_extents));
+        // Behind the camera.
+        assert!(!aabb(0., -60., 70., &half_extents));
+        // Sticking out of the side of the screen is still drawn.
+        assert!(aabb(-22.5, 0., 0., &Vector3::new(5., 5., 0.5)));
+        assert!(!aabb(-100., 0., 0., &half_extents));
+
+        let obb = Obb {
+            center: Vector3::new(0., 0., 0.),
+            half_extents,
+            rotation: 1.,
+        };
+        assert!(frustum.intersects_obb(&obb));
+    }
+}
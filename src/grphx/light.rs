@@ -0,0 +1,51 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use super::Shader;
+use nalgebra::Vector3;
+
+/// Uniform locations of the light in the default shader.
+const DIRECTION_LOCATION: i32 = 7;
+const COLOR_LOCATION: i32 = 8;
+const AMBIENT_LOCATION: i32 = 9;
+
+/// Light from far away, like the sun, that shines on everything from the same direction.
+#[derive(Debug, Copy, Clone)]
+pub struct DirectionalLight {
+    /// Direction in which the light shines, it doesn't need to be normalized.
+    pub direction: Vector3<f32>,
+    /// Color of the light, each channel between 0.0 and 1.0.
+    pub color: Vector3<f32>,
+    /// Fraction of the light [0.0; 1.0] that reaches faces turned away from it.
+    pub ambient: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> DirectionalLight {
+        DirectionalLight {
+            direction: Vector3::new(-1., -2., -3.),
+            color: Vector3::new(1., 1., 1.),
+            ambient: 0.2,
+        }
+    }
+}
+
+impl DirectionalLight {
+    /// Bind the light to the currently bound shader program.
+    pub(crate) unsafe fn bind(&self) {
+        Shader::set_uniform_vec3(DIRECTION_LOCATION, &self.direction.normalize());
+        Shader::set_uniform_vec3(COLOR_LOCATION, &self.color);
+        Shader::set_uniform_float(AMBIENT_LOCATION, self.ambient);
+    }
+}
@@ -14,6 +14,7 @@
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
 use gl;
 use log::{debug, info};
+use nalgebra::Matrix4;
 use serde_derive::{Deserialize, Serialize};
 
 use std::mem::size_of;
@@ -61,6 +62,16 @@ pub struct Mesh {
     pub(super) vao: u32,
     pub(super) vbo: u32,
     pub(super) ibo: u32,
+    /// Buffer of the model matrices for instanced drawing.
+    pub(super) instance_vbo: u32,
+}
+
+/// First attribute location of the model matrix per instance, one location per column.
+const INSTANCE_LOCATION: u32 = 3;
+
+/// Return the size [byte] of the instance buffer holding `count` model matrices.
+fn instance_buffer_size(count: usize) -> isize {
+    (count * size_of::<Matrix4<f32>>()) as isize
 }
 
 impl Mesh {
@@ -89,6 +100,30 @@ impl Mesh {
         gl::BindVertexArray(0);
     }
 
+    /// Render the mesh once for every model matrix in `instances` with a single draw call.
+    ///
+    /// The model matrices are uploaded as per instance attributes, so the bound shader reads them
+    /// instead of a model uniform.
+    pub unsafe fn draw_instanced(&self, instances: &[Matrix4<f32>]) {
+        if instances.is_empty() {
+            return;
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+        let data = instances.as_ptr() as *const c_void;
+        gl::BufferData(gl::ARRAY_BUFFER, instance_buffer_size(instances.len()), data, gl::STREAM_DRAW);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+        gl::BindVertexArray(self.vao);
+        gl::DrawElementsInstanced(
+            gl::TRIANGLES,
+            self.indices.len() as i32,
+            gl::UNSIGNED_INT,
+            ptr::null(),
+            instances.len() as i32,
+        );
+        gl::BindVertexArray(0);
+    }
+
     /// Generate the vertex array object and all buffers.
     unsafe fn init(&mut self) {
         // VAO
@@ -117,6 +152,18 @@ impl Mesh {
         gl::EnableVertexAttribArray(2);
         gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, uv) as *const c_void);
 
+        // Instance VBO, a mat4 attribute takes four locations with one column each.
+        gl::GenBuffers(1, &mut self.instance_vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+        let size = size_of::<Matrix4<f32>>() as i32;
+        for column in 0..4 {
+            let location = INSTANCE_LOCATION + column;
+            let offset = (column as usize * 4 * size_of::<f32>()) as *const c_void;
+            gl::EnableVertexAttribArray(location);
+            gl::VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, size, offset);
+            gl::VertexAttribDivisor(location, 1);
+        }
+
         gl::BindVertexArray(0);
 
         debug!("Mesh::init() : vao == {}, vbo == {}, ibo == {}", self.vao, self.vbo, self.ibo);
@@ -131,6 +178,7 @@ impl Default for Mesh {
             vao: 0,
             vbo: 0,
             ibo: 0,
+            instance_vbo: 0,
         }
     }
 }
@@ -138,9 +186,29 @@ impl Default for Mesh {
 impl Drop for Mesh {
     fn drop(&mut self) {
         unsafe {
-            gl::DeleteBuffers(1, self.ibo as *const u32);
-            gl::DeleteBuffers(1, self.vbo as *const u32);
-            gl::DeleteVertexArrays(1, self.vao as *const u32);
+            gl::DeleteBuffers(1, &self.instance_vbo);
+            gl::DeleteBuffers(1, &self.ibo);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::instance_buffer_size;
+    use nalgebra::{Matrix4, Vector3};
+    use std::mem::size_of;
+
+    #[test]
+    fn instance_buffer_fits_all_matrices() {
+        assert_eq!(instance_buffer_size(0), 0);
+        assert_eq!(instance_buffer_size(1), 16 * size_of::<f32>() as isize);
+        assert_eq!(instance_buffer_size(12), 12 * 64);
+
+        // The columns are contiguous, as the shader reads them one per attribute location.
+        let instances = [Matrix4::new_translation(&Vector3::new(1., 2., 3.)); 2];
+        assert_eq!(size_of::<[Matrix4<f32>; 2]>() as isize, instance_buffer_size(instances.len()));
+        assert_eq!(&instances[1].as_slice()[12..15], &[1., 2., 3.]);
+    }
+}
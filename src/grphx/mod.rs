@@ -17,6 +17,10 @@
 mod camera;
 /// Frame buffer for background rendering.
 mod framebuffer;
+/// View frustum to skip drawing objects outside of the screen.
+mod frustum;
+/// Sun light for shading the models.
+mod light;
 /// Material unused.
 mod material;
 /// 3D Mesh for Model
@@ -25,15 +29,23 @@ mod mesh;
 mod model;
 /// Handle FrameBuffer blending.
 mod screen;
+/// Save the rendered frame to an image file.
+mod screenshot;
 /// OpenGL shader program and usability functions.
 mod shader;
 /// 2D Texture for Models
 mod texture;
+/// Split the window between the players.
+mod viewport;
 
 pub(crate) use self::camera::*;
 pub(crate) use self::framebuffer::*;
+pub(crate) use self::frustum::*;
+pub(crate) use self::light::*;
 pub(crate) use self::mesh::*;
 pub(crate) use self::model::*;
 pub(crate) use self::screen::*;
+pub(crate) use self::screenshot::*;
 pub(crate) use self::shader::*;
 pub(crate) use self::texture::*;
+pub(crate) use self::viewport::*;
@@ -12,9 +12,11 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
-use crate::grphx::{Mesh, Shader, Texture, Vertex};
+use crate::grphx::{DirectionalLight, Mesh, Shader, ShaderError, Texture, TextureError, Vertex};
 use log::{debug, info};
-use nalgebra::{inf, sup, zero, Matrix4, Vector3};
+use nalgebra::{inf, sup, zero, Matrix3, Matrix4, Vector3, U3};
+use std::error::Error;
+use std::fmt;
 use std::path::Path;
 use tobj;
 
@@ -22,12 +24,60 @@ use tobj;
 ///
 /// Currently and can consist of seperate `Mesh`es which all are drawn with
 /// on `Texture` as a color lookup table and `Shader`program.
+///
+/// The model owns the GL handles of its parts, which delete them when it is dropped, e.g. when a
+/// track is reloaded. It is not `Clone` on purpose, a copy would delete the same handles again.
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub shader: Shader,
+    /// Shader reading the model matrix per instance, compiled by the first `draw_instanced()`.
+    pub instanced_shader: Option<Shader>,
     pub texture: Texture,
 }
 
+/// Return the matrix that transforms normals into world space along with `model`.
+///
+/// Using the model matrix itself would skew the normals of non-uniformly scaled models.
+fn normal_matrix(model: &Matrix4<f32>) -> Matrix3<f32> {
+    let linear = model.fixed_slice::<U3, U3>(0, 0).into_owned();
+    linear.try_inverse().unwrap_or_else(Matrix3::identity).transpose()
+}
+
+/// Error while loading a `Model` from an obj file or building it from vertex data.
+#[derive(Debug)]
+pub enum ModelError {
+    /// The file could not be read or parsed by tobj.
+    Load(tobj::LoadError),
+    /// The file was parsed or the data was passed, but a mesh is incomplete or inconsistent.
+    Malformed(String),
+    /// The shaders of the model failed to load.
+    Shader(ShaderError),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::Load(e) => write!(f, "failed to load obj: {}", e),
+            ModelError::Malformed(msg) => write!(f, "malformed mesh: {}", msg),
+            ModelError::Shader(e) => write!(f, "failed to load shader: {}", e),
+        }
+    }
+}
+
+impl Error for ModelError {}
+
+impl From<tobj::LoadError> for ModelError {
+    fn from(e: tobj::LoadError) -> ModelError {
+        ModelError::Load(e)
+    }
+}
+
+impl From<ShaderError> for ModelError {
+    fn from(e: ShaderError) -> ModelError {
+        ModelError::Shader(e)
+    }
+}
+
 impl Model {
     /// Creates a new Model by passing a path to the obj file and a color palette name.
     ///
@@ -38,56 +88,138 @@ impl Model {
         info!("Model::new - file:{};palette:{}", file, palette);
 
         let file_str = format!("{}{}", "res/models/", file);
-        let file = Path::new(&file_str);
-        let obj = tobj::load_obj(file);
-
-        let (models, _materials) = obj.unwrap();
-
-        let mut meshes = Vec::with_capacity(models.len());
-        for model in models {
-            let mesh = &model.mesh;
-            let num_vertices = mesh.positions.len() / 3;
-
-            // data to fill
-            let mut vertices: Vec<Vertex> = Vec::with_capacity(num_vertices);
-            let indices: Vec<u32> = mesh.indices.clone();
-
-            let (p, n, t) = (&mesh.positions, &mesh.normals, &mesh.texcoords);
-            for i in 0..num_vertices {
-                vertices.push(Vertex {
-                    position: [p[i * 3], p[i * 3 + 1], p[i * 3 + 2]],
-                    normal: [n[i * 3], n[i * 3 + 1], n[i * 3 + 2]],
-                    uv: [t[i * 2], t[i * 2 + 1]],
-                })
-            }
+        let mut model = Model::from_obj(Path::new(&file_str)).unwrap_or_else(|e| panic!("Failed to load model {}: {}", file, e));
+        let palette_str = format!("{}{}", "res/textures/", palette);
+        model.set_texture(Path::new(&palette_str)).expect("ERROR: Failed to load texture!");
+        model
+    }
 
-            meshes.push(Mesh::new(vertices, indices));
-        }
+    /// Creates a new Model from a triangulated obj file without a texture.
+    ///
+    /// Every vertex needs a position, normal and texture coordinate.
+    pub fn from_obj(path: &Path) -> Result<Model, ModelError> {
+        let meshes = load_obj_meshes(path)?
+            .into_iter()
+            .map(|(vertices, indices)| Mesh::new(vertices, indices))
+            .collect();
 
-        let shader = Shader::new("default");
+        Model::from_meshes(meshes)
+    }
+
+    /// Creates a new Model with a single mesh from vertex data generated in code, without a texture.
+    ///
+    /// Every vertex needs a position, normal and texture coordinate, every three `indices` form a triangle.
+    pub fn from_vertices(positions: &[[f32; 3]], normals: &[[f32; 3]], uvs: &[[f32; 2]], indices: &[u32]) -> Result<Model, ModelError> {
+        let (vertices, indices) = mesh_data(positions, normals, uvs, indices)?;
+        Model::from_meshes(vec![Mesh::new(vertices, indices)])
+    }
 
-        let texture = Texture::new(palette);
+    /// Creates a new Model from `meshes` without a texture.
+    pub fn from_meshes(meshes: Vec<Mesh>) -> Result<Model, ModelError> {
+        Ok(Model {
+            meshes,
+            shader: Shader::new("default")?,
+            instanced_shader: None,
+            texture: Default::default(),
+        })
+    }
 
-        Model { meshes, shader, texture }
+    /// Replace the texture of the model with an image file, which is sampled with the texture coordinates
+    /// of the obj file. On an error the current texture is kept.
+    pub fn set_texture(&mut self, path: &Path) -> Result<(), TextureError> {
+        self.texture = Texture::from_file(path)?;
+        Ok(())
     }
 
     /// This function draws the `Model`.
     ///
     /// Because the basic model has no translation, rotation or scale it needs the model-, view-,
-    /// and projection matrix as parameter.
-    pub fn draw(&self, model: &Matrix4<f32>, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+    /// and projection matrix as parameter. It is shaded by the `light`.
+    pub fn draw(&self, model: &Matrix4<f32>, view: &Matrix4<f32>, projection: &Matrix4<f32>, light: &DirectionalLight) {
+        self.draw_translucent(model, view, projection, light, 1.);
+    }
+
+    /// Draw the `Model` like `draw()` with an opacity between 0.0 (invisible) and 1.0 (opaque).
+    pub fn draw_translucent(
+        &self,
+        model: &Matrix4<f32>,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+        light: &DirectionalLight,
+        alpha: f32,
+    ) {
+        self.draw_tinted(model, view, projection, light, alpha, &Vector3::repeat(1.));
+    }
+
+    /// Draw the `Model` like `draw_translucent()` with its colors multiplied by the RGB `tint`.
+    pub fn draw_tinted(
+        &self,
+        model: &Matrix4<f32>,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+        light: &DirectionalLight,
+        alpha: f32,
+        tint: &Vector3<f32>,
+    ) {
         unsafe {
             self.shader.bind();
             Shader::bind_texture(0, &self.texture);
             Shader::set_uniform_mat4(0, model);
             Shader::set_uniform_mat4(1, view);
             Shader::set_uniform_mat4(2, projection);
+            Shader::set_uniform_mat3(3, &normal_matrix(model));
+            Shader::set_uniform_float(6, alpha);
+            Shader::set_uniform_vec3(10, tint);
+            light.bind();
             for mesh in &self.meshes {
                 mesh.draw();
             }
         }
     }
 
+    /// Draw the `Model` once for every model matrix in `instances` with one draw call per `Mesh`,
+    /// its colors multiplied by the RGB `tint`.
+    ///
+    /// This is much cheaper than calling `draw()` for every instance, e.g. for many opponents with
+    /// the same model and texture. The shader for it is compiled on the first call, most models are
+    /// never drawn this way.
+    pub fn draw_instanced(
+        &mut self,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+        light: &DirectionalLight,
+        tint: &Vector3<f32>,
+        instances: &[Matrix4<f32>],
+    ) -> Result<(), ShaderError> {
+        let shader = match self.instanced_shader.take() {
+            Some(shader) => shader,
+            None => Shader::from_files("instanced", "default")?,
+        };
+        unsafe {
+            shader.bind();
+            Shader::bind_texture(0, &self.texture);
+            Shader::set_uniform_mat4(1, view);
+            Shader::set_uniform_mat4(2, projection);
+            Shader::set_uniform_float(6, 1.);
+            Shader::set_uniform_vec3(10, tint);
+            light.bind();
+            for mesh in &self.meshes {
+                mesh.draw_instanced(instances);
+            }
+        }
+        self.instanced_shader = Some(shader);
+        Ok(())
+    }
+
+    /// Compile the shaders of the model again from their files, see `Shader::reload()`.
+    pub fn reload_shaders(&mut self) -> Result<(), ShaderError> {
+        self.shader.reload()?;
+        match &mut self.instanced_shader {
+            Some(shader) => shader.reload(),
+            None => Ok(()),
+        }
+    }
+
     /// Get the minum and maximum x-, y-, and z-coordinates of all vertices in our model.
     ///
     /// This could be used to generate a bounding box. This is not an efficient function
@@ -106,3 +238,226 @@ impl Model {
         (min, max)
     }
 }
+
+/// Vertices and triangle indices of a single mesh.
+type MeshData = (Vec<Vertex>, Vec<u32>);
+
+/// Read the vertices and indices of every mesh in an obj file.
+fn load_obj_meshes(path: &Path) -> Result<Vec<MeshData>, ModelError> {
+    let (models, _materials) = tobj::load_obj(path)?;
+
+    let mut meshes = Vec::with_capacity(models.len());
+    for model in models {
+        let mesh = &model.mesh;
+        let num_vertices = mesh.positions.len() / 3;
+
+        let (p, n, t) = (&mesh.positions, &mesh.normals, &mesh.texcoords);
+        if n.len() != num_vertices * 3 {
+            return Err(ModelError::Malformed(format!("{} needs a normal for every vertex", model.name)));
+        }
+        if t.len() != num_vertices * 2 {
+            return Err(ModelError::Malformed(format!(
+                "{} needs a texture coordinate for every vertex",
+                model.name
+            )));
+        }
+        if mesh.indices.len() % 3 != 0 {
+            return Err(ModelError::Malformed(format!("{} is not triangulated", model.name)));
+        }
+        if mesh.indices.iter().any(|&i| i as usize >= num_vertices) {
+            return Err(ModelError::Malformed(format!("{} has a face with an invalid vertex", model.name)));
+        }
+
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(num_vertices);
+        for i in 0..num_vertices {
+            vertices.push(Vertex {
+                position: [p[i * 3], p[i * 3 + 1], p[i * 3 + 2]],
+                normal: [n[i * 3], n[i * 3 + 1], n[i * 3 + 2]],
+                uv: [t[i * 2], t[i * 2 + 1]],
+            })
+        }
+
+        meshes.push((vertices, mesh.indices.clone()));
+    }
+    Ok(meshes)
+}
+
+/// Combine the attributes of every vertex and check that `indices` form triangles of existing vertices.
+fn mesh_data(positions: &[[f32; 3]], normals: &[[f32; 3]], uvs: &[[f32; 2]], indices: &[u32]) -> Result<MeshData, ModelError> {
+    if normals.len() != positions.len() || uvs.len() != positions.len() {
+        return Err(ModelError::Malformed(format!(
+            "{} positions, {} normals and {} texture coordinates don't match",
+            positions.len(),
+            normals.len(),
+            uvs.len()
+        )));
+    }
+    if !indices.chunks_exact(3).remainder().is_empty() {
+        return Err(ModelError::Malformed(format!("{} indices don't form triangles", indices.len())));
+    }
+    if let Some(i) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        return Err(ModelError::Malformed(format!(
+            "index {} is out of range of {} vertices",
+            i,
+            positions.len()
+        )));
+    }
+
+    let vertices = positions
+        .iter()
+        .zip(normals)
+        .zip(uvs)
+        .map(|((&position, &normal), &uv)| Vertex { position, normal, uv })
+        .collect();
+    Ok((vertices, indices.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_obj_meshes, mesh_data, normal_matrix, Model, ModelError};
+    use crate::grphx::{Mesh, Shader, Texture};
+    use nalgebra::{Matrix4, Vector3};
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::os::raw::c_void;
+    use std::path::PathBuf;
+
+    fn write_obj(name: &str, content: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("carambolage-{}.obj", name));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_quad() {
+        let path = write_obj(
+            "quad",
+            "o quad\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+             vn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/4/1\n",
+        );
+        let meshes = load_obj_meshes(&path).unwrap();
+        assert_eq!(meshes.len(), 1);
+
+        let (vertices, indices) = &meshes[0];
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.len(), 6);
+        assert_eq!(vertices[2].position, [1., 1., 0.]);
+        assert_eq!(vertices[2].uv, [1., 1.]);
+        assert_eq!(vertices[2].normal, [0., 0., 1.]);
+    }
+
+    #[test]
+    fn load_malformed() {
+        let missing = env::temp_dir().join("carambolage-missing.obj");
+        assert!(matches!(load_obj_meshes(&missing), Err(ModelError::Load(_))));
+
+        let path = write_obj("broken", "v 0 0 zero\n");
+        assert!(load_obj_meshes(&path).is_err());
+
+        let path = write_obj("no-normals", "v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 0\nf 1/1 2/1 3/1\n");
+        assert!(matches!(load_obj_meshes(&path), Err(ModelError::Malformed(_))));
+    }
+
+    #[test]
+    fn quad_from_vertices() {
+        let positions = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]];
+        let normals = [[0., 0., 1.]; 4];
+        let uvs = [[0., 0.], [1., 0.], [1., 1.], [0., 1.]];
+        let (vertices, indices) = mesh_data(&positions, &normals, &uvs, &[0, 1, 2, 0, 2, 3]).unwrap();
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.len(), 6);
+        assert_eq!(vertices[2].position, [1., 1., 0.]);
+        assert_eq!(vertices[2].uv, [1., 1.]);
+
+        // Indices past the last vertex, incomplete triangles and missing attributes are rejected.
+        assert!(matches!(
+            mesh_data(&positions, &normals, &uvs, &[0, 1, 4]),
+            Err(ModelError::Malformed(_))
+        ));
+        assert!(mesh_data(&positions, &normals, &uvs, &[0, 1]).is_err());
+        assert!(mesh_data(&positions, &normals[..3], &uvs, &[0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        // A surface along the diagonal of a model stretched along x.
+        let model = Matrix4::from_euler_angles(0., 0., 0.7) * Matrix4::new_nonuniform_scaling(&Vector3::new(3., 1., 1.));
+        let tangent = model.transform_vector(&Vector3::new(1., -1., 0.));
+        let normal = normal_matrix(&model) * Vector3::new(1., 1., 0.);
+        assert!(tangent.dot(&normal).abs() < 1e-5);
+
+        // Normals of rotated models are rotated alike.
+        let rotation = Matrix4::from_euler_angles(0., 0., 0.7);
+        let normal = normal_matrix(&rotation) * Vector3::new(1., 0., 0.);
+        assert!((normal - rotation.transform_vector(&Vector3::new(1., 0., 0.))).norm() < 1e-6);
+    }
+
+    thread_local! {
+        /// Kind and handle of every delete call of the fake GL on this thread.
+        static DELETED: RefCell<Vec<(&'static str, u32)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(kind: &'static str, count: i32, handles: *const u32) {
+        let handles = unsafe { std::slice::from_raw_parts(handles, count as usize) };
+        DELETED.with(|deleted| deleted.borrow_mut().extend(handles.iter().map(|&handle| (kind, handle))));
+    }
+
+    extern "system" fn delete_buffers(count: i32, handles: *const u32) {
+        record("buffer", count, handles);
+    }
+
+    extern "system" fn delete_vertex_arrays(count: i32, handles: *const u32) {
+        record("vertex array", count, handles);
+    }
+
+    extern "system" fn delete_textures(count: i32, handles: *const u32) {
+        record("texture", count, handles);
+    }
+
+    extern "system" fn delete_program(program: u32) {
+        record("program", 1, &program);
+    }
+
+    #[test]
+    fn delete_handles_once() {
+        // Only the deletes are needed, so they are faked instead of creating a GL context.
+        gl::DeleteBuffers::load_with(|_| delete_buffers as *const c_void);
+        gl::DeleteVertexArrays::load_with(|_| delete_vertex_arrays as *const c_void);
+        gl::DeleteTextures::load_with(|_| delete_textures as *const c_void);
+        gl::DeleteProgram::load_with(|_| delete_program as *const c_void);
+
+        let mesh = |first: u32| {
+            let mut mesh = Mesh::default();
+            mesh.vao = first;
+            mesh.vbo = first + 1;
+            mesh.ibo = first + 2;
+            mesh.instance_vbo = first + 3;
+            mesh
+        };
+        let model = Model {
+            meshes: vec![mesh(1), mesh(5)],
+            shader: Shader::with_id(9),
+            instanced_shader: Some(Shader::with_id(10)),
+            texture: Texture { id: 11 },
+        };
+        drop(model);
+
+        let mut deleted = DELETED.with(|deleted| deleted.borrow().clone());
+        deleted.sort();
+        let mut expected = vec![("program", 9), ("program", 10), ("texture", 11)];
+        expected.extend((1..=5).step_by(4).flat_map(|first| {
+            vec![
+                ("vertex array", first),
+                ("buffer", first + 1),
+                ("buffer", first + 2),
+                ("buffer", first + 3),
+            ]
+        }));
+        expected.sort();
+        assert_eq!(deleted, expected);
+    }
+}
@@ -12,7 +12,7 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
-use super::{FrameBuffer, Shader};
+use super::{FrameBuffer, Shader, ShaderError};
 
 use std::mem::size_of;
 use std::os::raw::c_void;
@@ -28,8 +28,8 @@ pub(crate) struct Screen {
 }
 
 impl Screen {
-    /// Create a new `Screen` with `width`and `height`in pixels.
-    pub(crate) fn new(width: u32, height: u32) -> Screen {
+    /// Create a new `Screen` with `width`and `height`in pixels, multisampled with `samples` per pixel unless it is 0.
+    pub(crate) fn new(width: u32, height: u32, samples: u32) -> Screen {
         // Vertex coordinates of two triangles from [-1.0, -1.0] to [1.0, 1.0].
         let vertices: [f32; 24] = [
             -1.0, 1.0, 0.0, 1.0, -1.0, -1.0, 0.0, 0.0, 1.0, -1.0, 1.0, 0.0, -1.0, 1.0, 0.0, 1.0, 1.0, -1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0,
@@ -57,8 +57,8 @@ impl Screen {
             gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (2 * size_of::<f32>()) as *const c_void);
         }
 
-        let frame_buffer = FrameBuffer::new(width as i32, height as i32);
-        let post_proc_shader = Shader::new("post_proc");
+        let frame_buffer = FrameBuffer::new(width as i32, height as i32, samples);
+        let post_proc_shader = Shader::new("post_proc").unwrap_or_else(|e| panic!("Failed to load the post processing shader: {}", e));
 
         Screen {
             vao,
@@ -68,6 +68,11 @@ impl Screen {
         }
     }
 
+    /// Compile the post processing shader again from its files.
+    pub(crate) fn reload_shader(&mut self) -> Result<(), ShaderError> {
+        self.post_proc_shader.reload()
+    }
+
     /// Takes the width and height in pixels for resizing the frame buffer.
     pub(crate) fn resize(&mut self, width: u32, height: u32) {
         unsafe {
@@ -93,6 +98,7 @@ impl Screen {
     /// to the default framebuffer.
     pub(crate) fn second_step(&self) {
         unsafe {
+            self.frame_buffer.resolve();
             self.frame_buffer.unbind();
 
             gl::Disable(gl::DEPTH_TEST);
@@ -0,0 +1,92 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+use image::ColorType;
+use log::{info, warn};
+
+use std::fs;
+use std::os::raw::c_void;
+use std::path::PathBuf;
+use std::thread;
+
+/// Directory the screenshots are saved to.
+const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Save the frame in the default framebuffer of `width` x `height` pixels as a png in `SCREENSHOT_DIR`.
+///
+/// Only reading the pixels happens right away, they are encoded and written on another thread.
+pub(crate) fn save_screenshot(width: u32, height: u32) {
+    let mut pixels = vec![0u8; width as usize * height as usize * 3];
+    unsafe {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            0,
+            0,
+            width as i32,
+            height as i32,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut c_void,
+        );
+    }
+
+    let now = time::now();
+    let name = format!(
+        "{}_{:03}.png",
+        time::strftime("%Y-%m-%d_%H-%M-%S", &now).unwrap_or_default(),
+        now.tm_nsec / 1_000_000
+    );
+    let path = PathBuf::from(SCREENSHOT_DIR).join(name);
+    thread::spawn(move || {
+        // OpenGL starts at the bottom row, images at the top.
+        flip_rows(&mut pixels, width as usize * 3);
+        let result = fs::create_dir_all(SCREENSHOT_DIR).and_then(|_| image::save_buffer(&path, &pixels, width, height, ColorType::RGB(8)));
+        match result {
+            Ok(()) => info!("Saved screenshot {}", path.display()),
+            Err(e) => warn!("Could not save screenshot {}: {}", path.display(), e),
+        }
+    });
+}
+
+/// Reverse the order of the rows of `row_len` bytes in `pixels`.
+fn flip_rows(pixels: &mut [u8], row_len: usize) {
+    if row_len == 0 {
+        return;
+    }
+    let rows = pixels.len() / row_len;
+    for row in 0..rows / 2 {
+        let (top, bottom) = pixels.split_at_mut((rows - 1 - row) * row_len);
+        top[row * row_len..(row + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::flip_rows;
+
+    #[test]
+    fn flip_pixel_rows() {
+        // Three rows of two RGB pixels.
+        let mut pixels = (0..18).collect::<Vec<u8>>();
+        flip_rows(&mut pixels, 6);
+        assert_eq!(pixels[..6], [12, 13, 14, 15, 16, 17]);
+        assert_eq!(pixels[6..12], [6, 7, 8, 9, 10, 11]);
+        assert_eq!(pixels[12..], [0, 1, 2, 3, 4, 5]);
+
+        let mut pixels = vec![1, 2, 3, 4];
+        flip_rows(&mut pixels, 2);
+        assert_eq!(pixels, [3, 4, 1, 2]);
+    }
+}
@@ -14,77 +14,92 @@
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
 use crate::grphx::Texture;
 use gl;
-use log::{debug, error};
+use log::{debug, warn};
 use nalgebra::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use std::error::Error;
 use std::ffi::CString;
-use std::fs::File;
-use std::io::Read;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
 use std::ptr;
-use std::str;
 
 /// Compiled GLSL Shader Program.
 pub struct Shader {
     pub id: u32,
+    /// Names of the vertex and fragment shader files to reload the program from, `None` for a program
+    /// compiled from code in memory.
+    files: Option<(String, String)>,
 }
 
+/// Error while loading a `Shader` from its source files.
+#[derive(Debug)]
+pub enum ShaderError {
+    /// A source file could not be read.
+    Read(String, io::Error),
+    /// The source of a stage contains a nul byte, which GL can't take.
+    Nul(String),
+    /// A stage failed to compile, with the info log of the compiler.
+    Compile { stage: String, log: String },
+    /// The stages failed to link into a program, with the info log of the linker.
+    Link { program: String, log: String },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Read(path, e) => write!(f, "failed to read {}: {}", path, e),
+            ShaderError::Nul(stage) => write!(f, "{} contains a nul byte", stage),
+            ShaderError::Compile { stage, log } => write!(f, "failed to compile {}:\n{}", stage, log),
+            ShaderError::Link { program, log } => write!(f, "failed to link {}:\n{}", program, log),
+        }
+    }
+}
+
+impl Error for ShaderError {}
+
 impl Shader {
-    pub fn new(file: &str) -> Shader {
-        debug!("New {}", file);
-        let mut shader = Shader { id: 0 };
-
-        // Load vertex shader code from file.
-        let vertex_file_path = format!("res/shaders/{}.vs", file);
-        let mut vertex_file = File::open(vertex_file_path).unwrap_or_else(|_| {
-            error!("Failed to open {}.vs", file);
-            panic!()
-        });
-        let mut vertex_string = String::new();
-        vertex_file.read_to_string(&mut vertex_string).unwrap_or_else(|_| {
-            error!("Failed to read vertex shader");
-            panic!()
-        });
-        let vertex_code = CString::new(vertex_string.as_bytes()).unwrap();
+    pub fn new(file: &str) -> Result<Shader, ShaderError> {
+        Shader::from_files(file, file)
+    }
 
-        // Load fragment shader code from file.
-        let fragment_file_path = format!("res/shaders/{}.fs", file);
-        let mut fragment_file = File::open(fragment_file_path).unwrap_or_else(|_| {
-            error!("Failed to open {}.fs", file);
-            panic!()
-        });
-        let mut fragment_string = String::new();
-        fragment_file.read_to_string(&mut fragment_string).unwrap_or_else(|_| {
-            error!("Failed to read fragment shader");
-            panic!()
-        });
-        let fragment_code = CString::new(fragment_string.as_bytes()).unwrap();
+    /// Create a shader program from the vertex shader `vertex`.vs and the fragment shader `fragment`.fs,
+    /// so shader stages can be shared between programs.
+    pub fn from_files(vertex: &str, fragment: &str) -> Result<Shader, ShaderError> {
+        debug!("New {}, {}", vertex, fragment);
+        Ok(Shader {
+            id: program_from_files(vertex, fragment)?,
+            files: Some((vertex.to_string(), fragment.to_string())),
+        })
+    }
 
-        // Try to compile both shaders.
-        unsafe {
-            // Compile vertex shader.
-            let vertex = gl::CreateShader(gl::VERTEX_SHADER);
-            gl::ShaderSource(vertex, 1, &vertex_code.as_ptr(), ptr::null());
-            gl::CompileShader(vertex);
-            shader.check_compile_errors(vertex, "VertexShader");
-
-            // Compile fragment Shader.
-            let fragment = gl::CreateShader(gl::FRAGMENT_SHADER);
-            gl::ShaderSource(fragment, 1, &fragment_code.as_ptr(), ptr::null());
-            gl::CompileShader(fragment);
-            shader.check_compile_errors(fragment, "FragmentShader");
-
-            // Create program from vertex and fragment shader.
-            let id = gl::CreateProgram();
-            gl::AttachShader(id, vertex);
-            gl::AttachShader(id, fragment);
-            gl::LinkProgram(id);
-            shader.check_compile_errors(id, "ShaderProgram");
-
-            gl::DeleteShader(vertex);
-            gl::DeleteShader(fragment);
-            shader.id = id;
-        }
+    /// Create the shader program of an effect the game can do without, like `from_files()`.
+    ///
+    /// If the shader fails to load, the effect is turned off with a warning instead of stopping the game.
+    pub fn for_effect(vertex: &str, fragment: &str, effect: &str) -> Option<Shader> {
+        Shader::from_files(vertex, fragment)
+            .map_err(|e| warn!("{} turned off, {}", effect, e))
+            .ok()
+    }
+
+    /// Wrap the program `id` without source files, so tests can do without a GL context.
+    #[cfg(test)]
+    pub(crate) fn with_id(id: u32) -> Shader {
+        Shader { id, files: None }
+    }
 
-        shader
+    /// Compile and link the program again from its files, e.g. after editing them while the game runs.
+    ///
+    /// On an error the previous program is kept. Programs compiled from code in memory stay as they are.
+    pub fn reload(&mut self) -> Result<(), ShaderError> {
+        if let Some((vertex, fragment)) = &self.files {
+            debug!("Reload {}, {}", vertex, fragment);
+            let previous = replace_program(&mut self.id, || program_from_files(vertex, fragment))?;
+            unsafe {
+                gl::DeleteProgram(previous);
+            }
+        }
+        Ok(())
     }
 
     /// Bind the shader program.
@@ -110,7 +125,7 @@ impl Shader {
     }
 
     /// Bind a `Vector3<f32>` to the currently boundshader program at location `id`.
-    pub unsafe fn _set_uniform_vec3(id: i32, value: &Vector3<f32>) {
+    pub unsafe fn set_uniform_vec3(id: i32, value: &Vector3<f32>) {
         gl::Uniform3fv(id, 1, value.as_slice().as_ptr());
     }
 
@@ -125,7 +140,7 @@ impl Shader {
     }
 
     /// Bind a `Matrix3<f32>` to the currently boundshader program at location `id`.
-    pub unsafe fn _set_uniform_mat3(id: i32, mat: &Matrix3<f32>) {
+    pub unsafe fn set_uniform_mat3(id: i32, mat: &Matrix3<f32>) {
         gl::UniformMatrix3fv(id, 1, gl::FALSE, mat.as_slice().as_ptr());
     }
 
@@ -138,34 +153,165 @@ impl Shader {
         gl::Uniform1i(id, value);
     }
 
-    ///
-    unsafe fn check_compile_errors(&self, shader: u32, shader_type: &str) {
-        debug!("Checking {} shader for compile errors", shader_type);
-        let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(1024);
-        info_log.set_len(1024 - 1);
-
-        if shader_type != "ShaderProgram" {
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
-            if success != i32::from(gl::TRUE) {
-                // i8 is a GLchar
-                gl::GetShaderInfoLog(shader, 1024, ptr::null_mut(), info_log.as_mut_ptr() as *mut i8);
-                error!(
-                    "Compilation error of type: {}\nInfo log:\n{}",
-                    shader_type,
-                    str::from_utf8(&info_log).unwrap_or("UNKNOWN")
-                );
-            }
-        } else {
-            gl::GetProgramiv(shader, gl::LINK_STATUS, &mut success);
-            if success != i32::from(gl::TRUE) {
-                gl::GetProgramInfoLog(shader, 1024, ptr::null_mut(), info_log.as_mut_ptr() as *mut i8);
-                error!(
-                    "Linking error of type: {}\nInfo log:\n{}",
-                    shader_type,
-                    str::from_utf8(&info_log).unwrap_or("UNKNOWN")
-                );
+    /// Bind a `f32` to the currently bound shader program at location `id`.
+    pub unsafe fn set_uniform_float(id: i32, value: f32) {
+        gl::Uniform1f(id, value);
+    }
+}
+
+/// Read the files of the vertex shader `vertex`.vs and the fragment shader `fragment`.fs and link them
+/// into a program, return the id of the program.
+fn program_from_files(vertex: &str, fragment: &str) -> Result<u32, ShaderError> {
+    let read = |path: String| fs::read_to_string(&path).map_err(|e| ShaderError::Read(path, e));
+    let vertex_code = read(format!("res/shaders/{}.vs", vertex))?;
+    let fragment_code = read(format!("res/shaders/{}.fs", fragment))?;
+    unsafe {
+        link(
+            (&format!("{}.vs", vertex), &vertex_code),
+            (&format!("{}.fs", fragment), &fragment_code),
+        )
+    }
+}
+
+/// Replace the program `id` with the one `create` returns and return the previous program to delete.
+///
+/// On an error `id` keeps the previous program.
+fn replace_program(id: &mut u32, create: impl FnOnce() -> Result<u32, ShaderError>) -> Result<u32, ShaderError> {
+    let program = create()?;
+    Ok(mem::replace(id, program))
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteProgram(self.id);
+        }
+    }
+}
+
+/// Compile a vertex and a fragment shader, each given by its name and code, and link them into a program,
+/// return the id of the program.
+unsafe fn link(vertex: (&str, &str), fragment: (&str, &str)) -> Result<u32, ShaderError> {
+    let vertex_id = compile(gl::VERTEX_SHADER, vertex.0, vertex.1)?;
+    let fragment_id = match compile(gl::FRAGMENT_SHADER, fragment.0, fragment.1) {
+        Ok(id) => id,
+        Err(e) => {
+            gl::DeleteShader(vertex_id);
+            return Err(e);
+        }
+    };
+
+    // Create program from vertex and fragment shader.
+    let id = gl::CreateProgram();
+    gl::AttachShader(id, vertex_id);
+    gl::AttachShader(id, fragment_id);
+    gl::LinkProgram(id);
+    gl::DeleteShader(vertex_id);
+    gl::DeleteShader(fragment_id);
+
+    let mut success = i32::from(gl::FALSE);
+    gl::GetProgramiv(id, gl::LINK_STATUS, &mut success);
+    if success != i32::from(gl::TRUE) {
+        let mut length = 0;
+        gl::GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut length);
+        let mut log = vec![0u8; length.max(1) as usize];
+        gl::GetProgramInfoLog(id, length, ptr::null_mut(), log.as_mut_ptr() as *mut i8);
+        gl::DeleteProgram(id);
+        return Err(ShaderError::Link {
+            program: format!("{} and {}", vertex.0, fragment.0),
+            log: info_log_text(&log),
+        });
+    }
+    Ok(id)
+}
+
+/// Compile the GLSL `code` of the shader `stage` named `name`, return the id of the compiled shader.
+unsafe fn compile(stage: u32, name: &str, code: &str) -> Result<u32, ShaderError> {
+    debug!("Compiling {}", name);
+    let code = CString::new(code).map_err(|_| ShaderError::Nul(name.to_string()))?;
+    let id = gl::CreateShader(stage);
+    gl::ShaderSource(id, 1, &code.as_ptr(), ptr::null());
+    gl::CompileShader(id);
+
+    let mut success = i32::from(gl::FALSE);
+    gl::GetShaderiv(id, gl::COMPILE_STATUS, &mut success);
+    if success != i32::from(gl::TRUE) {
+        // The whole log, however long it is.
+        let mut length = 0;
+        gl::GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut length);
+        let mut log = vec![0u8; length.max(1) as usize];
+        // i8 is a GLchar
+        gl::GetShaderInfoLog(id, length, ptr::null_mut(), log.as_mut_ptr() as *mut i8);
+        gl::DeleteShader(id);
+        return Err(ShaderError::Compile {
+            stage: name.to_string(),
+            log: info_log_text(&log),
+        });
+    }
+    Ok(id)
+}
+
+/// Return the text of an info log as GL writes it, ending with a nul byte.
+fn info_log_text(log: &[u8]) -> String {
+    let end = log.iter().position(|&c| c == 0).unwrap_or(log.len());
+    String::from_utf8_lossy(&log[..end]).trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{info_log_text, link, replace_program, ShaderError};
+    use imgui_glfw_rs::glfw;
+    use imgui_glfw_rs::glfw::Context;
+
+    #[test]
+    fn info_log_in_error() {
+        let log = info_log_text(b"0:3(1): error: syntax error, unexpected '}'\n\0\0\0");
+        assert_eq!(log, "0:3(1): error: syntax error, unexpected '}'");
+        let e = ShaderError::Compile {
+            stage: "broken.fs".to_string(),
+            log,
+        };
+        assert!(e.to_string().contains("broken.fs"));
+        assert!(e.to_string().contains("syntax error, unexpected '}'"));
+    }
+
+    #[test]
+    fn reload_keeps_program_on_failure() {
+        let mut id = 3;
+        let broken = || {
+            Err(ShaderError::Compile {
+                stage: "default.fs".to_string(),
+                log: "0:12(2): error: syntax error".to_string(),
+            })
+        };
+        assert!(replace_program(&mut id, broken).is_err());
+        assert_eq!(id, 3);
+
+        // The previous program is handed back to be deleted.
+        assert_eq!(replace_program(&mut id, || Ok(7)).unwrap(), 3);
+        assert_eq!(id, 7);
+    }
+
+    #[test]
+    #[ignore] // Needs a display for the OpenGL context.
+    fn reject_broken_glsl() {
+        let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+        glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
+        glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+        glfw.window_hint(glfw::WindowHint::Visible(false));
+        let (mut window, _events) = glfw.create_window(64, 64, "test", glfw::WindowMode::Windowed).unwrap();
+        window.make_current();
+        gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+
+        let vertex = "#version 330 core\nvoid main() { gl_Position = vec4(0.); }\n";
+        let broken = "#version 330 core\nout vec4 FragColor;\nvoid main() { FragColor = vec4(1.) }\n";
+        match unsafe { link(("test.vs", vertex), ("broken.fs", broken)) } {
+            Err(ShaderError::Compile { stage, log }) => {
+                assert_eq!(stage, "broken.fs");
+                // The compiler names the line of the error.
+                assert!(log.contains('3'), "{}", log);
             }
+            result => panic!("expected a compile error, got {:?}", result),
         }
     }
 }
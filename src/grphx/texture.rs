@@ -13,11 +13,14 @@
 // You should have received a copy of the GNU General Public License
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
 use gl;
+use gl::types::GLenum;
 use image;
 use image::DynamicImage::*;
 use image::GenericImageView;
 use log::{debug, error};
 
+use std::error::Error;
+use std::fmt;
 use std::os::raw::c_void;
 use std::path::Path;
 
@@ -26,9 +29,42 @@ pub struct Texture {
     pub id: u32,
 }
 
+/// Error while loading a `Texture` from an image file.
+#[derive(Debug)]
+pub enum TextureError {
+    /// The file could not be read or decoded.
+    Image(image::ImageError),
+    /// The image is neither RGB8 nor RGBA8.
+    Format,
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Image(e) => write!(f, "failed to load image: {}", e),
+            TextureError::Format => write!(f, "image format has to be RGB8 or RGBA8"),
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+impl From<image::ImageError> for TextureError {
+    fn from(e: image::ImageError) -> TextureError {
+        TextureError::Image(e)
+    }
+}
+
 impl Texture {
-    pub fn new(path: &str) -> Texture {
-        unsafe { Texture { id: load_texture(path) } }
+    /// Load a texture from a PNG or JPEG file with a relative or absolute path.
+    pub fn from_file(path: &Path) -> Result<Texture, TextureError> {
+        debug!("New from {}", path.display());
+        let image = load_image(path)?;
+        unsafe {
+            Ok(Texture {
+                id: upload_texture(&image),
+            })
+        }
     }
 }
 
@@ -38,17 +74,18 @@ impl Default for Texture {
     }
 }
 
-unsafe fn load_texture(path: &str) -> u32 {
-    let path_str = format!("{}{}", "res/textures/", path);
-    debug!("New from {}", path_str);
-
-    let path = Path::new(&path_str);
-
-    let mut tex_id = 0;
+/// Decoded pixels of an image and their OpenGL format.
+struct Image {
+    format: GLenum,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
 
-    gl::GenTextures(1, &mut tex_id);
-    let img = image::open(&path).expect("ERROR: Failed to load texture!").flipv();
-    let image_format = match img {
+/// Read an image and flip it vertically, as OpenGL starts at the bottom row.
+fn load_image(path: &Path) -> Result<Image, TextureError> {
+    let img = image::open(path)?.flipv();
+    let format = match img {
         ImageRgb8(_) => {
             debug!("Format: RGB8");
             gl::RGB
@@ -59,23 +96,33 @@ unsafe fn load_texture(path: &str) -> u32 {
         }
         _ => {
             error!("Format wrong");
-            panic!("ERROR: Wrong image format!")
+            return Err(TextureError::Format);
         }
     };
 
-    let data = img.raw_pixels();
+    Ok(Image {
+        format,
+        width: img.width(),
+        height: img.height(),
+        data: img.raw_pixels(),
+    })
+}
+
+unsafe fn upload_texture(image: &Image) -> u32 {
+    let mut tex_id = 0;
 
+    gl::GenTextures(1, &mut tex_id);
     gl::BindTexture(gl::TEXTURE_2D, tex_id);
     gl::TexImage2D(
         gl::TEXTURE_2D,
         0,
-        image_format as i32,
-        img.width() as i32,
-        img.height() as i32,
+        image.format as i32,
+        image.width as i32,
+        image.height as i32,
         0,
-        image_format,
+        image.format,
         gl::UNSIGNED_BYTE,
-        &data[0] as *const u8 as *const c_void,
+        &image.data[0] as *const u8 as *const c_void,
     );
     gl::GenerateMipmap(gl::TEXTURE_2D);
 
@@ -84,7 +131,7 @@ unsafe fn load_texture(path: &str) -> u32 {
     gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
     gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
 
-    debug!("id:{}, width:{}px, height:{}px", tex_id, img.width(), img.height());
+    debug!("id:{}, width:{}px, height:{}px", tex_id, image.width, image.height);
 
     tex_id
 }
@@ -92,7 +139,36 @@ unsafe fn load_texture(path: &str) -> u32 {
 impl Drop for Texture {
     fn drop(&mut self) {
         unsafe {
-            gl::DeleteTextures(1, self.id as *const u32);
+            gl::DeleteTextures(1, &self.id);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{load_image, TextureError};
+    use image::{Rgb, RgbImage};
+    use std::env;
+    use std::path::Path;
+
+    #[test]
+    fn load_valid_image() {
+        let path = env::temp_dir().join("carambolage-texture.png");
+        let mut img = RgbImage::new(2, 3);
+        img.put_pixel(0, 0, Rgb([255, 0, 0]));
+        img.save(&path).unwrap();
+
+        let image = load_image(&path).unwrap();
+        assert_eq!(image.format, gl::RGB);
+        assert_eq!((image.width, image.height), (2, 3));
+        assert_eq!(image.data.len(), 2 * 3 * 3);
+        // The top left pixel ends up in the last row.
+        assert_eq!(&image.data[2 * 2 * 3..2 * 2 * 3 + 3], &[255, 0, 0]);
+    }
+
+    #[test]
+    fn load_missing_image() {
+        let path = Path::new("res/textures/does-not-exist.png");
+        assert!(matches!(load_image(path), Err(TextureError::Image(_))));
+    }
+}
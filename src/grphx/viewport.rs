@@ -0,0 +1,99 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::game::SplitScreen;
+
+/// Rectangle of the window [px] to draw into, with the origin in the bottom left corner like OpenGL.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Viewport {
+        Viewport { x, y, width, height }
+    }
+
+    /// Return a viewport covering the whole window.
+    pub fn full(width: u32, height: u32) -> Viewport {
+        Viewport::new(0, 0, width, height)
+    }
+
+    /// Return the distance [px] of the top edge from the top of a window `height` high, e.g. for the UI.
+    pub fn top(&self, height: u32) -> u32 {
+        height.saturating_sub(self.y + self.height)
+    }
+
+    /// Draw into this rectangle from now on.
+    pub fn apply(&self) {
+        unsafe {
+            gl::Viewport(self.x as i32, self.y as i32, self.width as i32, self.height as i32);
+        }
+    }
+}
+
+/// Return the viewport of each of the `players` in a window of `width` x `height` [px].
+///
+/// Two players share the window along the `split`, three or four players get a quarter each,
+/// starting in the top left corner. Without a split there is a single viewport for everyone.
+pub fn split_viewports(players: usize, width: u32, height: u32, split: SplitScreen) -> Vec<Viewport> {
+    let (left, right) = (width / 2, width - width / 2);
+    let (bottom, top) = (height / 2, height - height / 2);
+    match (players, split) {
+        (0..=1, _) | (_, SplitScreen::Off) => vec![Viewport::full(width, height)],
+        (2, SplitScreen::Horizontal) => vec![Viewport::new(0, bottom, width, top), Viewport::new(0, 0, width, bottom)],
+        (2, SplitScreen::Vertical) => vec![Viewport::new(0, 0, left, height), Viewport::new(left, 0, right, height)],
+        _ => vec![
+            Viewport::new(0, bottom, left, top),
+            Viewport::new(left, bottom, right, top),
+            Viewport::new(0, 0, left, bottom),
+            Viewport::new(left, 0, right, bottom),
+        ]
+        .into_iter()
+        .take(players)
+        .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_window() {
+        assert_eq!(split_viewports(1, 640, 480, SplitScreen::Vertical), vec![Viewport::full(640, 480)]);
+        assert_eq!(split_viewports(2, 640, 480, SplitScreen::Off), vec![Viewport::full(640, 480)]);
+
+        // Player 1 on top, odd sizes don't leave a gap.
+        let horizontal = split_viewports(2, 640, 481, SplitScreen::Horizontal);
+        assert_eq!(horizontal[0], Viewport::new(0, 240, 640, 241));
+        assert_eq!(horizontal[1], Viewport::new(0, 0, 640, 240));
+        assert_eq!(horizontal[0].top(481), 0);
+        assert_eq!(horizontal[1].top(481), 241);
+
+        let vertical = split_viewports(2, 641, 480, SplitScreen::Vertical);
+        assert_eq!(vertical[0], Viewport::new(0, 0, 320, 480));
+        assert_eq!(vertical[1], Viewport::new(320, 0, 321, 480));
+
+        let quarters = split_viewports(3, 640, 480, SplitScreen::Horizontal);
+        assert_eq!(quarters.len(), 3);
+        assert_eq!(quarters[2], Viewport::new(0, 0, 320, 240));
+        let area = |viewports: Vec<Viewport>| viewports.iter().map(|v| v.width * v.height).sum::<u32>();
+        assert_eq!(area(split_viewports(4, 641, 481, SplitScreen::Vertical)), 641 * 481);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::game::lap::LapTimer;
+use imgui_glfw_rs::imgui::Ui;
+
+/// Shown instead of a lap time that isn't there yet.
+const NO_TIME: &str = "--:--";
+
+/// Convert a speed from m/s to km/h.
+pub fn to_kmh(speed: f32) -> f32 {
+    speed * 3.6
+}
+
+/// Format a duration of `seconds` as `m:ss.mmm`.
+pub fn format_lap_time(seconds: f32) -> String {
+    let millis = (f64::from(seconds.max(0.)) * 1000.).round() as u64;
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+
+/// Format an optional lap time, or a placeholder if there is none.
+fn format_optional(seconds: Option<f32>) -> String {
+    seconds.map_or_else(|| NO_TIME.to_owned(), format_lap_time)
+}
+
+/// Format the running `lap` of a race of `laps` with its `current` time, or the finish after the last lap.
+fn format_lap(lap: usize, laps: usize, current: Option<f32>) -> String {
+    if lap > laps {
+        String::from("Finished")
+    } else {
+        format!("Lap {}/{} {}", lap, laps, format_optional(current))
+    }
+}
+
+/// Format the average `frame_time` [s] as frames per second and milliseconds.
+pub fn format_frame_stats(frame_time: f32) -> String {
+    if frame_time > 0. {
+        format!("{:.0} FPS {:.1} ms", 1. / frame_time, frame_time * 1000.)
+    } else {
+        String::from("-- FPS")
+    }
+}
+
+/// Format the `score` with the combo `multiplier`, which is left out outside of a combo.
+fn format_score(score: u32, multiplier: f32) -> String {
+    if multiplier > 1. {
+        format!("Score {} x{:.1}", score, multiplier)
+    } else {
+        format!("Score {}", score)
+    }
+}
+
+/// Lap times shown in the HUD.
+struct LapTimes {
+    /// Number of the running lap.
+    lap: usize,
+    /// Laps of the race.
+    laps: usize,
+    current: Option<f32>,
+    last: Option<f32>,
+    best: Option<f32>,
+}
+
+/// Heads-up display of a player, drawn in screen space on top of the scene.
+#[derive(Default)]
+pub struct Hud {
+    /// Displayed speed [km/h].
+    speed: f32,
+    /// `None` if there is no lap timer.
+    laps: Option<LapTimes>,
+    /// Points for hitting other cars.
+    score: u32,
+    /// Multiplier of the next hit, 1.0 outside of a combo.
+    multiplier: f32,
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Default::default()
+    }
+
+    /// Update the speedometer with the `speed` [m/s] of the car.
+    pub fn update(&mut self, speed: f32) {
+        self.speed = to_kmh(speed);
+    }
+
+    /// Update the `score` and the combo `multiplier` of the next hit.
+    pub fn update_score(&mut self, score: u32, multiplier: f32) {
+        self.score = score;
+        self.multiplier = multiplier;
+    }
+
+    /// Update the lap times from the `timer` of the car in a race of `laps`, if the track has a finish line.
+    pub fn update_laps(&mut self, timer: Option<&LapTimer>, laps: usize) {
+        self.laps = timer.map(|t| LapTimes {
+            lap: t.laps() + 1,
+            laps,
+            current: t.current_lap_time(),
+            last: t.last_lap(),
+            best: t.best_lap(),
+        });
+    }
+
+    /// Draw the speedometer, the score and the lap times into the current window.
+    pub fn draw(&self, ui: &Ui) {
+        ui.text(format!("{:>3.0} km/h", self.speed));
+        ui.text(format_score(self.score, self.multiplier));
+        if let Some(laps) = &self.laps {
+            ui.text(format_lap(laps.lap, laps.laps, laps.current));
+            ui.text(format!("Last   {}", format_optional(laps.last)));
+            ui.text(format!("Best   {}", format_optional(laps.best)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_frame_stats, format_lap, format_lap_time, format_optional, format_score, to_kmh, Hud, NO_TIME};
+
+    #[test]
+    fn speed_in_kmh() {
+        assert_eq!(to_kmh(0.), 0.);
+        assert!((to_kmh(10.) - 36.).abs() < 1e-4);
+        assert!((to_kmh(27.5) - 99.).abs() < 1e-4);
+
+        let mut hud = Hud::new();
+        hud.update(25.);
+        assert!((hud.speed - 90.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lap_time_format() {
+        assert_eq!(format_lap_time(0.), "0:00.000");
+        assert_eq!(format_lap_time(0.042), "0:00.042");
+        assert_eq!(format_lap_time(0.9999), "0:01.000");
+        assert_eq!(format_lap_time(7.25), "0:07.250");
+        assert_eq!(format_lap_time(59.5), "0:59.500");
+        assert_eq!(format_lap_time(83.125), "1:23.125");
+        assert_eq!(format_lap_time(754.5), "12:34.500");
+        assert_eq!(format_optional(None), "--:--");
+    }
+
+    #[test]
+    fn lap_format() {
+        assert_eq!(format_lap(1, 3, None), format!("Lap 1/3 {}", NO_TIME));
+        assert_eq!(format_lap(3, 3, Some(1.5)), "Lap 3/3 0:01.500");
+        assert_eq!(format_lap(4, 3, Some(1.5)), "Finished");
+    }
+
+    #[test]
+    fn score_format() {
+        assert_eq!(format_score(0, 1.), "Score 0");
+        assert_eq!(format_score(250, 1.5), "Score 250 x1.5");
+    }
+
+    #[test]
+    fn frame_stats_format() {
+        assert_eq!(format_frame_stats(1. / 60.), "60 FPS 16.7 ms");
+        assert_eq!(format_frame_stats(0.), "-- FPS");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::game::car::Car;
+use crate::game::track::Track;
+use imgui_glfw_rs::imgui::Ui;
+use nalgebra::Vector3;
+
+/// Color of the walls on the minimap.
+const WALL_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.];
+/// Color of the ring around the car of the player.
+const HIGHLIGHT_COLOR: [f32; 4] = [1., 0.8, 0., 1.];
+
+/// Top-down view of the track and the cars in a corner of the screen.
+///
+/// The x-y plane of the world is scaled uniformly to fit the bounding box of the track into the
+/// minimap, so every level fits.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Minimap {
+    /// Lower left corner of the track in world space.
+    min: Vector3<f32>,
+    /// Pixels per meter.
+    scale: f32,
+    /// Margin [px] to center the track in the minimap.
+    offset: (f32, f32),
+    /// Height of the minimap [px].
+    height: f32,
+}
+
+impl Minimap {
+    /// Create a minimap of `size` [px] showing the box from `min` to `max` in the x-y plane of the world.
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>, size: (f32, f32)) -> Minimap {
+        let extents = max - min;
+        let scale = (size.0 / extents[0].max(1.)).min(size.1 / extents[1].max(1.));
+        Minimap {
+            min,
+            scale,
+            offset: ((size.0 - extents[0] * scale) * 0.5, (size.1 - extents[1] * scale) * 0.5),
+            height: size.1,
+        }
+    }
+
+    /// Create a minimap of `size` [px] fitting the walls of the `track`, `None` if it has no walls.
+    pub fn from_track(track: &Track, size: (f32, f32)) -> Option<Minimap> {
+        track.bounds().map(|(min, max)| Minimap::new(min, max, size))
+    }
+
+    /// Return the position [px] of the world `position` on the minimap, relative to its top left corner.
+    ///
+    /// The y-axis of the screen points down, so north on the track is up on the minimap.
+    pub fn project(&self, position: &Vector3<f32>) -> (f32, f32) {
+        let x = self.offset.0 + (position[0] - self.min[0]) * self.scale;
+        let y = self.height - self.offset.1 - (position[1] - self.min[1]) * self.scale;
+        (x, y)
+    }
+
+    /// Draw the walls of the `track` and a dot for every car in its color into the current window.
+    /// The car of `player` is highlighted.
+    pub fn draw(&self, ui: &Ui, track: &Track, cars: &[Car], player: usize) {
+        let (left, top) = ui.get_cursor_screen_pos();
+        let screen = |position: &Vector3<f32>| {
+            let (x, y) = self.project(position);
+            (left + x, top + y)
+        };
+        let draw_list = ui.get_window_draw_list();
+        for wall in &track.walls {
+            draw_list.add_line(screen(&wall.a), screen(&wall.b), WALL_COLOR).build();
+        }
+        for (id, car) in cars.iter().enumerate() {
+            let center = screen(&car.position);
+            draw_list.add_circle(center, 4., dot_color(car)).filled(true).build();
+            if id == player {
+                draw_list.add_circle(center, 7., HIGHLIGHT_COLOR).thickness(2.).build();
+            }
+        }
+    }
+}
+
+/// Return the opaque color of the dot of `car`, its tint.
+fn dot_color(car: &Car) -> [f32; 4] {
+    let color = car.color();
+    [color[0], color[1], color[2], 1.]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dot_color, Minimap};
+    use crate::game::car::Car;
+    use crate::game::track::{Track, Wall};
+    use nalgebra::Vector3;
+
+    #[test]
+    fn track_corners() {
+        let corners = [
+            Vector3::new(-100., -50., 0.),
+            Vector3::new(100., -50., 0.),
+            Vector3::new(100., 50., 0.),
+            Vector3::new(-100., 50., 0.),
+        ];
+        let walls = (0..4).map(|i| Wall::new(corners[i], corners[(i + 1) % 4])).collect();
+        let minimap = Minimap::from_track(&Track::new(walls), (200., 200.)).unwrap();
+
+        // The track is twice as wide as high, so it is centered vertically with a margin of 50 px.
+        assert_eq!(minimap.project(&corners[0]), (0., 150.));
+        assert_eq!(minimap.project(&corners[1]), (200., 150.));
+        assert_eq!(minimap.project(&corners[2]), (200., 50.));
+        assert_eq!(minimap.project(&corners[3]), (0., 50.));
+        assert_eq!(minimap.project(&Vector3::new(0., 0., 5.)), (100., 100.));
+
+        assert_eq!(Minimap::from_track(&Track::new(Vec::new()), (200., 200.)), None);
+    }
+
+    #[test]
+    fn dot_colors() {
+        let mut red = Car::test_car(Vector3::new(0., 0., 0.), 1000.0);
+        let mut blue = Car::test_car(Vector3::new(5., 0., 0.), 1000.0);
+        red.set_color(Vector3::new(1., 0.3, 0.3));
+        blue.set_color(Vector3::new(0.3, 0.5, 1.));
+        assert_eq!(dot_color(&red), [1., 0.3, 0.3, 1.]);
+        assert_eq!(dot_color(&blue), [0.3, 0.5, 1., 1.]);
+    }
+}
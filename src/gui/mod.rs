@@ -1,9 +1,18 @@
+/// Speedometer and lap times of each player.
+mod hud;
+/// Top-down overview of the track.
+mod minimap;
+
 use imgui_glfw_rs::glfw;
 use imgui_glfw_rs::imgui;
 
+use self::hud::{format_frame_stats, format_lap_time, Hud};
+use self::minimap::Minimap;
+use crate::game::lap::RaceResult;
 use crate::game::scene::Scene;
+use crate::game::state::{GameState, StateInput};
 use glfw::{Window, WindowEvent};
-use imgui::{im_str, FontGlyphRange, ImFontConfig, ImGui, ImGuiCol, ImVec2, ImVec4};
+use imgui::{im_str, FontGlyphRange, ImFontConfig, ImGui, ImGuiCol, ImString, ImVec2, ImVec4};
 use imgui_glfw_rs::ImguiGLFW;
 use imgui_opengl_renderer::Renderer;
 
@@ -11,11 +20,10 @@ pub struct AppUI {
     imgui: ImGui,
     imgui_glfw: ImguiGLFW,
     imgui_renderer: Renderer,
-
-    pub is_ingame: bool,
-    pub is_ingame_menu: bool,
-
-    is_key_esc: bool,
+    huds: Vec<Hud>,
+    results: Vec<RaceResult>,
+    /// Average frame time [s] to show, if the frame rate is visible.
+    frame_time: Option<f32>,
 }
 
 impl AppUI {
@@ -30,11 +38,9 @@ impl AppUI {
             imgui,
             imgui_glfw,
             imgui_renderer,
-
-            is_ingame: true,
-            is_ingame_menu: false,
-
-            is_key_esc: false,
+            huds: Vec::new(),
+            results: Vec::new(),
+            frame_time: None,
         }
     }
 
@@ -116,7 +122,20 @@ impl AppUI {
         imgui
     }
 
-    pub fn draw(&mut self, window: &mut Window, scene: &mut Scene) {
+    /// Set the results of the last race, in finishing order.
+    pub fn set_results(&mut self, results: Vec<RaceResult>) {
+        self.results = results;
+    }
+
+    /// Show the average `frame_time` [s] in the corner of the screen, or hide it with `None`.
+    pub fn set_frame_time(&mut self, frame_time: Option<f32>) {
+        self.frame_time = frame_time;
+    }
+
+    /// Draw the screen of the game `state` and return the input of its buttons.
+    ///
+    /// The menu toggles `vsync`.
+    pub fn draw(&mut self, window: &mut Window, scene: &mut Scene, state: GameState, vsync: &mut bool) -> Option<StateInput> {
         let ui = self.imgui_glfw.frame(window, &mut self.imgui);
 
         let (width, height) = window.get_size();
@@ -124,52 +143,124 @@ impl AppUI {
         let height = height as f32;
 
         let mut should_close = false;
+        let mut input = None;
 
-        if self.is_ingame {
-            ui.window(im_str!("Player 1"))
-                .title_bar(true)
-                .position((20.0, height - 100.), imgui::ImGuiCond::Always)
-                .size((250.0, 0.0), imgui::ImGuiCond::Once)
-                .always_use_window_padding(true)
+        if let Some(frame_time) = self.frame_time {
+            ui.window(im_str!("Frame rate"))
+                .title_bar(false)
+                .position((width - 220., 10.), imgui::ImGuiCond::Always)
+                .size((210., 0.), imgui::ImGuiCond::Once)
                 .collapsible(false)
                 .resizable(false)
                 .movable(false)
                 .build(|| {
-                    ui.progress_bar(scene.cars[0].boost / 100.)
-                        .overlay_text(im_str!("BOOST"))
-                        .size((-1., 40.))
-                        .build();
+                    ui.text(format_frame_stats(frame_time));
                 });
-            ui.window(im_str!("Player 2"))
+        }
+
+        if state == GameState::Menu {
+            ui.window(im_str!("Carambolage"))
                 .title_bar(true)
-                .position((width - 270., height - 100.), imgui::ImGuiCond::Always)
-                .size((250.0, 0.0), imgui::ImGuiCond::Once)
+                .position((width * 0.5 - 120., height * 0.5 - 80.), imgui::ImGuiCond::Always)
+                .size((240., 0.), imgui::ImGuiCond::Once)
                 .always_use_window_padding(true)
                 .collapsible(false)
                 .resizable(false)
                 .movable(false)
                 .build(|| {
-                    ui.progress_bar(scene.cars[1].boost / 100.)
-                        .overlay_text(im_str!("BOOST"))
-                        .size((-1., 40.))
-                        .build();
+                    if ui.button(im_str!("Race"), (200., 40.)) {
+                        input = Some(StateInput::Confirm);
+                    }
+                    if ui.button(im_str!("Exit"), (200., 40.)) {
+                        should_close = true;
+                    }
                 });
+        }
+
+        if state == GameState::Playing || state == GameState::Paused {
+            self.huds.resize_with(scene.simulation.cars.len(), Hud::new);
+            for (id, (car, hud)) in scene.simulation.cars.iter().zip(self.huds.iter_mut()).enumerate() {
+                hud.update(car.speed());
+                hud.update_laps(scene.simulation.lap_timers.get(id), scene.simulation.laps);
+                hud.update_score(scene.score.points(id), scene.score.multiplier(id));
+            }
 
-            let mut close_ingame_menu = false;
-            if !self.is_key_esc && window.get_key(glfw::Key::Escape) == glfw::Action::Press {
-                if self.is_ingame_menu {
-                    close_ingame_menu = true;
+            // Speedometers, scores, lap times, boost and damage bars, odd players on the left and even players on the right.
+            // On a split screen each player has them in the bottom left corner of its viewport.
+            let window_height = if scene.simulation.lap_timers.is_empty() { 160. } else { 240. };
+            let viewports = scene.viewports(width as u32, height as u32);
+            for (id, (car, hud)) in scene.simulation.cars.iter().zip(self.huds.iter()).enumerate() {
+                let (x, y) = match viewports.get(id).filter(|_| viewports.len() > 1) {
+                    Some(viewport) => (
+                        viewport.x as f32 + 20.,
+                        (viewport.top(height as u32) + viewport.height) as f32 - (window_height + 20.),
+                    ),
+                    None => (
+                        if id % 2 == 0 { 20. } else { width - 270. },
+                        height - (window_height + 20.) * (1 + id / 2) as f32,
+                    ),
+                };
+                ui.window(&ImString::new(format!("Player {}", id + 1)))
+                    .title_bar(true)
+                    .position((x, y), imgui::ImGuiCond::Always)
+                    .size((250.0, 0.0), imgui::ImGuiCond::Once)
+                    .always_use_window_padding(true)
+                    .collapsible(false)
+                    .resizable(false)
+                    .movable(false)
+                    .build(|| {
+                        hud.draw(&ui);
+                        ui.progress_bar(car.boost_level())
+                            .overlay_text(im_str!("BOOST"))
+                            .size((-1., 40.))
+                            .build();
+                        ui.progress_bar(car.damage_level())
+                            .overlay_text(im_str!("DAMAGE"))
+                            .size((-1., 20.))
+                            .build();
+                    });
+            }
+
+            // Minimap in the top left corner, if the track has walls to fit it to.
+            let minimap_size = (200., 200.);
+            if let Some(track) = scene.simulation.layout.as_ref().map(|layout| &layout.track) {
+                if let Some(minimap) = Minimap::from_track(track, minimap_size) {
+                    ui.window(im_str!("Minimap"))
+                        .title_bar(false)
+                        .position((10., 10.), imgui::ImGuiCond::Always)
+                        .size((0., 0.), imgui::ImGuiCond::Always)
+                        .always_use_window_padding(true)
+                        .collapsible(false)
+                        .resizable(false)
+                        .movable(false)
+                        .build(|| {
+                            minimap.draw(&ui, track, &scene.simulation.cars, 0);
+                            ui.dummy(minimap_size);
+                        });
                 }
-                self.is_ingame_menu = !self.is_ingame_menu;
-                self.is_key_esc = true;
-            } else if self.is_key_esc && window.get_key(glfw::Key::Escape) == glfw::Action::Release {
-                self.is_key_esc = false;
             }
 
-            let mut is_ingame_menu = self.is_ingame_menu;
+            if let Some(label) = scene.simulation.countdown.label() {
+                ui.window(im_str!("Countdown"))
+                    .title_bar(false)
+                    .position((width * 0.5 - 100., height * 0.3 - 60.), imgui::ImGuiCond::Always)
+                    .size((200., 120.), imgui::ImGuiCond::Always)
+                    .collapsible(false)
+                    .resizable(false)
+                    .movable(false)
+                    .build(|| {
+                        unsafe {
+                            imgui::sys::igSetWindowFontScale(4.);
+                        }
+                        ui.text(label);
+                    });
+            }
+
             let mut is_smooth_zoom = scene.camera.is_smooth_zoom;
             let mut is_smooth_pan = scene.camera.is_smooth_pan;
-            if is_ingame_menu {
+            let mut is_chase_camera = scene.is_chase_camera;
+            let mut is_skid_smoke = scene.skid_emitters.iter().any(|e| e.is_enabled);
+            if state == GameState::Paused {
                 ui.open_popup(im_str!("Menu"));
             }
             ui.popup_modal(im_str!("Menu"))
@@ -179,27 +270,65 @@ impl AppUI {
                 .resizable(false)
                 .movable(false)
                 .build(|| {
-                    if ui.button(im_str!("Continue"), (200., 40.)) || close_ingame_menu {
+                    if ui.button(im_str!("Continue"), (200., 40.)) {
+                        input = Some(StateInput::Confirm);
+                    }
+                    // Close as soon as the game continues, whether by button or key.
+                    if state != GameState::Paused {
                         ui.close_current_popup();
-                        is_ingame_menu = false;
                     }
                     ui.separator();
                     ui.text(im_str!("Camera settings:"));
                     ui.checkbox(im_str!("Smooth zoom"), &mut is_smooth_zoom);
                     ui.checkbox(im_str!("Smooth pan"), &mut is_smooth_pan);
+                    ui.checkbox(im_str!("Chase player 1"), &mut is_chase_camera);
+                    ui.separator();
+                    ui.text(im_str!("Effects:"));
+                    ui.checkbox(im_str!("Skid smoke"), &mut is_skid_smoke);
+                    ui.separator();
+                    ui.text(im_str!("Display:"));
+                    ui.checkbox(im_str!("Vertical sync"), vsync);
                     ui.separator();
                     if ui.button(im_str!("Exit"), (200., 40.)) {
                         should_close = true;
                     }
                 });
-            self.is_ingame_menu = is_ingame_menu;
             scene.camera.is_smooth_zoom = is_smooth_zoom;
             scene.camera.is_smooth_pan = is_smooth_pan;
+            scene.is_chase_camera = is_chase_camera;
+            for emitter in &mut scene.skid_emitters {
+                emitter.is_enabled = is_skid_smoke;
+            }
+        }
+
+        if state == GameState::Results {
+            let results = &self.results;
+            ui.window(im_str!("Results"))
+                .title_bar(true)
+                .position((width * 0.5 - 210., height * 0.5 - 150.), imgui::ImGuiCond::Always)
+                .size((420., 0.), imgui::ImGuiCond::Once)
+                .always_use_window_padding(true)
+                .collapsible(false)
+                .resizable(false)
+                .movable(false)
+                .build(|| {
+                    ui.text("   Player    Total       Best lap");
+                    for (place, result) in results.iter().enumerate() {
+                        let total = result.total.map_or_else(|| String::from("DNF"), format_lap_time);
+                        let best = result.best_lap.map_or_else(|| String::from("--:--"), format_lap_time);
+                        ui.text(format!("{}. {:<9} {:<11} {}", place + 1, result.player + 1, total, best));
+                    }
+                    ui.separator();
+                    if ui.button(im_str!("Back to menu"), (-1., 40.)) {
+                        input = Some(StateInput::Confirm);
+                    }
+                });
         }
 
         window.set_should_close(should_close);
 
         self.imgui_renderer.render(ui);
+        input
     }
 
     pub fn handle_event(&mut self, event: &WindowEvent) {
@@ -14,18 +14,22 @@
 // along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
 use simplelog::*;
 
+/// Engine sound.
+mod audio;
 /// Core application logic.
 mod game;
 /// Graphics module of carambolage.
 mod grphx;
 /// User interface
 mod gui;
+/// Online races between a host and a client.
+mod net;
 /// Physics module of carambolage.
 mod physx;
 /// Utility modules and traits.
 mod util;
 
-use crate::game::{Game, GameSettings};
+use crate::game::{Game, GameSettings, CONFIG_FILE};
 use getopts::{Matches, Options};
 use log::info;
 use std::env;
@@ -42,9 +46,6 @@ fn main() {
         Err(f) => panic!(f.to_string()),
     };
 
-    // Filter settings
-    let game_settings = match_options(&matches);
-
     let terminal_log_config = Config {
         time: Some(Level::Error),
         target: Some(Level::Debug),
@@ -61,6 +62,9 @@ fn main() {
     ])
     .unwrap();
 
+    // Filter settings, the command line overrides the config file.
+    let game_settings = match_options(&matches, GameSettings::load(CONFIG_FILE));
+
     // Start the game
     info!("Starting game");
     let mut game = Game::new(game_settings);
@@ -74,11 +78,16 @@ fn get_options() -> Options {
     opts.optopt("h", "height", "set window height", "HEIGHT");
     opts.optopt("m", "map", "set the startup map by id", "MAP");
     opts.optopt("l", "limit-fps", "set max game fps [0 = unlimited]", "FPS");
+    opts.optopt("p", "players", "set the number of local players [1-4]", "PLAYERS");
+    opts.optopt("g", "ghost", "race against the ghost of a recorded run", "FILE");
+    opts.optopt("r", "record", "record the run of player 1 to a file", "FILE");
+    opts.optopt("t", "time-scale", "set the speed of the simulation [1 = real time]", "SCALE");
+    opts.optopt("", "host", "host an online race of two players", "ADDRESS");
+    opts.optopt("", "join", "join the online race of a host", "ADDRESS");
     opts
 }
 
-fn match_options(matches: &Matches) -> GameSettings {
-    let mut game_settings: GameSettings = Default::default();
+fn match_options(matches: &Matches, mut game_settings: GameSettings) -> GameSettings {
     if matches.opt_present("f") {
         game_settings.is_fullscreen = true;
     }
@@ -94,6 +103,24 @@ fn match_options(matches: &Matches) -> GameSettings {
     if matches.opt_str("l").is_some() {
         game_settings.fps = matches.opt_str("l").unwrap().parse().unwrap();
     }
+    if matches.opt_str("p").is_some() {
+        game_settings.players = matches.opt_str("p").unwrap().parse().unwrap();
+    }
+    if matches.opt_str("t").is_some() {
+        game_settings.time_scale = matches.opt_str("t").unwrap().parse().unwrap();
+    }
+    if matches.opt_present("g") {
+        game_settings.ghost = matches.opt_str("g");
+    }
+    if matches.opt_present("r") {
+        game_settings.record = matches.opt_str("r");
+    }
+    if matches.opt_present("host") {
+        game_settings.host = matches.opt_str("host");
+    }
+    if matches.opt_present("join") {
+        game_settings.join = matches.opt_str("join");
+    }
     game_settings
 }
 
@@ -114,6 +141,14 @@ mod tests {
             String::from("60"),
             String::from("-m"),
             String::from("1"),
+            String::from("-p"),
+            String::from("3"),
+            String::from("-g"),
+            String::from("best.json"),
+            String::from("-t"),
+            String::from("0.5"),
+            String::from("--join"),
+            String::from("127.0.0.1:7878"),
         ];
         let opts = get_options();
         let matches = match opts.parse(&args[1..]) {
@@ -121,12 +156,18 @@ mod tests {
             Err(f) => panic!(f.to_string()),
         };
 
-        let settings = match_options(&matches);
+        let settings = match_options(&matches, Default::default());
 
         assert_eq!(settings.is_fullscreen, true);
         assert_eq!(settings.width, 1920);
         assert_eq!(settings.height, 1080);
         assert_eq!(settings.fps, 60);
         assert_eq!(settings.map, 1);
+        assert_eq!(settings.players, 3);
+        assert_eq!(settings.ghost, Some(String::from("best.json")));
+        assert_eq!(settings.record, None);
+        assert_eq!(settings.time_scale, 0.5);
+        assert_eq!(settings.host, None);
+        assert_eq!(settings.join, Some(String::from("127.0.0.1:7878")));
     }
 }
@@ -0,0 +1,582 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::game::car::{Car, CarState};
+use crate::game::controller::Controller;
+use crate::game::replay::InputFrame;
+use crate::game::simulation::Simulation;
+use crate::game::FIXED_TIMESTEP;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// Number of players in an online race, the host and one client.
+pub const NET_PLAYERS: usize = 2;
+/// Player id of the client, the host drives player 0.
+pub const CLIENT_PLAYER: u8 = 1;
+/// Largest packet [bytes] that is read, a snapshot of two cars takes about 400.
+const MAX_PACKET_SIZE: usize = 4096;
+/// Rate [1/s] at which the client pulls its cars toward the received snapshot.
+const INTERPOLATION_RATE: f32 = 15.;
+/// Distance [m] from the snapshot at which a car jumps there instead, e.g. after a reset.
+const SNAP_DISTANCE: f32 = 5.;
+/// Inputs [steps] the host keeps of a client before dropping the oldest, so the car of a client
+/// that sent a burst of inputs doesn't drive behind for good.
+const MAX_QUEUED_INPUTS: usize = 30;
+/// Inputs [steps] the client predicts before giving up on the host, e.g. after it quit.
+const MAX_PREDICTED_INPUTS: usize = 600;
+/// Time [steps] without a new input after which a client may join again in the place of its player,
+/// e.g. after restarting the game.
+const REMOTE_TIMEOUT: u32 = 240;
+/// Time [steps] without a new snapshot after which the client follows the host from an older tick again,
+/// e.g. after restarting the game.
+const HOST_TIMEOUT: u32 = 240;
+
+/// Message between the host and a client.
+///
+/// Inputs go up from the clients, one per simulation step, snapshots of all cars come down from the
+/// host. Both carry a number that grows with every packet, so late packets of the unreliable UDP
+/// are dropped. A snapshot acknowledges the sequence of the last input of each player it includes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Packet {
+    Input {
+        player: u8,
+        sequence: u32,
+        input: InputFrame,
+    },
+    Snapshot {
+        tick: u32,
+        cars: Vec<CarState>,
+        #[serde(default)]
+        acks: Vec<u32>,
+    },
+}
+
+impl Packet {
+    pub fn to_bytes(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> serde_json::Result<Packet> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+/// Read all packets waiting on a non-blocking `socket` and pass them on with their sender.
+fn receive_packets(socket: &UdpSocket, mut on_packet: impl FnMut(Packet, SocketAddr)) {
+    let mut buffer = [0; MAX_PACKET_SIZE];
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((len, from)) => match Packet::from_bytes(&buffer[..len]) {
+                Ok(packet) => on_packet(packet, from),
+                Err(e) => warn!("Dropped a broken packet from {}: {}", from, e),
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => {
+                warn!("Could not receive packets: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Send a `packet` to `to`, a lost packet is replaced by the next one anyway.
+fn send_packet(socket: &UdpSocket, packet: &Packet, to: SocketAddr) {
+    let result = packet
+        .to_bytes()
+        .map_err(io::Error::from)
+        .and_then(|bytes| socket.send_to(&bytes, to));
+    if let Err(e) = result {
+        warn!("Could not send a packet to {}: {}", to, e);
+    }
+}
+
+/// A remote player of the host.
+struct Remote {
+    address: SocketAddr,
+    /// Sequence of the newest received input.
+    sequence: u32,
+    /// Inputs waiting for their step, oldest first.
+    inputs: VecDeque<(u32, InputFrame)>,
+    /// Sequence and input of the last simulated step, repeated while no new input arrives.
+    last: (u32, InputFrame),
+    /// Steps simulated since the newest input arrived.
+    idle: u32,
+}
+
+impl Remote {
+    fn new(address: SocketAddr, input: InputFrame) -> Remote {
+        Remote {
+            address,
+            sequence: 0,
+            inputs: VecDeque::new(),
+            last: (0, input),
+            idle: 0,
+        }
+    }
+
+    /// Queue the `input` of step `sequence` that arrived from `address`, unless it is late.
+    ///
+    /// A client that restarted begins with a lower sequence again, possibly from another address.
+    /// Once the player timed out, such an input starts over in its place instead of being dropped.
+    fn receive(&mut self, address: SocketAddr, sequence: u32, input: InputFrame) {
+        if self.idle > REMOTE_TIMEOUT && (sequence <= self.sequence || address != self.address) {
+            info!("Rejoined from {}", address);
+            *self = Remote::new(address, input);
+        }
+        if sequence > self.sequence {
+            self.address = address;
+            self.sequence = sequence;
+            self.idle = 0;
+            self.inputs.push_back((sequence, input));
+            if self.inputs.len() > MAX_QUEUED_INPUTS {
+                self.inputs.pop_front();
+            }
+        }
+    }
+}
+
+/// Host of an online race, it simulates all cars and sends their states to the clients.
+pub struct Server {
+    socket: UdpSocket,
+    remotes: [Option<Remote>; NET_PLAYERS],
+    tick: u32,
+}
+
+impl Server {
+    /// Listen for clients on `address`, e.g. "0.0.0.0:7878".
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Server> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        info!("Hosting on {}", socket.local_addr()?);
+        Ok(Server {
+            socket,
+            remotes: [None, None],
+            tick: 0,
+        })
+    }
+
+    /// Take the newest inputs of the clients.
+    pub fn receive(&mut self) {
+        let remotes = &mut self.remotes;
+        receive_packets(&self.socket, |packet, address| {
+            let (player, sequence, input) = match packet {
+                Packet::Input { player, sequence, input } => (player, sequence, input),
+                Packet::Snapshot { .. } => return,
+            };
+            // The host drives player 0 itself.
+            let remote = match remotes.get_mut(player as usize) {
+                Some(remote) if player != 0 => remote,
+                _ => return,
+            };
+            remote
+                .get_or_insert_with(|| {
+                    info!("Player {} joined from {}", player, address);
+                    Remote::new(address, input)
+                })
+                .receive(address, sequence, input);
+        });
+    }
+
+    /// Replace the `controllers` of the remote players by their next input for one step.
+    pub fn apply_inputs(&mut self, controllers: &mut [Controller]) {
+        for (controller, remote) in controllers.iter_mut().zip(&mut self.remotes) {
+            if let Some(remote) = remote {
+                if let Some(next) = remote.inputs.pop_front() {
+                    remote.last = next;
+                }
+                remote.idle = remote.idle.saturating_add(1);
+                *controller = remote.last.1.to_controller();
+            }
+        }
+    }
+
+    /// Send the states of all `cars` to the clients.
+    pub fn send_snapshot(&mut self, cars: &[Car]) {
+        self.tick = self.tick.wrapping_add(1);
+        let packet = Packet::Snapshot {
+            tick: self.tick,
+            cars: cars.iter().map(Car::state).collect(),
+            acks: self
+                .remotes
+                .iter()
+                .map(|remote| remote.as_ref().map_or(0, |remote| remote.last.0))
+                .collect(),
+        };
+        for remote in self.remotes.iter().flatten() {
+            send_packet(&self.socket, &packet, remote.address);
+        }
+    }
+}
+
+/// Player of an online race that sends its input to the host and shows the cars it receives.
+pub struct Client {
+    socket: UdpSocket,
+    server: SocketAddr,
+    player: u8,
+    sequence: u32,
+    tick: u32,
+    /// Steps simulated since the newest snapshot arrived.
+    idle: u32,
+    /// Newest snapshot of the own car and the sequence of the last input it includes, until it is
+    /// reconciled with the prediction.
+    snapshot: Option<(CarState, u32)>,
+    pub prediction: Prediction,
+    pub interpolation: Interpolation,
+}
+
+impl Client {
+    /// Join the race hosted at `server` as `player`.
+    pub fn connect(server: impl ToSocketAddrs, player: u8) -> io::Result<Client> {
+        let server = server
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no server address"))?;
+        let local: SocketAddr = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        info!("Joining {} as player {}", server, player);
+        Ok(Client {
+            socket,
+            server,
+            player,
+            sequence: 0,
+            tick: 0,
+            idle: 0,
+            snapshot: None,
+            prediction: Prediction::new(),
+            interpolation: Interpolation::new(),
+        })
+    }
+
+    pub fn player(&self) -> usize {
+        self.player as usize
+    }
+
+    /// Send the input of the local `controller` for the step that is about to be simulated.
+    ///
+    /// The input is kept to replay it in case the host disagrees with the prediction.
+    pub fn send_input(&mut self, controller: &Controller) {
+        self.sequence += 1;
+        self.idle = self.idle.saturating_add(1);
+        let input = InputFrame::from_controller(controller);
+        self.prediction.push(self.sequence, input);
+        let packet = Packet::Input {
+            player: self.player,
+            sequence: self.sequence,
+            input,
+        };
+        send_packet(&self.socket, &packet, self.server);
+    }
+
+    /// Take the newest snapshot of the host, for the own car and the `interpolation` of the others.
+    pub fn receive(&mut self) {
+        let server = self.server;
+        let mut snapshots = Vec::new();
+        receive_packets(&self.socket, |packet, from| {
+            if let (true, Packet::Snapshot { tick, cars, acks }) = (from == server, packet) {
+                snapshots.push((tick, cars, acks));
+            }
+        });
+        self.interpolation.local = Some(self.player());
+        for (tick, cars, acks) in snapshots {
+            self.take_snapshot(tick, cars, acks);
+        }
+    }
+
+    /// Keep the snapshot of `tick` with the states of the `cars` and the sequences of the inputs they
+    /// include, unless it is older than the last one.
+    ///
+    /// A host that restarted begins with a lower tick again. Once no snapshot arrived for `HOST_TIMEOUT`
+    /// steps, such a snapshot starts over instead of being dropped.
+    fn take_snapshot(&mut self, tick: u32, cars: Vec<CarState>, acks: Vec<u32>) {
+        if tick <= self.tick {
+            if self.idle <= HOST_TIMEOUT {
+                return;
+            }
+            info!("Following the host from tick {} again", tick);
+        }
+        self.tick = tick;
+        self.idle = 0;
+        let player = self.player();
+        if let (Some(state), Some(ack)) = (cars.get(player), acks.get(player)) {
+            self.snapshot = Some((*state, *ack));
+        }
+        self.interpolation.target = cars;
+    }
+
+    /// Correct the predicted own car by the newest snapshot and move the others toward theirs.
+    pub fn update(&mut self, simulation: &mut Simulation, dt: f32) {
+        if let Some((state, ack)) = self.snapshot.take() {
+            self.prediction.reconcile(simulation, self.player(), &state, ack);
+        }
+        self.interpolation.update(&mut simulation.cars, dt);
+    }
+}
+
+/// Inputs of the own car the host didn't acknowledge yet.
+///
+/// The client drives its car with its input right away instead of waiting for the host to send it
+/// back, which would lag behind by the round trip. Whenever a snapshot arrives, the car is put back
+/// to the state of the host and the inputs the host didn't simulate yet are replayed on top. The
+/// car stays where it is as long as the prediction was right, and catches up with the host if not.
+#[derive(Debug, Clone, Default)]
+pub struct Prediction {
+    pending: VecDeque<(u32, InputFrame)>,
+}
+
+impl Prediction {
+    pub fn new() -> Prediction {
+        Prediction::default()
+    }
+
+    /// Keep the `input` of step `sequence`, it was just simulated locally.
+    pub fn push(&mut self, sequence: u32, input: InputFrame) {
+        self.pending.push_back((sequence, input));
+        if self.pending.len() > MAX_PREDICTED_INPUTS {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Put the car of `player` back to the `state` of the host that includes the input `ack` and
+    /// replay the newer inputs from there.
+    pub fn reconcile(&mut self, simulation: &mut Simulation, player: usize, state: &CarState, ack: u32) {
+        while self.pending.front().filter(|(sequence, _)| *sequence <= ack).is_some() {
+            self.pending.pop_front();
+        }
+        if player >= simulation.cars.len() {
+            return;
+        }
+        simulation.cars[player].set_state(state);
+        for (_, input) in &self.pending {
+            simulation.step_car(player, FIXED_TIMESTEP, Some(input.to_controller()));
+        }
+    }
+}
+
+/// Moves the cars of a client smoothly toward the last snapshot of the host.
+///
+/// Snapshots arrive less regularly than the frames are drawn, jumping from one to the next would
+/// make the cars stutter.
+#[derive(Debug, Clone)]
+pub struct Interpolation {
+    /// States of the cars in the last snapshot.
+    pub target: Vec<CarState>,
+    /// Rate [1/s] to close the gap to the `target` with, higher rates follow it more closely.
+    pub rate: f32,
+    /// Car the client predicts itself, it doesn't follow the `target`.
+    pub local: Option<usize>,
+}
+
+impl Interpolation {
+    pub fn new() -> Interpolation {
+        Interpolation {
+            target: Vec::new(),
+            rate: INTERPOLATION_RATE,
+            local: None,
+        }
+    }
+
+    /// Move the `cars` toward the `target` as much as `dt` [s] allows.
+    pub fn update(&self, cars: &mut [Car], dt: f32) {
+        let factor = 1. - (-self.rate * dt).exp();
+        for (id, (car, target)) in cars.iter_mut().zip(&self.target).enumerate() {
+            if self.local == Some(id) {
+                continue;
+            }
+            let state = car.state();
+            if (target.position - state.position).norm() > SNAP_DISTANCE {
+                car.set_state(target);
+            } else {
+                car.set_state(&interpolate(&state, target, factor));
+            }
+        }
+    }
+}
+
+/// Return the state a fraction `t` [0.0; 1.0] of the way from `from` to `to`.
+///
+/// Angles take the shorter way around, e.g. from 170° to -170° through 180°.
+pub fn interpolate(from: &CarState, to: &CarState, t: f32) -> CarState {
+    let angle = |from: f32, to: f32| {
+        let delta = (to - from + PI).rem_euclid(2. * PI) - PI;
+        from + delta * t
+    };
+    CarState {
+        position: from.position.lerp(&to.position, t),
+        rotation: from.rotation.zip_map(&to.rotation, angle),
+        velocity: from.velocity.lerp(&to.velocity, t),
+        mass: to.mass,
+        boost: from.boost + (to.boost - from.boost) * t,
+        damage: from.damage + (to.damage - from.damage) * t,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    fn test_car() -> Car {
+        Car::test_car(Vector3::new(0., 0., 0.5), 1000.0)
+    }
+
+    fn state(x: f32, yaw: f32) -> CarState {
+        CarState {
+            position: Vector3::new(x, 0., 0.5),
+            rotation: Vector3::new(0., 0., yaw),
+            velocity: Vector3::new(0., x, 0.),
+            mass: 1000.,
+            boost: 2.,
+            damage: 0.,
+        }
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let input = Packet::Input {
+            player: CLIENT_PLAYER,
+            sequence: 42,
+            input: InputFrame {
+                x: -0.5,
+                y: 1.,
+                boost: true,
+                handbrake: false,
+            },
+        };
+        let snapshot = Packet::Snapshot {
+            tick: 7,
+            cars: vec![state(1., 0.), state(-3., 2.)],
+            acks: vec![0, 41],
+        };
+        for packet in &[input, snapshot] {
+            let bytes = packet.to_bytes().unwrap();
+            assert!(bytes.len() <= MAX_PACKET_SIZE);
+            assert_eq!(&Packet::from_bytes(&bytes).unwrap(), packet);
+        }
+        assert!(Packet::from_bytes(b"{\"Snapshot\":").is_err());
+    }
+
+    #[test]
+    fn interpolate_halfway() {
+        let halfway = interpolate(&state(0., 0.), &state(2., 1.), 0.5);
+        assert_eq!(halfway.position.x, 1.);
+        assert_eq!(halfway.velocity.y, 1.);
+        assert!((halfway.rotation.z - 0.5).abs() < 1e-6);
+
+        // Around the back instead of through the front.
+        let back = interpolate(&state(0., 0.9 * PI), &state(0., -0.9 * PI), 0.5);
+        assert!((back.rotation.z.abs() - PI).abs() < 1e-4);
+    }
+
+    #[test]
+    fn follow_snapshot() {
+        let mut cars = vec![test_car()];
+        let mut interpolation = Interpolation::new();
+        interpolation.target = vec![state(1., 0.)];
+        interpolation.update(&mut cars, 0.05);
+        let x = cars[0].position.x;
+        assert!(x > 0. && x < 1.);
+        for _ in 0..100 {
+            interpolation.update(&mut cars, 0.05);
+        }
+        assert!((cars[0].position.x - 1.).abs() < 1e-3);
+
+        // Far off, the car jumps right to the snapshot.
+        interpolation.target = vec![state(20., 0.)];
+        interpolation.update(&mut cars, 0.01);
+        assert_eq!(cars[0].position.x, 20.);
+    }
+
+    #[test]
+    fn replay_inputs() {
+        let inputs = (0..90)
+            .map(|i| InputFrame {
+                x: if i < 40 { 0.6 } else { -0.4 },
+                y: 1.,
+                boost: i % 30 == 0,
+                handbrake: false,
+            })
+            .collect::<Vec<_>>();
+
+        // The client predicts every input right away.
+        let mut client = Simulation::new(vec![test_car()], None);
+        let mut prediction = Prediction::new();
+        for (sequence, input) in inputs.iter().enumerate() {
+            prediction.push(sequence as u32 + 1, *input);
+            client.step(FIXED_TIMESTEP, &[input.to_controller()]);
+        }
+        let predicted = client.cars[0].position;
+
+        // The host only got the first 50 inputs so far.
+        let mut server = Simulation::new(vec![test_car()], None);
+        for input in &inputs[..50] {
+            server.step(FIXED_TIMESTEP, &[input.to_controller()]);
+        }
+        client.cars[0].position = Vector3::new(100., 100., 0.);
+        prediction.reconcile(&mut client, 0, &server.cars[0].state(), 50);
+        assert_eq!(prediction.pending.len(), 40);
+        assert!((client.cars[0].position - predicted).norm() < 1e-4);
+    }
+
+    #[test]
+    fn follow_restarted_host() {
+        let mut client = Client::connect("127.0.0.1:7878", CLIENT_PLAYER).unwrap();
+        let cars = vec![state(0., 0.), state(5., 0.)];
+        client.take_snapshot(100, cars.clone(), vec![0, 12]);
+        assert_eq!(client.snapshot, Some((cars[1], 12)));
+
+        // Snapshots that arrive out of order are dropped.
+        client.snapshot = None;
+        client.take_snapshot(99, vec![state(1., 0.); 2], vec![0, 11]);
+        assert_eq!((client.tick, client.snapshot), (100, None));
+        assert_eq!(client.interpolation.target, cars);
+
+        // Until the host went quiet and starts over.
+        client.idle = HOST_TIMEOUT + 1;
+        client.take_snapshot(1, vec![state(1., 0.); 2], vec![0, 0]);
+        assert_eq!((client.tick, client.idle), (1, 0));
+        assert_eq!(client.snapshot, Some((state(1., 0.), 0)));
+    }
+
+    #[test]
+    fn rejoin_after_timeout() {
+        let input = InputFrame {
+            x: 0.,
+            y: 1.,
+            boost: false,
+            handbrake: false,
+        };
+        let first: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let second: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let mut remote = Remote::new(first, input);
+        for sequence in 1..=10 {
+            remote.receive(first, sequence, input);
+        }
+
+        // Late inputs are dropped while the player is connected.
+        remote.receive(second, 3, input);
+        assert_eq!((remote.address, remote.sequence, remote.inputs.len()), (first, 10, 10));
+
+        // After the timeout, a restarted client takes the place of the player.
+        remote.idle = REMOTE_TIMEOUT + 1;
+        remote.receive(second, 1, input);
+        assert_eq!((remote.address, remote.sequence, remote.inputs.len()), (second, 1, 1));
+        assert_eq!(remote.idle, 0);
+    }
+}
@@ -0,0 +1,146 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Carambolage.  If not, see <http://www.gnu.org/licenses/>.
+
+use nalgebra::clamp;
+
+/// Time [s] within which an accumulated remainder counts as a full step.
+const TOLERANCE: f64 = 1e-6;
+
+/// Splits the frame time into steps of a fixed size.
+///
+/// The remainder of a frame is carried over to the next one, so the simulation runs
+/// with the same time steps regardless of the frame rate.
+/// # Example
+/// ```
+/// # fn main() {
+/// let mut timestep = FixedTimestep::new(1. / 120.);
+/// // GameLoop {
+///     for _ in 0..timestep.advance(delta_time) {
+///         // Simulate 1 / 120 seconds
+///     }
+/// // }
+/// # }
+/// ```
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+    /// Maximum number of steps per frame. The simulation slows down on longer frames
+    /// instead of taking ever longer to catch up.
+    pub max_steps: u32,
+    /// No steps are taken and no time accumulates while paused, so resuming continues
+    /// right where the simulation stopped.
+    pub is_paused: bool,
+    /// Speed of the simulation relative to the wall clock, e.g. 0.5 for slow motion.
+    /// A scale of 0.0 stops the simulation like a pause.
+    pub time_scale: f32,
+}
+
+impl FixedTimestep {
+    /// Create a new FixedTimestep with a `step` size in seconds.
+    pub fn new(step: f32) -> FixedTimestep {
+        FixedTimestep {
+            step: f64::from(step),
+            accumulator: 0.,
+            max_steps: 30,
+            is_paused: false,
+            time_scale: 1.,
+        }
+    }
+
+    /// Add the frame time `dt` scaled by `time_scale` and return the number of steps to simulate.
+    ///
+    /// Frame times adding up to a multiple of the step don't lose a step to rounding.
+    pub fn advance(&mut self, dt: f32) -> u32 {
+        if self.is_paused {
+            return 0;
+        }
+        self.accumulator += f64::from(dt) * f64::from(self.time_scale.max(0.));
+        let mut steps = 0;
+        while self.accumulator + TOLERANCE >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+            if steps == self.max_steps {
+                self.accumulator = 0.;
+                break;
+            }
+        }
+        steps
+    }
+
+    /// Return the time left over after the last step as a fraction of a step, between 0.0 and 1.0.
+    ///
+    /// Drawing the simulation this far between the last two steps hides the fixed time step.
+    pub fn alpha(&self) -> f32 {
+        clamp(self.accumulator / self.step, 0., 1.) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixedTimestep;
+
+    #[test]
+    fn carry_remainder() {
+        let mut timestep = FixedTimestep::new(0.01);
+        assert_eq!(timestep.advance(0.025), 2);
+        assert_eq!(timestep.advance(0.004), 0);
+        assert_eq!(timestep.advance(0.001), 1);
+        assert_eq!(timestep.advance(0.005), 0);
+    }
+
+    #[test]
+    fn alpha() {
+        let mut timestep = FixedTimestep::new(0.01);
+        assert_eq!(timestep.alpha(), 0.);
+        timestep.advance(0.025);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+        timestep.advance(0.005);
+        assert!(timestep.alpha() < 1e-4);
+    }
+
+    #[test]
+    fn limit_steps() {
+        let mut timestep = FixedTimestep::new(0.01);
+        timestep.max_steps = 5;
+        assert_eq!(timestep.advance(1.), 5);
+        // The time of the slow frame is dropped.
+        assert_eq!(timestep.advance(0.01), 1);
+    }
+
+    #[test]
+    fn pause() {
+        let mut timestep = FixedTimestep::new(0.01);
+        assert_eq!(timestep.advance(0.015), 1);
+        timestep.is_paused = true;
+        assert_eq!(timestep.advance(0.02), 0);
+        assert_eq!(timestep.advance(10.), 0);
+
+        // Only the remainder from before the pause is left.
+        timestep.is_paused = false;
+        assert_eq!(timestep.advance(0.005), 1);
+        assert_eq!(timestep.advance(0.004), 0);
+    }
+
+    #[test]
+    fn time_scale() {
+        let mut timestep = FixedTimestep::new(0.01);
+        timestep.time_scale = 0.5;
+        assert_eq!(timestep.advance(0.1), 5);
+        timestep.time_scale = 2.;
+        assert_eq!(timestep.advance(0.1), 20);
+        timestep.time_scale = 0.;
+        assert_eq!(timestep.advance(0.1), 0);
+    }
+}
@@ -0,0 +1,98 @@
+// This file is part of Carambolage.
+
+// Carambolage is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Carambolage is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Foobar.  If not, see <http://www.gnu.org/licenses/>.
+use std::collections::VecDeque;
+
+/// Measures the frame rate as a rolling average over the last frames, so the readout doesn't jitter.
+/// # Example
+/// ```
+/// # fn main() {
+/// let mut frame_counter = FrameCounter::new(60);
+/// // GameLoop {
+///     frame_counter.add(delta_time);
+///     println!("{:.0} FPS", frame_counter.fps());
+/// // }
+/// # }
+/// ```
+pub struct FrameCounter {
+    frame_times: VecDeque<f32>,
+    sum: f32,
+    capacity: usize,
+}
+
+impl FrameCounter {
+    /// Create a new FrameCounter averaging over the last `frames` frames.
+    pub fn new(frames: usize) -> FrameCounter {
+        let capacity = frames.max(1);
+        FrameCounter {
+            frame_times: VecDeque::with_capacity(capacity),
+            sum: 0.,
+            capacity,
+        }
+    }
+
+    /// Add the duration `dt` [s] of a frame, replacing the oldest one.
+    pub fn add(&mut self, dt: f32) {
+        if self.frame_times.len() == self.capacity {
+            if let Some(oldest) = self.frame_times.pop_front() {
+                self.sum -= oldest;
+            }
+        }
+        self.frame_times.push_back(dt);
+        self.sum += dt;
+    }
+
+    /// Return the average frame time [s], 0.0 before the first frame.
+    pub fn frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            0.
+        } else {
+            self.sum / self.frame_times.len() as f32
+        }
+    }
+
+    /// Return the average frames per second, 0.0 before the first frame.
+    pub fn fps(&self) -> f32 {
+        let frame_time = self.frame_time();
+        if frame_time > 0. {
+            1. / frame_time
+        } else {
+            0.
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameCounter;
+
+    #[test]
+    fn rolling_average() {
+        let mut counter = FrameCounter::new(4);
+        assert_eq!(counter.fps(), 0.);
+
+        counter.add(0.02);
+        assert!((counter.fps() - 50.).abs() < 1e-3);
+        for _ in 0..3 {
+            counter.add(0.01);
+        }
+        assert!((counter.frame_time() - 0.0125).abs() < 1e-6);
+        assert!((counter.fps() - 80.).abs() < 1e-2);
+
+        // The slow first frame drops out of the average.
+        counter.add(0.01);
+        assert!((counter.frame_time() - 0.01).abs() < 1e-6);
+        assert!((counter.fps() - 100.).abs() < 1e-2);
+    }
+}
@@ -12,8 +12,12 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Foobar.  If not, see <http://www.gnu.org/licenses/>.
+use std::time::Duration as StdDuration;
 use time::{Duration, PreciseTime};
 
+/// Time [ms] left at the end of a frame to spin instead of sleeping, because sleeping may take longer.
+const SPIN_TIME: i64 = 1;
+
 /// Limits the FPS to chosen value.
 ///
 /// This is just a utility, that's why you have to limit the frame rate
@@ -67,6 +71,13 @@ impl FrameLimiter {
         self.dt
     }
 
+    /// Return how long to sleep until shortly before the frame ends, `None` if the frame is almost over.
+    ///
+    /// The rest of the frame is left to spin with `stop()` to end it on time.
+    pub fn sleep_time(&self) -> Option<StdDuration> {
+        sleep_time(self.time_per_frame, self.time.to(PreciseTime::now()))
+    }
+
     /// Make this the very last function call of your game loop.
     pub fn stop(&mut self) -> bool {
         self.delta_time = self.time.to(PreciseTime::now());
@@ -74,3 +85,29 @@ impl FrameLimiter {
         self.delta_time < self.time_per_frame
     }
 }
+
+/// Return how long to sleep of a frame taking `time_per_frame` after `elapsed`, leaving `SPIN_TIME`.
+fn sleep_time(time_per_frame: Duration, elapsed: Duration) -> Option<StdDuration> {
+    (time_per_frame - elapsed - Duration::milliseconds(SPIN_TIME))
+        .to_std()
+        .ok()
+        .filter(|sleep| *sleep > StdDuration::from_millis(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_rest_of_frame() {
+        let frame = Duration::nanoseconds((1e9 / 60.) as i64);
+        let sleep = sleep_time(frame, Duration::milliseconds(5)).unwrap();
+        assert_eq!(sleep.as_micros(), 16_666 - 5_000 - 1_000);
+
+        // The last millisecond is spun, and late frames don't sleep at all.
+        assert_eq!(sleep_time(frame, Duration::microseconds(15_700)), None);
+        assert_eq!(sleep_time(frame, Duration::milliseconds(20)), None);
+        // Uncapped frames are always over.
+        assert_eq!(sleep_time(Duration::nanoseconds(1), Duration::zero()), None);
+    }
+}
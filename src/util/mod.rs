@@ -14,8 +14,12 @@
 // along with Foobar.  If not, see <http://www.gnu.org/licenses/>.
 use nalgebra::{clamp, Vector2, Vector3};
 
+mod fixedtimestep;
+mod framecounter;
 mod framelimiter;
 
+pub use self::fixedtimestep::FixedTimestep;
+pub use self::framecounter::FrameCounter;
 pub use self::framelimiter::FrameLimiter;
 
 /// Interpolate from a to b with a given factor.